@@ -0,0 +1,92 @@
+/*
+Records real responses from the open API as test fixtures, scrubbed of anything personal.
+
+    TICKTICK_ACCESS_TOKEN=... cargo run --example record_fixtures -- tests/fixtures
+
+writes the project list to projects/recorded.json and every project's data to
+project_data/recorded-<n>.json. Set TICKTICK_API_URL to record from somewhere other
+than TickTick, e.g. https://api.dida365.com
+
+Responses captured some other way can be scrubbed on their own with
+
+    cargo run --example record_fixtures -- --scrub < response.json
+
+Look over the output before committing it, the scrubber only knows about the fields it knows about
+*/
+
+#[path = "../tests/common/scrub.rs"]
+mod scrub;
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::blocking::Client;
+use serde_json::Value;
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use scrub::Scrubber;
+
+const DEFAULT_API_URL: &str = "https://api.ticktick.com";
+
+fn main() -> Result<()> {
+    let arg = env::args()
+        .nth(1)
+        .context("Usage: record_fixtures <fixture dir> | --scrub")?;
+
+    if arg == "--scrub" {
+        let mut input = String::new();
+        io::stdin().read_to_string(&mut input)?;
+        println!(
+            "{}",
+            scrubbed(serde_json::from_str(&input).context("Input is not JSON")?)?
+        );
+        return Ok(());
+    }
+
+    let token = env::var("TICKTICK_ACCESS_TOKEN").context("TICKTICK_ACCESS_TOKEN is not set")?;
+    let api_url = env::var("TICKTICK_API_URL").unwrap_or(DEFAULT_API_URL.to_string());
+    let api_url = api_url.trim_end_matches('/');
+    let client = Client::new();
+    let get = |path: &str| -> Result<Value> {
+        let response = client.get(format!("{}{}", api_url, path)).bearer_auth(&token).send()?;
+        if !response.status().is_success() {
+            return Err(anyhow!("{} returned {}", path, response.status()));
+        }
+        Ok(response.json()?)
+    };
+
+    let dir = Path::new(&arg);
+    let projects = get("/open/v1/project")?;
+    write(&dir.join("projects").join("recorded.json"), projects.clone())?;
+
+    let ids = projects
+        .as_array()
+        .context("Expected a list of projects")?
+        .iter()
+        .filter_map(|project| project["id"].as_str());
+    for (num, id) in ids.enumerate() {
+        let data = get(&format!("/open/v1/project/{}/data", id))?;
+        write(
+            &dir.join("project_data").join(format!("recorded-{}.json", num + 1)),
+            data,
+        )?;
+    }
+
+    Ok(())
+}
+
+// Every file is scrubbed on its own so it can be checked on its own
+fn scrubbed(mut value: Value) -> Result<String> {
+    Scrubber::default().scrub(&mut value);
+    Ok(serde_json::to_string_pretty(&value)?)
+}
+
+fn write(path: &Path, value: Value) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, scrubbed(value)? + "\n").with_context(|| format!("Unable to write {}", path.display()))?;
+    println!("Recorded {}", path.display());
+    Ok(())
+}
@@ -0,0 +1,72 @@
+// Arguments the commandline and the shell's own commands have in common
+
+use clap::{arg, value_parser, Arg, ArgAction, ArgMatches};
+
+use crate::helpers::{normalize_tag, Filters, TagFilter, TimeFrame};
+
+// Everything that decides which tasks get listed, shared by every command that lists tasks
+pub fn filter_args() -> Vec<Arg> {
+    vec![
+        arg!(--tag <TAG>)
+            .help("Only To-Do items with this tag, repeat for more")
+            .value_parser(value_parser!(String))
+            .action(ArgAction::Append)
+            .global(true),
+        arg!(--"any-tag")
+            .help("Match To-Do items with any of the --tag tags instead of all of them")
+            .global(true),
+        arg!(--"no-tag" <TAG>)
+            .help("Leave out To-Do items with this tag, repeat for more")
+            .value_parser(value_parser!(String))
+            .action(ArgAction::Append)
+            .global(true),
+        arg!(--recurring).help("Only To-Do items that repeat").global(true),
+        arg!(--column <NAME>)
+            .help("Only To-Do items in this kanban column")
+            .value_parser(value_parser!(String))
+            .global(true),
+        arg!(--"expand-recurring")
+            .help("Also list upcoming occurrences of repeating To-Do items, not for all since it has no end")
+            .global(true),
+        arg!(--"include-completed")
+            .help("Also list To-Do items completed in the timeframe, the last seven days for week and all")
+            .global(true),
+    ]
+}
+
+pub fn read_filters(matches: &ArgMatches) -> Filters {
+    let tags = |name: &str| {
+        matches
+            .get_many::<String>(name)
+            .map(|tags| tags.map(|tag| normalize_tag(tag)).collect())
+            .unwrap_or_default()
+    };
+
+    Filters {
+        tags: TagFilter {
+            wanted: tags("tag"),
+            any: matches.get_flag("any-tag"),
+            excluded: tags("no-tag"),
+        },
+        recurring: matches.get_flag("recurring"),
+        column: matches.get_one::<String>("column").cloned(),
+        expand_recurring: matches.get_flag("expand-recurring"),
+        include_completed: matches.get_flag("include-completed"),
+    }
+}
+
+pub fn timeframe_arg() -> Arg {
+    arg!([TIMEFRAME])
+        .help("Which To-Do items to choose from")
+        .value_parser(["today", "tomorrow", "week", "all"])
+        .default_value("all")
+}
+
+pub fn read_timeframe(matches: &ArgMatches) -> TimeFrame {
+    match matches.get_one::<String>("TIMEFRAME").map(|x| x.as_str()) {
+        Some("today") => TimeFrame::Today,
+        Some("tomorrow") => TimeFrame::Tomorrow,
+        Some("week") => TimeFrame::Week,
+        _ => TimeFrame::All,
+    }
+}
@@ -0,0 +1,337 @@
+//! OAuth helpers for getting an access token out of TickTick.
+//!
+//! The flow is the usual authorization code dance: send the user to [`authorize_url`], catch
+//! the redirect with a [`redirect::RedirectListener`] (or have them paste it), then trade the
+//! code for an [`AccessToken`] with [`exchange_code_for_token`].
+
+pub mod redirect;
+
+use anyhow::{anyhow, Context, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jiff::{Timestamp, ToSpan};
+use rand::Rng;
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt::Write;
+use url::Url;
+
+use crate::client::Host;
+use redirect::AuthRedirect;
+
+/// The scopes tok asks for, which is everything the open API has to offer.
+pub const SCOPE: &str = "tasks:write tasks:read";
+
+/// A token along with when it stops working.
+///
+/// Only the parts of TickTick's response tok needs are kept, and the expiry is stored as a
+/// point in time rather than how long the token had left when it was issued.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AccessToken {
+    pub access_token: String,
+    #[serde(with = "jiff::fmt::serde::timestamp::second::required")]
+    pub expires_on: Timestamp,
+    // Tokens saved before scopes were tracked won't have any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+}
+
+impl AccessToken {
+    pub fn is_expired(&self) -> bool {
+        Timestamp::now() > self.expires_on
+    }
+}
+
+/// The token endpoint's response, as far as tok cares about it.
+#[derive(Deserialize, Debug, Clone)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub expires_in: i64,
+    pub scope: Option<String>,
+    pub token_type: Option<String>,
+}
+
+impl TokenResponse {
+    pub fn into_access_token(self) -> Result<AccessToken> {
+        let expires_on = Timestamp::now().checked_add(self.expires_in.seconds())?;
+
+        Ok(AccessToken {
+            access_token: self.access_token,
+            expires_on,
+            // Not every response says what was granted, in which case it's whatever was asked for
+            scope: Some(self.scope.unwrap_or_else(|| SCOPE.to_string())),
+        })
+    }
+}
+
+/// The app registered in the TickTick developer center.
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    pub client_id: String,
+    pub client_secret: String,
+    pub redirect_url: String,
+}
+
+/// A random hex string to tie the redirect back to the request that caused it.
+pub fn generate_state_token() -> String {
+    let mut rng = rand::thread_rng();
+    (0..32).fold(String::new(), |mut output, _| {
+        let _ = write!(output, "{:02x}", rng.r#gen::<u8>());
+        output
+    })
+}
+
+/// A fresh PKCE code verifier, 64 characters out of the unreserved set.
+pub fn generate_code_verifier() -> String {
+    const UNRESERVED: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-._~";
+    let mut rng = rand::thread_rng();
+    (0..64)
+        .map(|_| UNRESERVED[rng.gen_range(0..UNRESERVED.len())] as char)
+        .collect()
+}
+
+/// The S256 challenge for a PKCE code verifier.
+pub fn code_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+/// The page to send the user to, with a PKCE challenge when a code verifier is given.
+pub fn authorize_url(host: &Host, credentials: &Credentials, state: &str, code_verifier: Option<&str>) -> Result<Url> {
+    let mut url = Url::parse(&format!("{}/authorize", host.auth_url())).context("Bad authorization url")?;
+    url.query_pairs_mut()
+        .append_pair("scope", SCOPE)
+        .append_pair("client_id", &credentials.client_id)
+        .append_pair("state", state)
+        .append_pair("redirect_uri", &credentials.redirect_url)
+        .append_pair("response_type", "code");
+
+    if let Some(verifier) = code_verifier {
+        url.query_pairs_mut()
+            .append_pair("code_challenge", &code_challenge(verifier))
+            .append_pair("code_challenge_method", "S256");
+    }
+    Ok(url)
+}
+
+/// Trades the code from the redirect for a token, after making sure the redirect belongs to `state`.
+pub fn exchange_code_for_token(
+    host: &Host,
+    credentials: &Credentials,
+    auth_redirect: &AuthRedirect,
+    state: &str,
+    code_verifier: Option<&str>,
+) -> Result<AccessToken> {
+    if auth_redirect.state != state {
+        return Err(anyhow!("State token does not match"));
+    }
+
+    let http_client = Client::new();
+    let mut form = HashMap::new();
+
+    form.insert("client_id", credentials.client_id.as_str());
+    form.insert("client_secret", credentials.client_secret.as_str());
+    form.insert("code", &auth_redirect.code);
+    form.insert("grant_type", "authorization_code");
+    form.insert("redirect_uri", credentials.redirect_url.as_str());
+    if let Some(verifier) = code_verifier {
+        form.insert("code_verifier", verifier);
+    }
+
+    let response = http_client
+        .post(format!("{}/token", host.auth_url()))
+        .form(&form)
+        .send()?;
+
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!(
+            "Request failed with status {}: {}",
+            response.status(),
+            response.text()?
+        ));
+    }
+
+    let body = response.text()?;
+    let token: TokenResponse =
+        serde_json::from_str(&body).map_err(|e| anyhow!("Access token not found in api response: {}", e))?;
+    token.into_access_token()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::thread;
+    use tiny_http::{Header, Response, Server};
+
+    // A token endpoint that answers once with the given status and body, and hands back the form it received
+    fn token_endpoint(status: u16, body: &'static str) -> (Host, mpsc::Receiver<HashMap<String, String>>) {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", server.server_addr().to_ip().unwrap());
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let mut request = server.recv().unwrap();
+            assert_eq!(request.url(), "/oauth/token");
+            let mut form = String::new();
+            request.as_reader().read_to_string(&mut form).unwrap();
+            let content_type = Header::from_bytes("Content-Type", "application/json").unwrap();
+            let response = Response::from_string(body)
+                .with_status_code(status)
+                .with_header(content_type);
+            request.respond(response).unwrap();
+            let _ = tx.send(url::form_urlencoded::parse(form.as_bytes()).into_owned().collect());
+        });
+
+        let host = Host::Custom {
+            auth_url: format!("{base}/oauth"),
+            api_url: base,
+        };
+        (host, rx)
+    }
+
+    fn credentials(client_id: &str, client_secret: &str) -> Credentials {
+        Credentials {
+            client_id: client_id.to_string(),
+            client_secret: client_secret.to_string(),
+            redirect_url: "http://127.0.0.1:8000/".to_string(),
+        }
+    }
+
+    fn redirect(code: &str, state: &str) -> AuthRedirect {
+        AuthRedirect {
+            code: code.to_string(),
+            state: state.to_string(),
+        }
+    }
+
+    #[test]
+    fn exchanges_code_for_token() {
+        let (host, rx) = token_endpoint(
+            200,
+            r#"{"access_token":"tok-123","expires_in":3600,"scope":"tasks:read","token_type":"bearer"}"#,
+        );
+
+        let token = exchange_code_for_token(
+            &host,
+            &credentials("client id", "s3cr&t"),
+            &redirect("the code", "state"),
+            "state",
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(token.access_token, "tok-123");
+        assert_eq!(token.scope.as_deref(), Some("tasks:read"));
+        assert!(!token.is_expired());
+
+        let form = rx.recv().unwrap();
+        assert_eq!(form["client_id"], "client id");
+        assert_eq!(form["client_secret"], "s3cr&t");
+        assert_eq!(form["code"], "the code");
+        assert_eq!(form["grant_type"], "authorization_code");
+        assert_eq!(form["redirect_uri"], "http://127.0.0.1:8000/");
+        assert!(!form.contains_key("code_verifier"));
+    }
+
+    #[test]
+    fn sends_code_verifier_with_pkce() {
+        let (host, rx) = token_endpoint(200, r#"{"access_token":"tok-123","expires_in":3600}"#);
+
+        let token = exchange_code_for_token(
+            &host,
+            &credentials("id", "secret"),
+            &redirect("code", "state"),
+            "state",
+            Some("verifier"),
+        )
+        .unwrap();
+
+        // Falls back to the requested scope when the response doesn't have one
+        assert_eq!(token.scope.as_deref(), Some(SCOPE));
+        assert_eq!(rx.recv().unwrap()["code_verifier"], "verifier");
+    }
+
+    #[test]
+    fn rejects_mismatched_state() {
+        let host = Host::Custom {
+            auth_url: "http://127.0.0.1:1/oauth".to_string(),
+            api_url: "http://127.0.0.1:1".to_string(),
+        };
+        let err = exchange_code_for_token(
+            &host,
+            &credentials("id", "secret"),
+            &redirect("code", "forged"),
+            "state",
+            None,
+        )
+        .unwrap_err();
+
+        assert!(err.to_string().contains("State token does not match"));
+    }
+
+    #[test]
+    fn surfaces_token_endpoint_errors() {
+        let (host, _rx) = token_endpoint(400, r#"{"error":"invalid_grant"}"#);
+
+        let err = exchange_code_for_token(
+            &host,
+            &credentials("id", "secret"),
+            &redirect("code", "state"),
+            "state",
+            None,
+        )
+        .unwrap_err()
+        .to_string();
+
+        assert!(err.contains("400"));
+        assert!(err.contains("invalid_grant"));
+    }
+
+    #[test]
+    fn rejects_incomplete_token_responses() {
+        let (host, _rx) = token_endpoint(200, r#"{"expires_in":3600}"#);
+
+        let err = exchange_code_for_token(
+            &host,
+            &credentials("id", "secret"),
+            &redirect("code", "state"),
+            "state",
+            None,
+        )
+        .unwrap_err();
+
+        assert!(err.to_string().contains("Access token not found"));
+    }
+
+    #[test]
+    fn encodes_authorize_url() {
+        let url = authorize_url(&Host::TickTick, &credentials("id&x=1", "secret"), "state", None).unwrap();
+
+        assert_eq!(
+            url.as_str(),
+            "https://ticktick.com/oauth/authorize?scope=tasks%3Awrite+tasks%3Aread&client_id=id%26x%3D1\
+             &state=state&redirect_uri=http%3A%2F%2F127.0.0.1%3A8000%2F&response_type=code"
+        );
+    }
+
+    #[test]
+    fn adds_pkce_challenge_to_authorize_url() {
+        let verifier = "tok-pkce-test-verifier-0123456789-abcdefghijklmnop";
+        let url = authorize_url(&Host::TickTick, &credentials("id", "secret"), "state", Some(verifier)).unwrap();
+
+        let params: HashMap<String, String> = url.query_pairs().into_owned().collect();
+        assert_eq!(params["code_challenge"], "HVrMq12H-BB6c2Ls5ZtInB-1MtvOdb2ZEW9bTsDONJo");
+        assert_eq!(params["code_challenge_method"], "S256");
+    }
+
+    #[test]
+    fn generates_valid_code_verifiers() {
+        let verifier = generate_code_verifier();
+        assert_eq!(verifier.len(), 64);
+        assert!(verifier
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || "-._~".contains(ch)));
+    }
+}
@@ -0,0 +1,320 @@
+use anyhow::{anyhow, Context, Result};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tiny_http::{Header, Request, Response, Server};
+use url::{form_urlencoded, Url};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthRedirect {
+    pub code: String,
+    pub state: String,
+}
+
+/*
+A oneshot server for the OAuth redirect.
+Browsers love to poke at favicons and whatnot, so anything that isn't the
+redirect path, or doesn't look like an OAuth response, is turned away and
+the listener keeps waiting until the deadline
+*/
+pub struct RedirectListener {
+    server: Server,
+    path: String,
+}
+
+impl RedirectListener {
+    pub fn bind(redirect_url: &str) -> Result<Self> {
+        let url = Url::parse(redirect_url).context("Bad redirect_url format")?;
+        let host = url.host_str().context("Redirect url has no host")?;
+        let port = url.port_or_known_default().context("Redirect url has no port")?;
+
+        let server = Server::http((host, port))
+            .map_err(|e| anyhow!("{}", e))
+            .context("Failed to start server")?;
+
+        Ok(Self {
+            server,
+            path: url.path().to_string(),
+        })
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    pub fn wait(&self, timeout: Duration) -> Result<AuthRedirect> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let request = match self
+                .server
+                .recv_timeout(remaining)
+                .context("Failed to receive the redirect")?
+            {
+                Some(request) => request,
+                None => {
+                    return Err(anyhow!(
+                        "Timed out after {} seconds waiting for the redirect",
+                        timeout.as_secs()
+                    ))
+                }
+            };
+
+            if let Some(result) = self.handle(request) {
+                return result;
+            }
+        }
+    }
+
+    // Waits on a separate thread and hands the result over the channel
+    pub fn spawn(self, timeout: Duration, tx: mpsc::Sender<Result<AuthRedirect>>) {
+        thread::spawn(move || {
+            let _ = tx.send(self.wait(timeout));
+        });
+    }
+
+    // None means the request had nothing to do with the redirect and was turned away
+    fn handle(&self, request: Request) -> Option<Result<AuthRedirect>> {
+        let (path, query) = match request.url().split_once('?') {
+            Some((path, query)) => (path, query),
+            None => (request.url(), ""),
+        };
+
+        if path != self.path {
+            let _ = request.respond(Response::from_string("Not Found").with_status_code(404));
+            return None;
+        }
+
+        let params = parse_query(query);
+        if !params.contains_key("code") && !params.contains_key("error") {
+            let _ = request.respond(Response::from_string("Not an OAuth redirect").with_status_code(400));
+            return None;
+        }
+
+        let result = redirect_from_params(&params);
+        let page = match &result {
+            Ok(_) => html_page("Logged in to tok", "This window can be closed now.", true),
+            Err(err) => html_page("Unable to log in to tok", &err.to_string(), false),
+        };
+        let status = if result.is_ok() { 200 } else { 400 };
+        let content_type =
+            Header::from_bytes("Content-Type", "text/html; charset=utf-8").expect("Content-Type header is valid");
+        let _ = request.respond(
+            Response::from_string(page)
+                .with_status_code(status)
+                .with_header(content_type),
+        );
+
+        Some(result)
+    }
+}
+
+/*
+Accepts whatever the user copied out of their browser.
+A bare code doesn't come with a state, the user copying it by hand is as good as a match
+*/
+pub fn parse_pasted_redirect(input: &str, state: &str) -> Result<AuthRedirect> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(anyhow!("Nothing was pasted"));
+    }
+
+    if !input.contains('?') && !input.contains('=') {
+        return Ok(AuthRedirect {
+            code: input.to_string(),
+            state: state.to_string(),
+        });
+    }
+
+    let query = input.split_once('?').map_or(input, |(_, query)| query);
+    // Anything after a fragment marker is not part of the query
+    let query = query.split('#').next().unwrap_or("");
+    redirect_from_params(&parse_query(query))
+}
+
+fn parse_query(query: &str) -> HashMap<String, String> {
+    form_urlencoded::parse(query.as_bytes()).into_owned().collect()
+}
+
+fn redirect_from_params(params: &HashMap<String, String>) -> Result<AuthRedirect> {
+    if let Some(error) = params.get("error") {
+        return Err(match params.get("error_description") {
+            Some(description) => anyhow!("TickTick denied the login: {} ({})", description, error),
+            None => anyhow!("TickTick denied the login: {}", error),
+        });
+    }
+
+    let code = params
+        .get("code")
+        .ok_or(anyhow!("No code in the redirect"))?
+        .to_string();
+    let state = params
+        .get("state")
+        .ok_or(anyhow!("No state in the redirect"))?
+        .to_string();
+
+    Ok(AuthRedirect { code, state })
+}
+
+fn html_page(title: &str, message: &str, success: bool) -> String {
+    let accent = if success { "#a6e3a1" } else { "#f38ba8" };
+    let symbol = if success { "✔" } else { "✘" };
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+  body {{ background: #1e1e2e; color: #cdd6f4; font-family: sans-serif; display: flex; align-items: center; justify-content: center; height: 100vh; margin: 0; }}
+  main {{ text-align: center; border: 2px solid {accent}; border-radius: 12px; padding: 2rem 3rem; }}
+  h1 {{ color: {accent}; }}
+</style>
+</head>
+<body>
+<main>
+<h1>{symbol} {title}</h1>
+<p>{message}</p>
+</main>
+</body>
+</html>
+"#,
+        title = escape_html(title),
+        message = escape_html(message),
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    text.chars().fold(String::with_capacity(text.len()), |mut escaped, ch| {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(ch),
+        }
+        escaped
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::blocking::Client;
+
+    fn listen(path: &str) -> (RedirectListener, String) {
+        let listener = RedirectListener::bind(&format!("http://127.0.0.1:0{}", path)).unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        (listener, base)
+    }
+
+    fn wait_in_background(listener: RedirectListener, timeout: Duration) -> mpsc::Receiver<Result<AuthRedirect>> {
+        let (tx, rx) = mpsc::channel();
+        listener.spawn(timeout, tx);
+        rx
+    }
+
+    #[test]
+    fn ignores_unrelated_requests() {
+        let (listener, base) = listen("/");
+        let rx = wait_in_background(listener, Duration::from_secs(5));
+        let client = Client::new();
+
+        let favicon = client.get(format!("{base}/favicon.ico")).send().unwrap();
+        assert_eq!(favicon.status(), 404);
+        let empty = client.get(format!("{base}/")).send().unwrap();
+        assert_eq!(empty.status(), 400);
+
+        let response = client.get(format!("{base}/?code=abc&state=xyz")).send().unwrap();
+        assert_eq!(response.status(), 200);
+        assert!(response.text().unwrap().contains("Logged in to tok"));
+
+        let redirect = rx.recv().unwrap().unwrap();
+        assert_eq!(
+            redirect,
+            AuthRedirect {
+                code: "abc".to_string(),
+                state: "xyz".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn only_accepts_the_redirect_path() {
+        let (listener, base) = listen("/callback");
+        let rx = wait_in_background(listener, Duration::from_secs(5));
+        let client = Client::new();
+
+        let wrong = client.get(format!("{base}/?code=abc&state=xyz")).send().unwrap();
+        assert_eq!(wrong.status(), 404);
+        client
+            .get(format!("{base}/callback?code=def&state=xyz"))
+            .send()
+            .unwrap();
+
+        assert_eq!(rx.recv().unwrap().unwrap().code, "def");
+    }
+
+    #[test]
+    fn decodes_parameters() {
+        let (listener, base) = listen("/");
+        let rx = wait_in_background(listener, Duration::from_secs(5));
+
+        Client::new()
+            .get(format!("{base}/?code=a%2Fb%3Dc&state=x+y%20z"))
+            .send()
+            .unwrap();
+
+        let redirect = rx.recv().unwrap().unwrap();
+        assert_eq!(redirect.code, "a/b=c");
+        assert_eq!(redirect.state, "x y z");
+    }
+
+    #[test]
+    fn surfaces_oauth_errors() {
+        let (listener, base) = listen("/");
+        let rx = wait_in_background(listener, Duration::from_secs(5));
+
+        let response = Client::new()
+            .get(format!(
+                "{base}/?error=access_denied&error_description=User%20said%20%3Cno%3E&state=xyz"
+            ))
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 400);
+        let page = response.text().unwrap();
+        assert!(page.contains("User said &lt;no&gt;"));
+
+        let err = rx.recv().unwrap().unwrap_err().to_string();
+        assert!(err.contains("access_denied"));
+        assert!(err.contains("User said <no>"));
+    }
+
+    #[test]
+    fn times_out() {
+        let (listener, _) = listen("/");
+        let err = listener.wait(Duration::from_millis(200)).unwrap_err();
+        assert!(err.to_string().contains("Timed out"));
+    }
+
+    #[test]
+    fn parses_pasted_redirects() {
+        let full = parse_pasted_redirect("http://127.0.0.1:8000/?code=abc&state=xyz\n", "other").unwrap();
+        assert_eq!(full.code, "abc");
+        assert_eq!(full.state, "xyz");
+
+        let bare = parse_pasted_redirect("  abc  ", "expected").unwrap();
+        assert_eq!(bare.code, "abc");
+        assert_eq!(bare.state, "expected");
+
+        let query = parse_pasted_redirect("code=a%20b&state=xyz#frag", "xyz").unwrap();
+        assert_eq!(query.code, "a b");
+
+        assert!(parse_pasted_redirect("", "xyz").is_err());
+        assert!(parse_pasted_redirect("http://127.0.0.1:8000/?error=access_denied", "xyz").is_err());
+    }
+}
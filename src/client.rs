@@ -1,250 +1,206 @@
+//! A blocking client for the TickTick open API.
+
 use anyhow::{anyhow, Context, Result};
-use jiff::{Timestamp, ToSpan};
-use reqwest::blocking::Client;
+use jiff::Zoned;
+use reqwest::blocking::{Client, RequestBuilder};
 use reqwest::header::{self, HeaderMap, HeaderValue};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use std::collections::HashMap;
-
-use crate::data::{Project, ProjectData, Task};
-use crate::helpers::generate_state_token;
-
-use bincode::{deserialize_from, serialize_into};
-use platform_dirs::AppDirs;
-use std::fs::{create_dir, OpenOptions};
-use std::io::{BufReader, BufWriter};
-
-use std::env;
-use std::sync::mpsc;
-use std::thread;
-use tiny_http::{Response, Server};
-
-const BASE_AUTH_URL: &str = "https://ticktick.com/oauth";
-const BASE_API_URL: &str = "https://api.ticktick.com";
-const SCOPE: &str = "tasks:write tasks:read";
-
-/*
-We don't need/want all the info given by the API.
-Knowing when the token expires is also more useful
-than knowing how long until it expires
-*/
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct AccessToken {
-    pub access_token: String,
-    #[serde(with = "jiff::fmt::serde::timestamp::second::required")]
-    pub expires_on: Timestamp,
+use serde_json::json;
+use std::time::Duration;
+
+use crate::data::{format_dt, Project, ProjectData, Task};
+use crate::error::TickTickError;
+use crate::store::TokenStore;
+
+const TICKTICK_AUTH_URL: &str = "https://ticktick.com/oauth";
+const TICKTICK_API_URL: &str = "https://api.ticktick.com";
+const DIDA365_AUTH_URL: &str = "https://dida365.com/oauth";
+const DIDA365_API_URL: &str = "https://api.dida365.com";
+const USER_AGENT: &str = concat!("tok/", env!("CARGO_PKG_VERSION"));
+
+/// Which service to talk to, every request the client makes goes through this.
+///
+/// TickTick and Dida365 are the same service on different domains. `Custom` is for anything
+/// else, most likely a mock or a proxy.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Host {
+    /// ticktick.com, the default.
+    // Spelled the way --host takes it, older configs have the kebab-case name
+    #[default]
+    #[serde(rename = "ticktick", alias = "tick-tick")]
+    TickTick,
+    /// dida365.com, TickTick's name in China.
+    Dida365,
+    /// Anything else, e.g. a mock server or a proxy.
+    Custom {
+        /// Base url for the OAuth endpoints, without a trailing slash.
+        auth_url: String,
+        /// Base url for the open API, without a trailing slash.
+        api_url: String,
+    },
+}
+
+impl Host {
+    /// Base url for the OAuth endpoints, without a trailing slash.
+    pub fn auth_url(&self) -> &str {
+        match self {
+            Self::TickTick => TICKTICK_AUTH_URL,
+            Self::Dida365 => DIDA365_AUTH_URL,
+            Self::Custom { auth_url, .. } => auth_url.trim_end_matches('/'),
+        }
+    }
+
+    /// Base url for the open API, without a trailing slash.
+    pub fn api_url(&self) -> &str {
+        match self {
+            Self::TickTick => TICKTICK_API_URL,
+            Self::Dida365 => DIDA365_API_URL,
+            Self::Custom { api_url, .. } => api_url.trim_end_matches('/'),
+        }
+    }
+}
+
+/// Where the client gets its bearer token from.
+pub enum TokenSource {
+    /// A raw access token.
+    Token(String),
+    /// Whatever is in the store when the client is built, which has to exist and not be expired.
+    Store(Box<dyn TokenStore>),
 }
 
+/// A blocking TickTick client, build one with [`TickTickClient::builder`].
 #[derive(Debug, Clone)]
 pub struct TickTickClient {
     http_client: Client,
+    host: Host,
 }
 
-#[derive(Debug, Clone)]
-pub struct AuthRedirect {
-    code: String,
-    state: String,
+/// Configuration for a [`TickTickClient`].
+pub struct ClientBuilder {
+    host: Host,
+    token: Option<TokenSource>,
+    timeout: Option<Duration>,
+    user_agent: String,
 }
 
-/*
-Everything related to auth and managing the token
-*/
-impl TickTickClient {
-    pub fn new() -> Result<Self> {
-        let access_token = match Self::read_access_token() {
-            Ok(token) => token,
-            Err(_) => Self::get_access_token_from_user()?,
-        };
-
-        let mut headers = HeaderMap::new();
-        let mut auth_header = HeaderValue::from_str(format!("Bearer {}", access_token.access_token).as_str())
-            .context("Unable to build auth header")?;
-        auth_header.set_sensitive(true);
-        headers.insert(header::AUTHORIZATION, auth_header);
-        let http_client = Client::builder().default_headers(headers).build()?;
-
-        Ok(Self { http_client })
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        Self {
+            host: Host::default(),
+            token: None,
+            timeout: None,
+            user_agent: USER_AGENT.to_string(),
+        }
     }
+}
 
-    fn get_access_token_from_user() -> Result<AccessToken> {
-        /*
-        I really don't think we need to bother too much with the state token.
-        The server is quite literally meant for a oneshot and any user
-        is going to be rolling their own credentials, this is not going to be a
-        long running service
-        */
-
-        let state = generate_state_token();
-        let redirect_url = env::var("TICKTICK_REDIRECT_URL").context("Did not find tictick redirect url")?;
-        let address: &str = redirect_url
-            .split("//")
-            .nth(1)
-            .context("Bad redirect_url format")?
-            .trim_end_matches("/");
-        let client_id = env::var("TICKTICK_CLIENT_ID").context("Did not find ticktick client id")?;
-        let client_secret = env::var("TICKTICK_CLIENT_SECRET").context("Did not find ticktick client secret")?;
-
-        let auth_url = format!("{BASE_AUTH_URL}/authorize?scope={SCOPE}&client_id={client_id}&state={state}&redirect_uri={redirect_url}&response_type=code");
-
-        open::that(&auth_url)?;
-
-        let auth_redirect = Self::listen_for_redirect(address)?;
-        let access_token =
-            Self::exchange_code_for_token(&client_id, &client_secret, &auth_redirect, &state, &redirect_url)?;
-
-        let _ = Self::save_access_token(&access_token);
-
-        Ok(access_token)
+impl ClientBuilder {
+    /// The service to talk to, defaults to TickTick.
+    pub fn host(mut self, host: Host) -> Self {
+        self.host = host;
+        self
     }
 
-    pub fn save_access_token(token: &AccessToken) -> Result<()> {
-        let app_dirs = AppDirs::new(Some("tok"), true).context("Unable to get cache directory")?;
-        if !app_dirs.cache_dir.exists() {
-            create_dir(&app_dirs.cache_dir).context("Unable to create cache directory")?;
-        }
-
-        let mut file = BufWriter::new(
-            OpenOptions::new()
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .open(app_dirs.cache_dir.join("tok"))
-                .context("Unable to create cache file")?,
-        );
-
-        serialize_into(&mut file, token).context("Unable to save token to file")?;
-        Ok(())
+    /// Use a raw access token.
+    pub fn access_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(TokenSource::Token(token.into()));
+        self
     }
 
-    pub fn read_access_token() -> Result<AccessToken> {
-        let app_dirs = AppDirs::new(Some("tok"), true).context("Unable to get cache directory")?;
-        let mut file = BufReader::new(
-            OpenOptions::new()
-                .read(true)
-                .open(app_dirs.cache_dir.join("tok"))
-                .context("tok cache does not exist")?,
-        );
-
-        let token: AccessToken = deserialize_from(&mut file).context("Unable to read IP from file")?;
-
-        if Timestamp::now() > token.expires_on {
-            return Err(anyhow!("Token expired"));
-        }
-        Ok(token)
+    /// Use the token in a store.
+    pub fn token_store(mut self, store: Box<dyn TokenStore>) -> Self {
+        self.token = Some(TokenSource::Store(store));
+        self
     }
 
-    fn listen_for_redirect(address: &str) -> Result<AuthRedirect> {
-        let (tx, rx) = mpsc::channel();
-
-        let server = Server::http(address)
-            .map_err(|e| anyhow::anyhow!("{}", e))
-            .context("Failed to start server")?;
-
-        // Clone server for move into thread
-        let server = std::sync::Arc::new(server);
-        let server_clone = server.clone();
-
-        thread::spawn(move || {
-            if let Some(request) = server_clone.incoming_requests().next() {
-                let url = request.url().to_string();
-
-                let params: HashMap<String, String> =
-                    url.split('?')
-                        .nth(1)
-                        .unwrap_or("")
-                        .split('&')
-                        .fold(HashMap::new(), |mut dict, param| {
-                            let mut parts = param.split('=');
-
-                            if let (Some(key), Some(value)) = (parts.next(), parts.next()) {
-                                dict.insert(key.to_string(), value.to_string());
-                            };
-                            dict
-                        });
-
-                let response = Response::from_string("This window can be closed now").with_status_code(200);
-                let _ = request.respond(response);
+    /// Timeout for each request, there is none by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
 
-                let _ = tx.send(params);
-            }
-        });
-
-        let params = rx.recv().context("Failed to receive data from the redirect")?;
-        let code = params
-            .get("code")
-            .ok_or(anyhow!("No code in the redirect"))?
-            .to_string();
-        let state = params
-            .get("state")
-            .ok_or(anyhow!("No state in the redirect"))?
-            .to_string();
-
-        Ok(AuthRedirect { code, state })
+    /// Defaults to `tok/<version>`.
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
     }
 
-    fn exchange_code_for_token(
-        client_id: &str,
-        client_secret: &str,
-        auth_redirect: &AuthRedirect,
-        state: &str,
-        redirect_uri: &str,
-    ) -> Result<AccessToken> {
-        if auth_redirect.state != state {
-            return Err(anyhow!("State token does not match"));
+    /// Builds the blocking client.
+    ///
+    /// Fails when no token was given, or the store doesn't have one that's still valid.
+    pub fn build(mut self) -> Result<TickTickClient> {
+        let (headers, timeout) = self.default_headers()?;
+        let mut builder = Client::builder().default_headers(headers).user_agent(self.user_agent);
+        if let Some(timeout) = timeout {
+            builder = builder.timeout(timeout);
         }
 
-        let http_client = Client::new();
-        let mut form = HashMap::new();
+        Ok(TickTickClient {
+            http_client: builder.build()?,
+            host: self.host,
+        })
+    }
 
-        form.insert("client_id", client_id);
-        form.insert("client_secret", client_secret);
-        form.insert("code", &auth_redirect.code);
-        form.insert("grant_type", "authorization_code");
-        form.insert("redirect_uri", redirect_uri);
+    /// Same as [`build`](Self::build), but for the async client.
+    #[cfg(feature = "async")]
+    pub fn build_async(mut self) -> Result<crate::nonblocking::TickTickClient> {
+        let (headers, timeout) = self.default_headers()?;
+        let mut builder = reqwest::Client::builder()
+            .default_headers(headers)
+            .user_agent(self.user_agent);
+        if let Some(timeout) = timeout {
+            builder = builder.timeout(timeout);
+        }
 
-        let response = http_client.post(format!("{BASE_AUTH_URL}/token")).form(&form).send()?;
+        Ok(crate::nonblocking::TickTickClient::new(builder.build()?, self.host))
+    }
 
-        if !response.status().is_success() {
-            return Err(anyhow::anyhow!(
-                "Request failed with status {}: {}",
-                response.status(),
-                response.text()?
-            ));
-        }
+    // Takes the token out of the builder, both clients send the same headers on every request
+    fn default_headers(&mut self) -> Result<(HeaderMap, Option<Duration>)> {
+        let access_token = match self.token.take().context("No token source was given")? {
+            TokenSource::Token(token) => token,
+            TokenSource::Store(store) => {
+                let token = store.load()?.ok_or(anyhow!("No token found in the store"))?;
+                if token.is_expired() {
+                    return Err(anyhow!("Token expired"));
+                }
+                token.access_token
+            }
+        };
 
-        let result: Value = response.json()?;
-        let access_token = result["access_token"]
-            .as_str()
-            .ok_or(anyhow!("Access token not found in api response"))?
-            .to_string();
+        let mut headers = HeaderMap::new();
+        let mut auth_header = HeaderValue::from_str(format!("Bearer {}", access_token).as_str())
+            .context("Unable to build auth header")?;
+        auth_header.set_sensitive(true);
+        headers.insert(header::AUTHORIZATION, auth_header);
 
-        let expires_in = result["expires_in"]
-            .as_i64()
-            .ok_or(anyhow!("Token lifetime not found in api response"))?;
+        Ok((headers, self.timeout))
+    }
+}
 
-        let expires_on = Timestamp::now().checked_add(expires_in.seconds())?;
+impl TickTickClient {
+    /// Starts configuring a client, a token source is the only thing that has to be given.
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::default()
+    }
 
-        Ok(AccessToken {
-            access_token,
-            expires_on,
-        })
+    /// The service this client talks to.
+    pub fn host(&self) -> &Host {
+        &self.host
     }
 }
 
 // API requests
 impl TickTickClient {
-    pub fn get_projects(&self) -> Result<Vec<Project>> {
-        self.http_client
-            .get(format!("{BASE_API_URL}/open/v1/project"))
-            .send()
-            .map_err(|e| anyhow!("Failed to send request: {}", e))?
-            .error_for_status()
-            .map_err(|e| anyhow!("API error: {}", e))?
-            .json()
-            .map_err(|e| anyhow!("Failed to parse response: {}", e))
+    /// Every project the token can see, without their tasks.
+    pub fn get_projects(&self) -> Result<Vec<Project>, TickTickError> {
+        self.send(self.http_client.get(format!("{}/open/v1/project", self.host.api_url())))
     }
 
-    pub fn get_single_project_with_data(&self, project_name: &str) -> Result<Vec<ProjectData>> {
+    /// Tasks and columns for the projects named `project_name`, which is matched exactly.
+    pub fn get_single_project_with_data(&self, project_name: &str) -> Result<Vec<ProjectData>, TickTickError> {
         /*
         Only returning Vec to keep it consistent with the other endpoint
         Less mental overhead for a little bit of memory/time overhead
@@ -252,62 +208,103 @@ impl TickTickClient {
         let projects = self.get_projects()?;
 
         projects
-            .iter().filter(|proj| proj.name == project_name)
-            .map(|project| {
-                self.http_client
-                    .get(format!("{BASE_API_URL}/open/v1/project/{}/data", project.id))
-                    .send()
-                    .map_err(|e| anyhow!("Failed to fetch project data request for {}: {}", project.id, e))?
-                    .error_for_status()
-                    .map_err(|e| anyhow!("API error fetching project data for {}: {}", project.id, e))?
-                    .json()
-                    .map_err(|e| anyhow!("Failed to parse project data for {}: {}", project.id, e))
-            })
+            .iter()
+            .filter(|proj| proj.name == project_name)
+            .map(|project| self.get_project_data(&project.id))
             .collect()
     }
 
-    pub fn get_projects_with_data(&self) -> Result<Vec<ProjectData>> {
+    /// Tasks and columns for every project, which takes one request per project.
+    pub fn get_projects_with_data(&self) -> Result<Vec<ProjectData>, TickTickError> {
         let projects = self.get_projects()?;
 
         projects
             .iter()
-            .map(|project| {
-                self.http_client
-                    .get(format!("{BASE_API_URL}/open/v1/project/{}/data", project.id))
-                    .send()
-                    .map_err(|e| anyhow!("Failed to fetch project data request for {}: {}", project.id, e))?
-                    .error_for_status()
-                    .map_err(|e| anyhow!("API error fetching project data for {}: {}", project.id, e))?
-                    .json()
-                    .map_err(|e| anyhow!("Failed to parse project data for {}: {}", project.id, e))
-            })
+            .map(|project| self.get_project_data(&project.id))
             .collect()
     }
-    pub fn complete_task(&self, task: &Task) -> Result<()> {
-        self.http_client
-            .post(format!(
-                "{BASE_API_URL}/open/v1/project/{}/task/{}/complete",
-                task.project_id, task.id
-            ))
-            .send()
-            .map_err(|e| anyhow!("Failed to send complete task request: {}", e))?
-            .error_for_status()
-            .map_err(|e| anyhow!("Failed to complete task: {}", e))?;
 
-        Ok(())
+    /// Tasks and columns for a single project.
+    pub fn get_project_data(&self, project_id: &str) -> Result<ProjectData, TickTickError> {
+        self.send(
+            self.http_client
+                .get(format!("{}/open/v1/project/{}/data", self.host.api_url(), project_id)),
+        )
+    }
+
+    /// Tasks in these projects that were completed between `start` and `end`.
+    pub fn get_completed_tasks(
+        &self,
+        project_ids: &[String],
+        start: &Zoned,
+        end: &Zoned,
+    ) -> Result<Vec<Task>, TickTickError> {
+        self.send(
+            self.http_client
+                .post(format!("{}/open/v1/task/completed", self.host.api_url()))
+                .json(&json!({
+                    "projectIds": project_ids,
+                    "startDate": format_dt(start),
+                    "endDate": format_dt(end),
+                })),
+        )
     }
 
-    pub fn delete_task(&self, task: &Task) -> Result<()> {
-        self.http_client
-            .delete(format!(
-                "{BASE_API_URL}/open/v1/project/{}/task/{}",
-                task.project_id, task.id
-            ))
-            .send()
-            .map_err(|e| anyhow!("Failed to send delete task request: {}", e))?
-            .error_for_status()
-            .map_err(|e| anyhow!("Failed to delete task: {}", e))?;
+    /// Marks a task as done.
+    pub fn complete_task(&self, task: &Task) -> Result<(), TickTickError> {
+        self.send_without_response(self.http_client.post(format!(
+            "{}/open/v1/project/{}/task/{}/complete",
+            self.host.api_url(),
+            task.project_id,
+            task.id
+        )))
+    }
+
+    /// Deletes a task for good.
+    pub fn delete_task(&self, task: &Task) -> Result<(), TickTickError> {
+        self.send_without_response(self.http_client.delete(format!(
+            "{}/open/v1/project/{}/task/{}",
+            self.host.api_url(),
+            task.project_id,
+            task.id
+        )))
+    }
+
+    /// Creates a task in `task.project_id`, returning it with the id TickTick gave it.
+    pub fn create_task(&self, task: &Task) -> Result<Task, TickTickError> {
+        self.send(
+            self.http_client
+                .post(format!("{}/open/v1/task", self.host.api_url()))
+                .json(task),
+        )
+    }
 
+    /// Saves changes to a task, returning the task as TickTick has it now.
+    ///
+    /// The whole task is sent, so fields that weren't touched stay exactly as TickTick sent them.
+    pub fn update_task(&self, task: &Task) -> Result<Task, TickTickError> {
+        self.send(
+            self.http_client
+                .post(format!("{}/open/v1/task/{}", self.host.api_url(), task.id))
+                .json(task),
+        )
+    }
+
+    fn send<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T, TickTickError> {
+        let response = request.send()?;
+        if !response.status().is_success() {
+            return Err(TickTickError::from_response(response));
+        }
+
+        let body = response.text()?;
+        serde_json::from_str(&body).map_err(|err| TickTickError::decode(err, &body))
+    }
+
+    fn send_without_response(&self, request: RequestBuilder) -> Result<(), TickTickError> {
+        let response = request.send()?;
+        if !response.status().is_success() {
+            return Err(TickTickError::from_response(response));
+        }
         Ok(())
     }
 }
@@ -0,0 +1,225 @@
+use anyhow::{anyhow, Context, Result};
+use platform_dirs::AppDirs;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{BufReader, BufWriter, ErrorKind};
+use std::path::PathBuf;
+
+use tok::auth::Credentials;
+use tok::store::{create_private_dir, open_private, FileTokenStore, TokenStore};
+use tok::Host;
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+/*
+Everything a profile needs to talk to its own account.
+Credentials are optional so the default profile keeps working
+off of the TICKTICK_* environment variables like it always has
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Profile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redirect_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_store: Option<TokenStoreKind>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pkce: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<Host>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Config {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_profile: Option<String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum TokenStoreKind {
+    #[default]
+    File,
+    SecretService,
+}
+
+impl TokenStoreKind {
+    pub fn open(self, profile_name: &str, path: PathBuf) -> Result<Box<dyn TokenStore>> {
+        match self {
+            Self::File => Ok(Box::new(FileTokenStore::new(path))),
+            #[cfg(feature = "secret-service")]
+            Self::SecretService => Ok(Box::new(tok::store::SecretServiceTokenStore::new(profile_name)?)),
+            #[cfg(not(feature = "secret-service"))]
+            Self::SecretService => {
+                let _ = profile_name;
+                Err(anyhow!(
+                    "tok was built without secret service support, rebuild with --features secret-service"
+                ))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ActiveProfile {
+    pub name: String,
+    pub profile: Profile,
+}
+
+fn app_dirs() -> Result<AppDirs> {
+    AppDirs::new(Some("tok"), true).context("Unable to get app directories")
+}
+
+impl Config {
+    fn path() -> Result<PathBuf> {
+        Ok(app_dirs()?.config_dir.join("config.json"))
+    }
+
+    pub fn load() -> Result<Self> {
+        let path = Self::path()?;
+        let file = match OpenOptions::new().read(true).open(&path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err).context("Unable to open config file"),
+        };
+
+        serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("Unable to parse config file at {}", path.display()))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+        // Profiles can hold a client secret, so it's kept as private as the tokens are
+        if let Some(parent) = path.parent() {
+            create_private_dir(parent).context("Unable to create config directory")?;
+        }
+
+        let file = BufWriter::new(open_private(&path).context("Unable to create config file")?);
+
+        serde_json::to_writer_pretty(file, self).context("Unable to save config file")
+    }
+
+    // The profile to use when one isn't given on the commandline or through TOK_PROFILE
+    pub fn default_profile(&self) -> &str {
+        self.default_profile.as_deref().unwrap_or(DEFAULT_PROFILE)
+    }
+
+    pub fn resolve(&self, requested: Option<&str>) -> Result<ActiveProfile> {
+        let name = requested.unwrap_or(self.default_profile());
+
+        match self.profiles.get(name) {
+            Some(profile) => Ok(ActiveProfile {
+                name: name.to_string(),
+                profile: profile.clone(),
+            }),
+            // The default profile always exists, even if it was never added
+            None if name == DEFAULT_PROFILE => Ok(ActiveProfile {
+                name: name.to_string(),
+                profile: Profile::default(),
+            }),
+            None => Err(anyhow!("Profile '{}' does not exist", name)),
+        }
+    }
+
+    pub fn add_profile(&mut self, name: &str, profile: Profile) -> Result<()> {
+        if self.profiles.contains_key(name) {
+            return Err(anyhow!("Profile '{}' already exists", name));
+        }
+        self.profiles.insert(name.to_string(), profile);
+        Ok(())
+    }
+
+    pub fn remove_profile(&mut self, name: &str) -> Result<Profile> {
+        let profile = self
+            .profiles
+            .remove(name)
+            .ok_or(anyhow!("Profile '{}' does not exist", name))?;
+
+        if self.default_profile.as_deref() == Some(name) {
+            self.default_profile = None;
+        }
+        Ok(profile)
+    }
+
+    pub fn set_default_profile(&mut self, name: &str) -> Result<()> {
+        if name != DEFAULT_PROFILE && !self.profiles.contains_key(name) {
+            return Err(anyhow!("Profile '{}' does not exist", name));
+        }
+        self.default_profile = Some(name.to_string());
+        Ok(())
+    }
+}
+
+impl ActiveProfile {
+    // Values set on the profile win, anything missing falls back to the environment
+    pub fn credentials(&self) -> Result<Credentials> {
+        let client_id = match &self.profile.client_id {
+            Some(id) => id.clone(),
+            None => env::var("TICKTICK_CLIENT_ID").context("Did not find ticktick client id")?,
+        };
+        let client_secret = match &self.profile.client_secret {
+            Some(secret) => secret.clone(),
+            None => env::var("TICKTICK_CLIENT_SECRET").context("Did not find ticktick client secret")?,
+        };
+        let redirect_url = match &self.profile.redirect_url {
+            Some(url) => url.clone(),
+            None => env::var("TICKTICK_REDIRECT_URL").context("Did not find tictick redirect url")?,
+        };
+
+        Ok(Credentials {
+            client_id,
+            client_secret,
+            redirect_url,
+        })
+    }
+
+    pub fn host(&self) -> Host {
+        self.profile.host.clone().unwrap_or_default()
+    }
+
+    // Every profile gets its own cache directory for the token and any fetched data
+    pub fn cache_dir(&self) -> Result<PathBuf> {
+        Ok(app_dirs()?.cache_dir.join("profiles").join(&self.name))
+    }
+
+    // Tokens live in the data dir so cache cleaners don't log the user out
+    pub fn token_path(&self) -> Result<PathBuf> {
+        Ok(app_dirs()?
+            .data_dir
+            .join("profiles")
+            .join(&self.name)
+            .join("token.json"))
+    }
+
+    // What tok undo can put back, kept with the token so clearing the cache doesn't lose it
+    pub fn journal_path(&self) -> Result<PathBuf> {
+        Ok(app_dirs()?
+            .data_dir
+            .join("profiles")
+            .join(&self.name)
+            .join("journal.json"))
+    }
+
+    pub fn token_store(&self) -> Result<Box<dyn TokenStore>> {
+        self.profile
+            .token_store
+            .unwrap_or_default()
+            .open(&self.name, self.token_path()?)
+    }
+
+    pub fn clear_cache(&self) -> Result<()> {
+        let dir = self.cache_dir()?;
+        match fs::remove_dir_all(dir) {
+            Ok(_) => Ok(()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err).context("Unable to remove profile cache"),
+        }
+    }
+}
@@ -1,102 +1,319 @@
-use anyhow::Result;
-use jiff::{civil::DateTime, tz::TimeZone, Zoned};
-use serde::{Deserialize, Serialize};
+//! The models for what TickTick sends and takes back.
+//!
+//! Plenty of fields are optional because the API docs don't say which ones really are. Anything the
+//! models don't name ends up in `extra` and gets written back untouched, so sending a task back
+//! doesn't quietly wipe out fields tok has never heard of. What's written back is equivalent rather
+//! than identical though: missing and null are the same thing here so nulls aren't written, and
+//! dates always come back in UTC.
 
+use anyhow::{anyhow, Result};
+use jiff::{tz::TimeZone, Timestamp, Zoned};
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::{Map, Value};
+
+use crate::recurrence::Recurrence;
+use crate::reminder::Reminder;
+
+// What TickTick sends and expects back
+const DT_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.3f%z";
+
+/*
+TickTick isn't consistent about millis, and anything going through a proxy or
+written by hand might use Z or an offset with a colon instead of +0000
+*/
+const DT_FALLBACK_FORMATS: &[&str] = &["%Y-%m-%dT%H:%M:%S%.f%z", "%Y-%m-%dT%H:%M:%S%.f%:z"];
+
+fn parse_dt(strtime: &str) -> Result<Timestamp> {
+    // RFC 3339 takes care of Z
+    if let Ok(timestamp) = strtime.parse::<Timestamp>() {
+        return Ok(timestamp);
+    }
+
+    DT_FALLBACK_FORMATS
+        .iter()
+        .find_map(|format| Timestamp::strptime(format, strtime).ok())
+        .ok_or(anyhow!("Unrecognized date '{}'", strtime))
+}
+
+/*
+The system zone doesn't need an IANA name, a POSIX TZ string or a bare
+offset are just as good for showing a date in the user's local time
+*/
 fn deserialize_dt<'de, D>(deserializer: D) -> Result<Option<Zoned>, D::Error>
 where
     D: serde::Deserializer<'de>,
 {
     Option::<String>::deserialize(deserializer)?
         .map(|strtime| {
-            let dt = DateTime::strptime("%Y-%m-%dT%H:%M:%S%.3f%z", strtime).map_err(serde::de::Error::custom)?;
-            let zoned = dt.to_zoned(TimeZone::UTC).map_err(serde::de::Error::custom)?;
-            zoned
-                .intz(TimeZone::system().iana_name().expect("Couldn't get system tz name"))
-                .map_err(serde::de::Error::custom)
+            let timestamp = parse_dt(&strtime).map_err(serde::de::Error::custom)?;
+            Ok(timestamp.to_zoned(TimeZone::system()))
         })
         .transpose()
 }
 
-/*
-There are a bunch of fields wrapped in Option because I honestly don't know
-what fields are really optional. The API docs don't say anything, and I hate
-it here
-*/
+// Always written back in UTC, the same way TickTick sends it
+pub(crate) fn format_dt(zoned: &Zoned) -> String {
+    zoned.timestamp().strftime(DT_FORMAT).to_string()
+}
 
+fn serialize_dt<S>(dt: &Option<Zoned>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match dt {
+        Some(zoned) => serializer.serialize_some(&format_dt(zoned)),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// A TickTick project, also called a list.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Project {
+    /// Handed out by TickTick.
     pub id: String,
+    /// Shown in the sidebar.
     pub name: String,
+    /// Hex color like `#4772FA`.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub color: Option<String>,
+    /// Where it sits among the other projects, lower comes first.
     #[serde(rename = "sortOrder")]
     pub sort_order: i64,
+    /// Whether the project is archived.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub closed: Option<bool>,
-    #[serde(rename = "groupId")]
+    /// The folder the project is in.
+    #[serde(rename = "groupId", skip_serializing_if = "Option::is_none")]
     pub group_id: Option<String>,
-    #[serde(rename = "viewMode")]
+    /// `list`, `kanban` or `timeline`.
+    #[serde(rename = "viewMode", skip_serializing_if = "Option::is_none")]
     pub view_mode: Option<String>,
+    /// What the token may do with a shared project, `read`, `comment` or `write`.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub permission: Option<String>,
+    /// `TASK` or `NOTE`.
     pub kind: String,
+    /// Every field the model doesn't name, written back untouched.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
+/// One entry in a task's checklist.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ChecklistItem {
+    /// Handed out by TickTick.
     pub id: String,
+    /// What the item says.
     pub title: String,
+    /// 0 while it's open, 1 once it's checked off.
     pub status: i32,
-    #[serde(rename = "completedTime", deserialize_with = "deserialize_dt", default)]
+    /// When it was checked off.
+    #[serde(rename = "completedTime", default, skip_serializing_if = "Option::is_none")]
+    #[serde(deserialize_with = "deserialize_dt", serialize_with = "serialize_dt")]
     pub completed_time: Option<Zoned>,
+    /// Whether `start_date` is a whole day rather than a time.
     #[serde(rename = "isAllDay")]
     pub is_all_day: bool,
+    /// Where it sits in the checklist, lower comes first.
     #[serde(rename = "sortOrder")]
     pub sort_order: i64,
-    #[serde(rename = "startDate", deserialize_with = "deserialize_dt", default)]
+    /// When it's scheduled for, if it is.
+    #[serde(rename = "startDate", default, skip_serializing_if = "Option::is_none")]
+    #[serde(deserialize_with = "deserialize_dt", serialize_with = "serialize_dt")]
     pub start_date: Option<Zoned>,
+    /// IANA name of the zone the dates were set in.
     #[serde(rename = "timeZone")]
     pub time_zone: String,
+    /// Every field the model doesn't name, written back untouched.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// A To-Do item.
+///
+/// Dates are read in the system's time zone and written back in UTC.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Task {
+    /// Left empty for a task that hasn't been created yet, TickTick hands one out.
+    #[serde(skip_serializing_if = "String::is_empty")]
     pub id: String,
+    /// The project the task is in, which every task has.
     #[serde(rename = "projectId")]
     pub project_id: String,
+    /// What needs doing.
     pub title: String,
+    /// All day tasks are due at midnight at the start of the day.
     #[serde(rename = "isAllDay")]
     pub is_all_day: bool,
-    #[serde(rename = "completedTime", deserialize_with = "deserialize_dt", default)]
+    /// When it was completed, for completed tasks.
+    #[serde(rename = "completedTime", default, skip_serializing_if = "Option::is_none")]
+    #[serde(deserialize_with = "deserialize_dt", serialize_with = "serialize_dt")]
     pub completed_time: Option<Zoned>,
+    /// The notes, in markdown.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub content: Option<String>,
+    /// The description shown above a checklist.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub desc: Option<String>,
-    #[serde(rename = "dueDate", deserialize_with = "deserialize_dt", default)]
+    /// When it's due, if it is.
+    #[serde(rename = "dueDate", default, skip_serializing_if = "Option::is_none")]
+    #[serde(deserialize_with = "deserialize_dt", serialize_with = "serialize_dt")]
     pub due_date: Option<Zoned>,
+    /// The checklist, for `CHECKLIST` tasks.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub items: Option<Vec<ChecklistItem>>,
+    /// 0 for none, 1 for low, 3 for medium and 5 for high.
     pub priority: i32,
+    /// iCalendar triggers, see [`Task::parsed_reminders`].
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub reminders: Option<Vec<String>>,
-    #[serde(rename = "repeatFlag")]
+    /// An RRULE, see [`Task::recurrence`].
+    #[serde(rename = "repeatFlag", skip_serializing_if = "Option::is_none")]
     pub repeat_flag: Option<String>,
+    /// Where it sits in its project, lower comes first.
     #[serde(rename = "sortOrder")]
     pub sort_order: i64,
-    #[serde(rename = "startDate", deserialize_with = "deserialize_dt", default)]
+    /// When it starts, for tasks that span some time.
+    #[serde(rename = "startDate", default, skip_serializing_if = "Option::is_none")]
+    #[serde(deserialize_with = "deserialize_dt", serialize_with = "serialize_dt")]
     pub start_date: Option<Zoned>,
+    /// 0 while it's open, 2 once it's completed.
     pub status: u32,
+    /// IANA name of the zone the dates were set in.
     #[serde(rename = "timeZone")]
     pub time_zone: String,
+    /// Tag names without the #.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    /// `TEXT`, `NOTE` or `CHECKLIST`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    /// The task this one is a subtask of.
+    #[serde(rename = "parentId", skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<String>,
+    /// Only set in kanban projects, one of the project's [`Column`] ids.
+    #[serde(rename = "columnId", skip_serializing_if = "Option::is_none")]
+    pub column_id: Option<String>,
+    /// When the task was created.
+    #[serde(rename = "createdTime", default, skip_serializing_if = "Option::is_none")]
+    #[serde(deserialize_with = "deserialize_dt", serialize_with = "serialize_dt")]
+    pub created_time: Option<Zoned>,
+    /// When the task last changed.
+    #[serde(rename = "modifiedTime", default, skip_serializing_if = "Option::is_none")]
+    #[serde(deserialize_with = "deserialize_dt", serialize_with = "serialize_dt")]
+    pub modified_time: Option<Zoned>,
+    /// Changes whenever the task does.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    /// Every field the model doesn't name, written back untouched.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Task {
+    /// The task's repeat rule, if it has one.
+    ///
+    /// TickTick sends an empty `repeatFlag` for tasks that don't repeat, which counts as no rule.
+    pub fn recurrence(&self) -> Result<Option<Recurrence>> {
+        self.repeat_flag
+            .as_deref()
+            .filter(|flag| !flag.trim().is_empty())
+            .map(str::parse)
+            .transpose()
+    }
+
+    /// The task's reminders, offsets from its due date.
+    pub fn parsed_reminders(&self) -> Result<Vec<Reminder>> {
+        self.reminders.iter().flatten().map(|trigger| trigger.parse()).collect()
+    }
 }
 
+/// A section of a kanban project.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Column {
+    /// Handed out by TickTick.
     pub id: String,
+    /// The kanban project the column belongs to.
     #[serde(rename = "projectId")]
     pub project_id: String,
+    /// Shown above the column.
     pub name: String,
+    /// Where it sits on the board, lower comes first.
     #[serde(rename = "sortOrder")]
     pub sort_order: i64,
+    /// Every field the model doesn't name, written back untouched.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
+/// A project along with its open tasks and, for kanban projects, its columns.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ProjectData {
+    /// The project itself.
     pub project: Project,
+    /// Every open task in the project.
     pub tasks: Vec<Task>,
+    /// The project's columns, empty unless it's a kanban project.
     pub columns: Vec<Column>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task_due(due: &str) -> serde_json::Result<Task> {
+        serde_json::from_value(serde_json::json!({
+            "id": "t1",
+            "projectId": "p1",
+            "title": "Task",
+            "isAllDay": false,
+            "priority": 0,
+            "sortOrder": 0,
+            "status": 0,
+            "timeZone": "UTC",
+            "dueDate": due
+        }))
+    }
+
+    #[test]
+    fn parses_every_timestamp_shape() {
+        let expected: Timestamp = "2024-03-18T16:30:00Z".parse().unwrap();
+        for shape in [
+            "2024-03-18T16:30:00.000+0000",
+            "2024-03-18T16:30:00+0000",
+            "2024-03-18T16:30:00Z",
+            "2024-03-18T16:30:00.000Z",
+            "2024-03-18T16:30:00+00:00",
+            "2024-03-18T17:30:00.000+0100",
+            "2024-03-18T12:30:00-04:00",
+        ] {
+            assert_eq!(parse_dt(shape).unwrap(), expected, "{}", shape);
+        }
+    }
+
+    #[test]
+    fn rejects_garbage_dates_without_panicking() {
+        assert!(parse_dt("next tuesday").is_err());
+        let err = task_due("2024-03-18").unwrap_err();
+        assert!(err.to_string().contains("Unrecognized date"));
+    }
+
+    #[test]
+    fn treats_an_empty_repeat_flag_as_no_rule() {
+        let mut task = task_due("2024-03-18T16:30:00Z").unwrap();
+        for flag in ["", "  "] {
+            task.repeat_flag = Some(flag.to_string());
+            assert!(task.recurrence().unwrap().is_none(), "{:?}", flag);
+        }
+        task.repeat_flag = Some("RRULE:FREQ=DAILY".to_string());
+        assert!(task.recurrence().unwrap().is_some());
+    }
+
+    #[test]
+    fn writes_dates_back_in_ticktick_format() {
+        let task = task_due("2024-03-18T12:30:00-04:00").unwrap();
+        let written = serde_json::to_value(&task).unwrap();
+        assert_eq!(written["dueDate"], "2024-03-18T16:30:00.000+0000");
+        assert!(written.get("startDate").is_none());
+    }
+}
@@ -0,0 +1,79 @@
+//! Errors from talking to the TickTick API.
+
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+use std::time::Duration;
+use thiserror::Error;
+
+// Enough of a response body to tell what went wrong without flooding the terminal
+const SNIPPET_LENGTH: usize = 200;
+
+/// Everything that can go wrong with an API request.
+#[derive(Debug, Error)]
+pub enum TickTickError {
+    /// The token is missing, expired, or was revoked.
+    #[error("TickTick rejected the access token")]
+    Unauthorized,
+    /// The project or task doesn't exist, or isn't visible to the token.
+    #[error("TickTick could not find the requested item")]
+    NotFound,
+    /// Too many requests, `retry_after` is set when TickTick says how long to back off.
+    #[error("TickTick is rate limiting requests")]
+    RateLimited { retry_after: Option<Duration> },
+    /// Any other unsuccessful status, along with the start of whatever the server said.
+    #[error("TickTick returned {status}: {body}")]
+    Server { status: StatusCode, body: String },
+    /// The request never got a response.
+    #[error("Unable to reach TickTick: {0}")]
+    Network(#[from] reqwest::Error),
+    /// The response came back but didn't have the expected shape.
+    #[error("Unable to parse the response from TickTick: {message}")]
+    Decode { message: String, body: String },
+}
+
+impl TickTickError {
+    pub(crate) fn from_response(response: reqwest::blocking::Response) -> Self {
+        let status = response.status();
+        let headers = response.headers().clone();
+        Self::from_parts(status, &headers, response.text().unwrap_or_default())
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) async fn from_async_response(response: reqwest::Response) -> Self {
+        let status = response.status();
+        let headers = response.headers().clone();
+        Self::from_parts(status, &headers, response.text().await.unwrap_or_default())
+    }
+
+    fn from_parts(status: StatusCode, headers: &HeaderMap, body: String) -> Self {
+        let retry_after = headers
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .map(Duration::from_secs);
+
+        match status {
+            StatusCode::UNAUTHORIZED => Self::Unauthorized,
+            StatusCode::NOT_FOUND => Self::NotFound,
+            StatusCode::TOO_MANY_REQUESTS => Self::RateLimited { retry_after },
+            _ => Self::Server {
+                status,
+                body: snippet(&body),
+            },
+        }
+    }
+
+    pub(crate) fn decode(err: serde_json::Error, body: &str) -> Self {
+        Self::Decode {
+            message: err.to_string(),
+            body: snippet(body),
+        }
+    }
+}
+
+fn snippet(body: &str) -> String {
+    match body.char_indices().nth(SNIPPET_LENGTH) {
+        Some((end, _)) => format!("{}...", &body[..end]),
+        None => body.to_string(),
+    }
+}
@@ -1,19 +1,474 @@
 use anyhow::{anyhow, Context, Result};
-use jiff::{Span, Unit, Zoned};
-use rand::Rng;
-use std::fmt::Write as FmtWrite;
+use jiff::civil::Date;
+use jiff::tz::TimeZone;
+use jiff::{Span, ToSpan, Unit, Zoned};
 use std::io::{self, Write};
 
-use crate::data::{ProjectData, Task};
 use kolorz::HexKolorize;
+use tok::data::{Column, ProjectData, Task};
+use tok::reminder::Reminder;
+use tok::TickTickError;
 
+use crate::markdown;
+use crate::picker::{self, Candidate, Picker, PickerKind};
+
+#[derive(Clone)]
 pub struct TaggedTask<'a> {
     pub project_name: &'a str,
     pub color: Option<&'a str>,
     pub task: &'a Task,
+    // Set for an occurrence of a repeating task that TickTick doesn't have yet
+    pub projected: Option<Zoned>,
+}
+
+impl TaggedTask<'_> {
+    // Only real tasks that are still open can be chosen
+    pub fn selectable(&self) -> bool {
+        self.projected.is_none() && self.task.status == 0
+    }
+}
+
+// Narrows a listing down beyond the timeframe, everything matches by default
+#[derive(Default)]
+pub struct Filters {
+    pub tags: TagFilter,
+    // Only tasks with a repeat rule
+    pub recurring: bool,
+    // Only tasks in the kanban column with this name, compared without caring about case
+    pub column: Option<String>,
+    // Not a filter, but it changes what gets listed all the same
+    pub expand_recurring: bool,
+    // Completed tasks are left out unless this is set, even when they're in the project data
+    pub include_completed: bool,
+}
+
+impl Filters {
+    pub fn matches(&self, task: &Task, project: &ProjectData) -> bool {
+        (self.include_completed || task.status == 0)
+            && self.tags.matches(task)
+            && (!self.recurring || repeats(task))
+            && self.matches_column(task, project)
+    }
+
+    fn matches_column(&self, task: &Task, project: &ProjectData) -> bool {
+        let Some(name) = &self.column else {
+            return true;
+        };
+        project
+            .columns
+            .iter()
+            .filter(|column| column.name.to_lowercase() == name.to_lowercase())
+            .any(|column| task.column_id.as_ref() == Some(&column.id))
+    }
+}
+
+/*
+Tags are compared without caring about case, TickTick itself
+lowercases them but people don't always type them that way
+*/
+#[derive(Default)]
+pub struct TagFilter {
+    // Tasks need all of these, or any one of them when `any` is set
+    pub wanted: Vec<String>,
+    pub any: bool,
+    // Tasks with any of these are left out
+    pub excluded: Vec<String>,
 }
 
+impl TagFilter {
+    pub fn matches(&self, task: &Task) -> bool {
+        let has = |tag: &String| has_tag(task, tag);
+        let wanted = match (self.wanted.is_empty(), self.any) {
+            (true, _) => true,
+            (false, true) => self.wanted.iter().any(has),
+            (false, false) => self.wanted.iter().all(has),
+        };
+        wanted && !self.excluded.iter().any(has)
+    }
+}
+
+// Along with the tag being added or removed
 #[derive(Clone, Copy)]
+pub enum TagEdit<'a> {
+    Add(&'a str),
+    Remove(&'a str),
+}
+
+pub fn has_tag(task: &Task, tag: &str) -> bool {
+    let tag = tag.to_lowercase();
+    task.tags
+        .iter()
+        .flatten()
+        .any(|existing| existing.to_lowercase() == tag)
+}
+
+// Tags can be typed with or without the #
+pub fn normalize_tag(tag: &str) -> String {
+    tag.trim().trim_start_matches('#').to_string()
+}
+
+// Every tag in use and how many tasks have it, counted case insensitively and named the way they were first seen
+pub fn tag_counts(projects: &[ProjectData]) -> Vec<(String, usize)> {
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    let tags = projects
+        .iter()
+        .flat_map(|proj| &proj.tasks)
+        .flat_map(|task| task.tags.iter().flatten());
+    for tag in tags {
+        let existing = counts
+            .iter_mut()
+            .find(|(seen, _)| seen.to_lowercase() == tag.to_lowercase());
+        match existing {
+            Some((_, count)) => *count += 1,
+            None => counts.push((tag.clone(), 1)),
+        }
+    }
+    counts.sort_by(|(a_tag, a_count), (b_tag, b_count)| b_count.cmp(a_count).then(a_tag.cmp(b_tag)));
+    counts
+}
+
+// TickTick's tag colors aren't in the open API, so each tag gets its own color from a fixed set instead
+const TAG_COLORS: &[&str] = &[
+    "#F18181", "#F2B04B", "#E6EA49", "#6BCB77", "#4CA1FF", "#8F7CF2", "#E58AC8", "#5AC8C8",
+];
+
+pub fn tag_color(tag: &str) -> &'static str {
+    let hash = tag
+        .to_lowercase()
+        .bytes()
+        .fold(0usize, |hash, byte| hash.wrapping_mul(31).wrapping_add(byte as usize));
+    TAG_COLORS[hash % TAG_COLORS.len()]
+}
+
+pub fn tag_chip(tag: &str) -> String {
+    format!("#{}", tag).kolorize(tag_color(tag)).to_string()
+}
+
+fn tag_chips(task: &Task) -> String {
+    task.tags.iter().flatten().fold(String::new(), |mut chips, tag| {
+        chips.push(' ');
+        chips.push_str(&tag_chip(tag));
+        chips
+    })
+}
+
+// Rules tok can't follow still count, only an empty or missing one doesn't
+pub fn repeats(task: &Task) -> bool {
+    !matches!(task.recurrence(), Ok(None))
+}
+
+// Rules tok can't follow still get the marker, just without saying how often
+pub fn repeat_marker(task: &Task) -> String {
+    match task.recurrence() {
+        Ok(Some(rule)) => format!(" ↻ {}", rule),
+        Ok(None) => String::new(),
+        Err(_) => " ↻".to_string(),
+    }
+}
+
+// Each reminder along with when it actually goes off
+pub fn reminder_lines(task: &Task) -> Vec<String> {
+    let Some(due) = &task.due_date else {
+        return Vec::new();
+    };
+    task.reminders
+        .iter()
+        .flatten()
+        .map(|trigger| match trigger.parse::<Reminder>() {
+            Ok(reminder) => match reminder.at(due) {
+                Some(at) => format!(
+                    "{} ({})",
+                    reminder.describe(due, task.is_all_day),
+                    at.strftime("%m/%d %I:%M %p")
+                ),
+                None => reminder.describe(due, task.is_all_day),
+            },
+            Err(_) => trigger.clone(),
+        })
+        .collect()
+}
+
+// One line per reminder under the task
+pub fn print_reminders(task: &Task) {
+    for line in reminder_lines(task) {
+        println!("      ⏰ {}", line);
+    }
+}
+
+// tok::reminder has the same ones but keeps them to itself, they aren't part of the library
+const MINUTE: i64 = 60;
+const HOUR: i64 = 60 * MINUTE;
+const DAY: i64 = 24 * HOUR;
+
+fn plural(amount: i64, unit: &str) -> String {
+    match amount {
+        1 => format!("1 {}", unit),
+        amount => format!("{} {}s", amount, unit),
+    }
+}
+
+// Roughly how far off a date is, months and years don't need to be exact for this
+pub fn relative(when: &Zoned, now: &Zoned) -> String {
+    let secs = now.duration_until(when).as_secs();
+    let abs = secs.abs();
+    let amount = match abs {
+        0..MINUTE => return "just now".to_string(),
+        MINUTE..HOUR => plural(abs / MINUTE, "minute"),
+        HOUR..DAY => plural(abs / HOUR, "hour"),
+        _ if abs < 60 * DAY => plural(abs / DAY, "day"),
+        _ if abs < 730 * DAY => plural(abs / (30 * DAY), "month"),
+        _ => plural(abs / (365 * DAY), "year"),
+    };
+    match secs > 0 {
+        true => format!("in {}", amount),
+        false => format!("{} ago", amount),
+    }
+}
+
+// Both the date itself and how far off it is, all day tasks don't have a meaningful time
+pub fn describe_date(when: &Zoned, all_day: bool, now: &Zoned) -> String {
+    let format = match all_day {
+        true => "%a %m/%d/%Y",
+        false => "%a %m/%d/%Y %I:%M %p",
+    };
+    format!("{} ({})", when.strftime(format), relative(when, now))
+}
+
+fn print_field(label: &str, value: impl std::fmt::Display) {
+    println!("{:<11} {}", format!("{}:", label), value);
+}
+
+// Every field TickTick sends for a task, anything that isn't set is left out
+pub fn print_task_details(task: &Task, project: Option<&ProjectData>, now: &Zoned) {
+    println!("\x1b[1m{}\x1b[22m", task.title);
+    println!();
+
+    match project {
+        Some(data) => match &data.project.color {
+            Some(color) => print_field("Project", data.project.name.kolorize(color)),
+            None => print_field("Project", &data.project.name),
+        },
+        None => print_field("Project", &task.project_id),
+    }
+    let column = task.column_id.as_ref().map(|id| {
+        project
+            .and_then(|data| data.columns.iter().find(|column| &column.id == id))
+            .map_or(id.clone(), |column| column.name.clone())
+    });
+    if let Some(column) = column {
+        print_field("Column", column);
+    }
+
+    match task.status {
+        0 => print_field("Status", "Open"),
+        2 => print_field("Status", "Completed"),
+        status => print_field("Status", format!("Unknown ({})", status)),
+    }
+    match task.priority {
+        0 => print_field("Priority", "None"),
+        1 => print_field("Priority", "Low"),
+        3 => print_field("Priority", "Medium"),
+        5 => print_field("Priority", "High"),
+        priority => print_field("Priority", priority),
+    }
+    if let Some(kind) = &task.kind {
+        print_field("Kind", kind);
+    }
+    if task.tags.as_ref().is_some_and(|tags| !tags.is_empty()) {
+        print_field("Tags", tag_chips(task).trim_start());
+    }
+
+    if let Some(start) = &task.start_date {
+        print_field("Start", describe_date(start, task.is_all_day, now));
+    }
+    if let Some(due) = &task.due_date {
+        print_field("Due", describe_date(due, task.is_all_day, now));
+    }
+    if let Some(completed) = &task.completed_time {
+        print_field("Completed", describe_date(completed, false, now));
+    }
+    if task.is_all_day {
+        print_field("All day", "Yes");
+    }
+    match task.recurrence() {
+        Ok(Some(rule)) => print_field("Repeats", format!("↻ {}", rule)),
+        Ok(None) => {}
+        Err(_) => print_field(
+            "Repeats",
+            format!("↻ {}", task.repeat_flag.as_deref().unwrap_or_default()),
+        ),
+    }
+    for (num, line) in reminder_lines(task).into_iter().enumerate() {
+        match num {
+            0 => print_field("Reminders", line),
+            _ => println!("{:<11} {}", "", line),
+        }
+    }
+    print_field("Time zone", &task.time_zone);
+
+    if let Some(parent) = &task.parent_id {
+        let title = project
+            .and_then(|data| data.tasks.iter().find(|task| &task.id == parent))
+            .map(|task| task.title.as_str());
+        match title {
+            Some(title) => print_field("Parent", format!("{} ({})", title, parent)),
+            None => print_field("Parent", parent),
+        }
+    }
+    if let Some(created) = &task.created_time {
+        print_field("Created", describe_date(created, false, now));
+    }
+    if let Some(modified) = &task.modified_time {
+        print_field("Modified", describe_date(modified, false, now));
+    }
+    print_field("Id", &task.id);
+
+    if let Some(items) = task.items.as_ref().filter(|items| !items.is_empty()) {
+        println!();
+        println!("Checklist:");
+        for item in items {
+            let done = if item.status == 0 { "[ ]" } else { "[x]" };
+            match (&item.completed_time, &item.start_date) {
+                (Some(completed), _) => println!("  {} {} (done {})", done, item.title, relative(completed, now)),
+                (None, Some(start)) => println!(
+                    "  {} {} ({})",
+                    done,
+                    item.title,
+                    describe_date(start, item.is_all_day, now)
+                ),
+                (None, None) => println!("  {} {}", done, item.title),
+            }
+        }
+    }
+
+    if let Some(desc) = task.desc.as_deref().filter(|desc| !desc.trim().is_empty()) {
+        println!();
+        println!("{}", desc.trim_end());
+    }
+    if let Some(content) = task.content.as_deref().filter(|content| !content.trim().is_empty()) {
+        println!();
+        let width = terminal_size::terminal_size().map_or(DEFAULT_WIDTH, |(width, _)| width.0 as usize);
+        println!("{}", markdown::render(content, width));
+    }
+}
+
+// Without a terminal to measure, e.g. when piped, 80 columns is as good a guess as any
+pub const DEFAULT_WIDTH: usize = 80;
+
+// Narrower than this and the board is stacked instead of side by side
+const MIN_COLUMN_WIDTH: usize = 20;
+const COLUMN_GAP: &str = "  ";
+
+/*
+Each column with its tasks in the order TickTick shows them.
+Tasks without a column, or with one that's gone, end up in a column of their own at the end
+*/
+fn board_columns(data: &ProjectData) -> Vec<(String, Vec<&Task>)> {
+    let mut columns: Vec<&Column> = data.columns.iter().collect();
+    columns.sort_by_key(|column| column.sort_order);
+    let in_column = |id: Option<&String>| {
+        let mut tasks: Vec<&Task> = data.tasks.iter().filter(|task| task.column_id.as_ref() == id).collect();
+        tasks.sort_by_key(|task| task.sort_order);
+        tasks
+    };
+
+    let mut board: Vec<(String, Vec<&Task>)> = columns
+        .iter()
+        .map(|column| (column.name.clone(), in_column(Some(&column.id))))
+        .collect();
+    let mut loose: Vec<&Task> = data
+        .tasks
+        .iter()
+        .filter(|task| {
+            task.column_id
+                .as_ref()
+                .is_none_or(|id| !columns.iter().any(|column| &column.id == id))
+        })
+        .collect();
+    loose.sort_by_key(|task| task.sort_order);
+    if !loose.is_empty() {
+        board.push(("No column".to_string(), loose));
+    }
+    board
+}
+
+// Columns belong to a project and are matched without caring about case
+pub fn find_column<'a>(data: &'a ProjectData, name: &str) -> Result<&'a Column> {
+    if let Some(column) = data
+        .columns
+        .iter()
+        .find(|col| col.name.to_lowercase() == name.to_lowercase())
+    {
+        return Ok(column);
+    }
+    let names: Vec<&str> = data.columns.iter().map(|col| col.name.as_str()).collect();
+    match names.is_empty() {
+        true => Err(anyhow!("'{}' has no columns", data.project.name)),
+        false => Err(anyhow!(
+            "'{}' has no column named '{}', try one of: {}",
+            data.project.name,
+            name,
+            names.join(", ")
+        )),
+    }
+}
+
+// Pads or cuts text to exactly `width` characters
+fn fit(text: &str, width: usize) -> String {
+    match text.chars().count() {
+        len if len <= width => format!("{}{}", text, " ".repeat(width - len)),
+        _ => format!("{}…", text.chars().take(width.saturating_sub(1)).collect::<String>()),
+    }
+}
+
+pub fn print_board(data: &ProjectData, width: usize) {
+    let board = board_columns(data);
+    if board.is_empty() {
+        println!("'{}' has nothing on it", data.project.name);
+        return;
+    }
+
+    let gaps = COLUMN_GAP.len() * (board.len() - 1);
+    let column_width = width.saturating_sub(gaps) / board.len();
+    if column_width < MIN_COLUMN_WIDTH {
+        for (num, (name, tasks)) in board.iter().enumerate() {
+            if num > 0 {
+                println!();
+            }
+            println!("\x1b[1m{} ({})\x1b[22m", name, tasks.len());
+            for task in tasks {
+                println!("  • {}{}", task.title, tag_chips(task));
+            }
+        }
+        return;
+    }
+
+    let headers: Vec<String> = board
+        .iter()
+        .map(|(name, tasks)| {
+            format!(
+                "\x1b[1m{}\x1b[22m",
+                fit(&format!("{} ({})", name, tasks.len()), column_width)
+            )
+        })
+        .collect();
+    println!("{}", headers.join(COLUMN_GAP).trim_end());
+    println!("{}", vec!["─".repeat(column_width); board.len()].join(COLUMN_GAP));
+
+    let rows = board.iter().map(|(_, tasks)| tasks.len()).max().unwrap_or(0);
+    for row in 0..rows {
+        let cells: Vec<String> = board
+            .iter()
+            .map(|(_, tasks)| match tasks.get(row) {
+                Some(task) => fit(&format!("• {}", task.title), column_width),
+                None => " ".repeat(column_width),
+            })
+            .collect();
+        println!("{}", cells.join(COLUMN_GAP).trim_end());
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
 pub enum TimeFrame {
     Today,
     Tomorrow,
@@ -42,57 +497,262 @@ impl TimeFrame {
             Self::All => true,
         }
     }
+
+    // Whether `due` is before the timeframe is over, which is never for All since it doesn't end
+    pub fn before_end(&self, today: &Zoned, due: &Zoned) -> bool {
+        match self {
+            Self::Today => due.date() <= today.date(),
+            Self::Tomorrow => today.tomorrow().is_ok_and(|tomorrow| due.date() <= tomorrow.date()),
+            Self::Week => self.inside(today, due),
+            Self::All => false,
+        }
+    }
+}
+
+/*
+Whole days for `tok done`, from the start of the first one
+up to but not including the start of the day after the last
+*/
+pub struct Period {
+    pub start: Zoned,
+    pub end: Zoned,
+}
+
+impl Period {
+    // today, week for the last seven days, a single date or a range like 2024-03-01..2024-03-15
+    pub fn parse(text: &str, now: &Zoned) -> Result<Self> {
+        let (first, last) = match text.to_lowercase().as_str() {
+            "today" => (now.date(), now.date()),
+            "week" => (now.date().checked_sub(6.days())?, now.date()),
+            other => match other.split_once("..") {
+                Some((first, last)) => (period_date(first, text)?, period_date(last, text)?),
+                None => {
+                    let date = period_date(other, text)?;
+                    (date, date)
+                }
+            },
+        };
+        if first > last {
+            return Err(anyhow!("'{}' ends before it starts", text));
+        }
+        Self::days(first, last, now.time_zone())
+    }
+
+    /*
+    When the completed tasks listed for a timeframe were completed. Tomorrow's day has nothing
+    yet, and All has no start so it goes back as far as week does
+    */
+    pub fn for_frame(frame: TimeFrame, now: &Zoned) -> Result<Self> {
+        match frame {
+            TimeFrame::Today => Self::days(now.date(), now.date(), now.time_zone()),
+            TimeFrame::Tomorrow => {
+                let tomorrow = now.date().tomorrow()?;
+                Self::days(tomorrow, tomorrow, now.time_zone())
+            }
+            TimeFrame::Week | TimeFrame::All => Self::parse("week", now),
+        }
+    }
+
+    pub fn days(first: Date, last: Date, zone: &TimeZone) -> Result<Self> {
+        Ok(Self {
+            start: first.to_zoned(zone.clone())?,
+            end: last.tomorrow()?.to_zoned(zone.clone())?,
+        })
+    }
+
+    pub fn contains(&self, when: &Zoned) -> bool {
+        self.start <= *when && *when < self.end
+    }
+}
+
+fn period_date(date: &str, text: &str) -> Result<Date> {
+    date.trim().parse().map_err(|_| {
+        anyhow!(
+            "'{}' isn't a period, try today, week, 2024-03-18 or 2024-03-01..2024-03-15",
+            text
+        )
+    })
 }
 
 pub fn print_task(num: usize, tagged_task: &TaggedTask, now: &Zoned) {
-    let time = tagged_task.task.due_date.as_ref().unwrap_or(now);
+    let time = tagged_task
+        .projected
+        .as_ref()
+        .or(tagged_task.task.due_date.as_ref())
+        .unwrap_or(now);
+    let (label, projected) = match tagged_task.projected {
+        Some(_) => ("( ~ )".to_string(), " (projected)"),
+        None if tagged_task.task.status != 0 => ("( ✓ )".to_string(), ""),
+        None => (format!("({:03})", num + 1), ""),
+    };
+    let title = match tagged_task.task.status {
+        0 => tagged_task.task.title.clone(),
+        _ => format!("\x1b[9m{}\x1b[29m", tagged_task.task.title),
+    };
     match tagged_task.color {
         Some(col) => {
             println!(
-                "({:03}) {:<16} {} [{}]",
-                num + 1,
+                "{} {:<16} {} [{}]{}{}{}",
+                label,
                 time.strftime("[%m/%d %I:%M %p]").to_string(),
-                tagged_task.task.title,
-                tagged_task.project_name.kolorize(col)
+                title,
+                tagged_task.project_name.kolorize(col),
+                tag_chips(tagged_task.task),
+                repeat_marker(tagged_task.task),
+                projected
             );
         }
         None => {
             println!(
-                "({:03}) {:<16} {} [{}]",
-                num + 1,
+                "{} {:<16} {} [{}]{}{}{}",
+                label,
                 time.strftime("[%m/%d %I:%M %p]").to_string(),
-                tagged_task.task.title,
-                tagged_task.project_name
+                title,
+                tagged_task.project_name,
+                tag_chips(tagged_task.task),
+                repeat_marker(tagged_task.task),
+                projected
             );
         }
     }
 }
 
-pub fn filter(projects: &[ProjectData], frame: TimeFrame) -> Vec<TaggedTask<'_>> {
+// One line per completed task, e.g. "10/18 03:04 PM  Write report [Work]"
+pub fn print_completed(tagged_task: &TaggedTask) {
+    let when = match &tagged_task.task.completed_time {
+        Some(at) => at.strftime("%m/%d %I:%M %p").to_string(),
+        None => "Unknown time".to_string(),
+    };
+    let project = match tagged_task.color {
+        Some(col) => tagged_task.project_name.kolorize(col).to_string(),
+        None => tagged_task.project_name.to_string(),
+    };
+    println!(
+        "{:<14}  {} [{}]{}",
+        when,
+        tagged_task.task.title,
+        project,
+        tag_chips(tagged_task.task)
+    );
+}
+
+// Projected occurrences and completed tasks are listed but can't be picked, so only the rest get numbers
+pub fn print_tasks<'a>(tagged_tasks: &[TaggedTask<'a>], now: &Zoned) -> Vec<&'a Task> {
+    let mut selectable = Vec::new();
+    for tagged_task in tagged_tasks {
+        print_task(selectable.len(), tagged_task, now);
+        if tagged_task.selectable() {
+            selectable.push(tagged_task.task);
+        }
+    }
+    selectable
+}
+
+pub fn filter<'a>(projects: &'a [ProjectData], frame: TimeFrame, filters: &Filters) -> Vec<TaggedTask<'a>> {
     let today = Zoned::now();
+    let completed_in = Period::for_frame(frame, &today).ok();
 
     projects
         .iter()
         .flat_map(|proj| {
-            let value = today.clone();
-            proj.tasks.iter().filter_map({
-                move |task| {
-                    let should_include = match &task.due_date {
-                        Some(date) => frame.inside(&value, date),
-                        None => matches!(frame, TimeFrame::All),
+            proj.tasks
+                .iter()
+                .filter(|task| filters.matches(task, proj))
+                .flat_map(|task| {
+                    // Completed tasks go by when they were completed rather than when they were due
+                    let should_include = match (&task.due_date, &task.completed_time) {
+                        (_, Some(done)) if task.status != 0 => {
+                            completed_in.as_ref().is_some_and(|in_frame| in_frame.contains(done))
+                        }
+                        (Some(date), _) => frame.inside(&today, date),
+                        (None, _) => matches!(frame, TimeFrame::All),
+                    };
+                    // A completed repeating task already has its next occurrence in TickTick
+                    let projected = match filters.expand_recurring && task.status == 0 {
+                        true => projected_occurrences(task, frame, &today),
+                        false => Vec::new(),
                     };
 
-                    should_include.then(|| TaggedTask {
+                    let tagged = |projected| TaggedTask {
                         project_name: &proj.project.name,
                         color: proj.project.color.as_deref(),
                         task,
-                    })
-                }
-            })
+                        projected,
+                    };
+                    should_include
+                        .then(|| tagged(None))
+                        .into_iter()
+                        .chain(projected.into_iter().map(move |at| tagged(Some(at))))
+                        .collect::<Vec<_>>()
+                })
         })
         .collect()
 }
 
+/*
+Where a repeating task lands next inside the timeframe, after the due date it has right now.
+TickTick only ever has the one instance, so these are worked out from the repeat rule.
+An overdue task is already listed once, so days that have gone by aren't projected
+*/
+fn projected_occurrences(task: &Task, frame: TimeFrame, today: &Zoned) -> Vec<Zoned> {
+    let (Some(due), Ok(Some(rule))) = (&task.due_date, task.recurrence()) else {
+        return Vec::new();
+    };
+
+    rule.occurrences(due)
+        .skip_while(|next| next <= due || next.date() < today.date())
+        .take_while(|next| frame.before_end(today, next))
+        .filter(|next| frame.inside(today, next))
+        .collect()
+}
+
+// What the pickers show for a task, they only match on the title, project and tags
+fn candidate(tagged_task: &TaggedTask) -> Candidate {
+    let mut text = format!("{} [{}]", tagged_task.task.title, tagged_task.project_name);
+    for tag in tagged_task.task.tags.iter().flatten() {
+        text.push_str(&format!(" #{}", tag));
+    }
+    let note = match &tagged_task.task.due_date {
+        Some(due) => due.strftime("%m/%d %I:%M %p").to_string(),
+        None => String::new(),
+    };
+    Candidate { text, note }
+}
+
+/*
+Asks which of the tasks to go with, one or as many as the user likes. Projected occurrences
+are still listed by the numeric prompt, but none of the pickers offer them
+*/
+pub fn choose_tasks<'a>(
+    tagged_tasks: &[TaggedTask<'a>],
+    picker: &Picker,
+    multiple: bool,
+    now: &Zoned,
+) -> Result<Vec<&'a Task>> {
+    if let PickerKind::Number = picker.kind() {
+        let selectable = print_tasks(tagged_tasks, now);
+        if selectable.is_empty() {
+            return Ok(Vec::new());
+        }
+        let chosen = match multiple {
+            true => get_numbers(selectable.len())?,
+            false => vec![get_number(selectable.len())?],
+        };
+        return Ok(chosen.into_iter().map(|num| selectable[num]).collect());
+    }
+
+    let selectable: Vec<&TaggedTask> = tagged_tasks.iter().filter(|tagged| tagged.selectable()).collect();
+    if selectable.is_empty() {
+        return Ok(Vec::new());
+    }
+    let candidates: Vec<Candidate> = selectable.iter().map(|tagged| candidate(tagged)).collect();
+    let chosen = match picker.kind() {
+        PickerKind::External(program) => picker::external(program, &candidates, multiple)?,
+        _ => picker::fuzzy(&candidates, multiple)?,
+    };
+    Ok(chosen.into_iter().map(|num| selectable[num].task).collect())
+}
+
 pub fn get_number(max: usize) -> Result<usize> {
     /*
     I really am just asking for off-by-one errors here
@@ -116,10 +776,73 @@ pub fn get_number(max: usize) -> Result<usize> {
     }
 }
 
-pub fn generate_state_token() -> String {
-    let mut rng = rand::thread_rng();
-    (0..32).fold(String::new(), |mut output, _| {
-        let _ = write!(output, "{:02x}", rng.r#gen::<u8>());
-        output
-    })
+// Any number of tasks, separated by spaces or commas
+pub fn get_numbers(max: usize) -> Result<Vec<usize>> {
+    print!("Please enter task numbers (e.g. 1 3 4): ");
+    io::stdout()
+        .flush()
+        .context("Could not flush stdout while asking for user input")?;
+    let mut input = String::new();
+
+    io::stdin().read_line(&mut input).context("Could not get user input")?;
+
+    let mut nums = Vec::new();
+    for part in input
+        .split([' ', ',', '\t', '\n'])
+        .filter(|part| !part.trim().is_empty())
+    {
+        let num: i64 = part.trim().parse().context("Task number wasn't a number")?;
+        if num < 1 || num > max as i64 {
+            return Err(anyhow!("Invalid task number {}", num));
+        }
+        if !nums.contains(&((num - 1) as usize)) {
+            nums.push((num - 1) as usize);
+        }
+    }
+
+    if nums.is_empty() {
+        return Err(anyhow!("No task numbers given"));
+    }
+    Ok(nums)
+}
+
+/*
+Loosely following sysexits.h so scripts can tell
+a bad token apart from TickTick having a bad day
+*/
+pub fn report_error(err: &anyhow::Error) -> i32 {
+    match err.downcast_ref::<TickTickError>() {
+        Some(TickTickError::Unauthorized) => {
+            eprintln!("TickTick rejected the access token, try logging in again with `tok auth login`");
+            77
+        }
+        Some(TickTickError::NotFound) => {
+            eprintln!("TickTick could not find that, it may have been deleted or moved already");
+            66
+        }
+        Some(TickTickError::RateLimited { retry_after }) => {
+            match retry_after {
+                Some(wait) => eprintln!("TickTick is rate limiting tok, try again in {} seconds", wait.as_secs()),
+                None => eprintln!("TickTick is rate limiting tok, try again in a little while"),
+            }
+            75
+        }
+        Some(TickTickError::Server { status, body }) => {
+            eprintln!("TickTick returned an error ({}): {}", status, body);
+            69
+        }
+        Some(TickTickError::Network(err)) => {
+            eprintln!("Unable to reach TickTick: {}", err);
+            69
+        }
+        Some(TickTickError::Decode { message, body }) => {
+            eprintln!("Unable to make sense of TickTick's response: {}", message);
+            eprintln!("Response started with: {}", body);
+            65
+        }
+        None => {
+            eprintln!("{}", err);
+            1
+        }
+    }
 }
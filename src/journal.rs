@@ -0,0 +1,235 @@
+use anyhow::{Context, Result};
+use jiff::tz::TimeZone;
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::{self, OpenOptions};
+use std::io::{BufReader, BufWriter, ErrorKind};
+use std::path::PathBuf;
+
+use tok::data::{Project, ProjectData, Task};
+use tok::store::{create_private_dir, open_private};
+use tok::TickTickError;
+
+use crate::config::ActiveProfile;
+use crate::helpers::Period;
+use crate::session::Session;
+
+// Plenty to dig out a mistake without the file growing forever
+const KEEP: usize = 100;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Complete,
+    Delete,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Entry {
+    pub action: Action,
+    pub at: Timestamp,
+    // The project's name back then, only for showing in `tok history`
+    pub project: String,
+    // Exactly as it was right before it was completed or deleted
+    pub task: Task,
+}
+
+impl Entry {
+    // What `tok undo` says once it's done
+    pub fn undone(&self) -> String {
+        match self.action {
+            Action::Complete => format!("Reopened '{}' in {}", self.task.title, self.project),
+            Action::Delete => format!("Restored '{}' in {}", self.task.title, self.project),
+        }
+    }
+}
+
+/*
+Every To-Do item tok completed or deleted for a profile, oldest first, so `tok undo` can
+put them back. Only the most recent ones are kept
+*/
+pub struct Journal {
+    path: PathBuf,
+}
+
+impl Journal {
+    pub fn new(profile: &ActiveProfile) -> Result<Self> {
+        Ok(Self {
+            path: profile.journal_path()?,
+        })
+    }
+
+    pub fn entries(&self) -> Result<Vec<Entry>> {
+        let file = match OpenOptions::new().read(true).open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err).context("Unable to open the undo history"),
+        };
+
+        serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("Unable to read the undo history at {}", self.path.display()))
+    }
+
+    pub fn record(&self, action: Action, project: &str, task: &Task) -> Result<()> {
+        let mut entries = self.entries()?;
+        entries.push(Entry {
+            action,
+            at: Timestamp::now(),
+            project: project.to_string(),
+            task: task.clone(),
+        });
+        let extra = entries.len().saturating_sub(KEEP);
+        entries.drain(..extra);
+        self.save(&entries)
+    }
+
+    /*
+    Records a change that already went through under its project's name. Losing the undo
+    history shouldn't fail that change, so callers only mention the error
+    */
+    pub fn remember(&self, action: Action, projects: &[ProjectData], task: &Task) -> Result<()> {
+        let project = projects
+            .iter()
+            .find(|proj| proj.project.id == task.project_id)
+            .map_or("", |proj| proj.project.name.as_str());
+        self.record(action, project, task)
+    }
+
+    /*
+    Undoes up to `count` of the latest changes, newest first, and returns how many were. Saves
+    after each one so a failure part way through doesn't undo anything twice, and hands each
+    to `undone` right away since the ones before a failure still went through
+    */
+    pub fn undo_latest(&self, count: usize, session: &Session, mut undone: impl FnMut(&Entry, Task)) -> Result<usize> {
+        let mut entries = self.entries()?;
+        let mut done = 0;
+        while done < count {
+            let Some(entry) = entries.pop() else {
+                break;
+            };
+            let task = match undo(&entry, session) {
+                Ok(task) => task,
+                Err(err) => {
+                    eprint!("Unable to undo '{}': ", entry.task.title);
+                    return Err(err);
+                }
+            };
+            self.save(&entries)?;
+            undone(&entry, task);
+            done += 1;
+        }
+        Ok(done)
+    }
+
+    pub fn save(&self, entries: &[Entry]) -> Result<()> {
+        // Whole tasks end up in here, notes and all, so it's kept as private as the token
+        if let Some(parent) = self.path.parent() {
+            create_private_dir(parent).context("Unable to create data directory")?;
+        }
+
+        let file = BufWriter::new(open_private(&self.path).context("Unable to create the undo history")?);
+        serde_json::to_writer(file, entries).context("Unable to save the undo history")
+    }
+
+    pub fn clear(&self) -> Result<()> {
+        match fs::remove_file(&self.path) {
+            Ok(_) => Ok(()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err).context("Unable to remove the undo history"),
+        }
+    }
+}
+
+/*
+Reopens a completed task or creates a deleted one again with everything it had, returning
+it as TickTick has it now. A recreated task gets a new id, TickTick won't reuse the old one
+*/
+fn undo(entry: &Entry, session: &Session) -> Result<Task> {
+    let mut task = entry.task.clone();
+    match entry.action {
+        Action::Complete => {
+            task.status = 0;
+            task.completed_time = None;
+            // A missing completedTime leaves TickTick's as it is, only null clears it
+            task.extra.insert("completedTime".to_string(), Value::Null);
+            session.call(|client| client.update_task(&task))
+        }
+        Action::Delete => {
+            task.id.clear();
+            task.etag = None;
+            session.call(|client| client.create_task(&task))
+        }
+    }
+}
+
+/*
+Tasks in these projects completed during the period, oldest first. TickTick's list comes first,
+with the journal filling in completion times it leaves out. Servers without the endpoint only
+have what tok itself completed
+*/
+pub fn completed(projects: &[Project], period: &Period, session: &Session) -> Result<Vec<Task>> {
+    let project_ids: Vec<String> = projects.iter().map(|proj| proj.id.clone()).collect();
+    if project_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let logged: Vec<Task> = Journal::new(session.profile())?
+        .entries()?
+        .into_iter()
+        .filter(|entry| entry.action == Action::Complete && project_ids.contains(&entry.task.project_id))
+        .map(|entry| Task {
+            status: 2,
+            completed_time: Some(entry.at.to_zoned(TimeZone::system())),
+            ..entry.task
+        })
+        .collect();
+
+    let fetched = session.call(|client| client.get_completed_tasks(&project_ids, &period.start, &period.end));
+    let mut tasks = match fetched {
+        Ok(tasks) => tasks,
+        Err(err) if matches!(err.downcast_ref(), Some(TickTickError::NotFound)) => logged
+            .iter()
+            .filter(|task| task.completed_time.as_ref().is_some_and(|at| period.contains(at)))
+            .cloned()
+            .collect(),
+        Err(err) => return Err(err),
+    };
+    for task in tasks.iter_mut().filter(|task| task.completed_time.is_none()) {
+        task.completed_time = logged
+            .iter()
+            .rev()
+            .find(|done| done.id == task.id)
+            .and_then(|done| done.completed_time.clone());
+    }
+    tasks.sort_by(|a, b| a.completed_time.cmp(&b.completed_time));
+    Ok(tasks)
+}
+
+// TickTick leaves completed tasks out of the project data, so the ones from the period are added back in
+pub fn add_completed(projects: &mut [ProjectData], period: &Period, session: &Session) -> Result<()> {
+    let listed: Vec<Project> = projects.iter().map(|data| data.project.clone()).collect();
+    for task in completed(&listed, period, session)? {
+        let Some(data) = projects.iter_mut().find(|data| data.project.id == task.project_id) else {
+            continue;
+        };
+        if !data.tasks.iter().any(|existing| existing.id == task.id) {
+            data.tasks.push(task);
+        }
+    }
+    Ok(())
+}
+
+// One line per entry, e.g. "10/18 03:04 PM  Completed  Write report [Work]"
+pub fn print_entry(entry: &Entry) {
+    let what = match entry.action {
+        Action::Complete => "Completed",
+        Action::Delete => "Deleted",
+    };
+    println!(
+        "{}  {:<9}  {} [{}]",
+        entry.at.to_zoned(TimeZone::system()).strftime("%m/%d %I:%M %p"),
+        what,
+        entry.task.title,
+        entry.project
+    );
+}
@@ -0,0 +1,40 @@
+//! A client for the [TickTick](https://ticktick.com) open API, and the library behind the `tok` CLI.
+//!
+//! ```no_run
+//! use tok::{Host, TickTickClient};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let client = TickTickClient::builder()
+//!     .host(Host::TickTick)
+//!     .access_token("an access token")
+//!     .build()?;
+//!
+//! for project in client.get_projects_with_data()? {
+//!     for task in project.tasks {
+//!         println!("{} [{}]", task.title, project.project.name);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! With the `async` feature, [`nonblocking::TickTickClient`] makes the same requests without blocking
+//! the thread, for use inside a tokio runtime.
+//!
+//! Getting a token in the first place is up to [`auth`], and keeping it around between runs is up to [`store`].
+
+pub mod auth;
+pub mod client;
+pub mod data;
+pub mod error;
+#[cfg(feature = "async")]
+pub mod nonblocking;
+pub mod recurrence;
+pub mod reminder;
+pub mod store;
+
+pub use client::{ClientBuilder, Host, TickTickClient, TokenSource};
+pub use error::TickTickError;
+
+/// Shorthand for [`TickTickClient`].
+pub type Client = TickTickClient;
@@ -1,29 +1,60 @@
-mod client;
-mod data;
+mod args;
+mod config;
 mod helpers;
+mod journal;
+mod markdown;
+mod picker;
+mod session;
+mod shell;
+mod tui;
 
-use clap::{arg, command, value_parser, Command};
+use clap::{arg, command, value_parser, ArgMatches, Command};
+use jiff::tz::TimeZone;
 use jiff::Zoned;
+use std::io::{self, IsTerminal};
 use std::process::exit;
+use std::time::Duration;
 
-use crate::helpers::{filter, get_number, print_task, TimeFrame};
+use tok::data::{Project, ProjectData, Task};
+use tok::reminder::Reminder;
+use tok::Host;
 
-use self::client::TickTickClient;
+use crate::args::{filter_args, read_filters, read_timeframe, timeframe_arg};
+use crate::config::{ActiveProfile, Config, Profile, TokenStoreKind};
+use crate::helpers::{
+    choose_tasks, filter, find_column, has_tag, normalize_tag, print_board, print_completed, print_reminders,
+    print_task, print_task_details, print_tasks, repeat_marker, repeats, report_error, tag_chip, tag_counts, Filters,
+    Period, TagEdit, TaggedTask, TimeFrame, DEFAULT_WIDTH,
+};
+use crate::journal::{Action, Journal};
+use crate::picker::Picker;
+use crate::session::{LoginOptions, Session};
 
 fn main() {
-    let tick = match client::TickTickClient::new() {
-        Ok(client) => client,
-        Err(err) => {
-            eprintln!("{}", err);
-            exit(1)
-        }
-    };
-
     let now = Zoned::now();
 
     let matches = command!()
         .subcommand_required(true)
         .arg_required_else_help(true)
+        .arg(
+            arg!(--profile <NAME>)
+                .help("Profile to use, falls back to the default profile")
+                .value_parser(value_parser!(String))
+                .env("TOK_PROFILE")
+                .global(true),
+        )
+        .arg(
+            arg!(-v --verbose)
+                .help("Print extra information about what tok is doing")
+                .global(true),
+        )
+        .arg(
+            arg!(--picker <PICKER>)
+                .help("How to choose To-Do items, defaults to fuzzy in a terminal and number otherwise")
+                .value_parser(["fuzzy", "external", "number"])
+                .env("TOK_PICKER")
+                .global(true),
+        )
         .subcommand(
             Command::new("show")
                 .about("Show To-Do items accross projects")
@@ -38,6 +69,7 @@ fn main() {
                         .global(true)
                         .require_equals(false),
                 )
+                .args(filter_args())
                 .subcommand_required(true),
         )
         .subcommand(
@@ -54,6 +86,7 @@ fn main() {
                         .global(true)
                         .require_equals(false),
                 )
+                .args(filter_args())
                 .subcommand_required(true),
         )
         .subcommand(
@@ -70,70 +103,494 @@ fn main() {
                         .global(true)
                         .require_equals(false),
                 )
+                .args(filter_args())
+                .subcommand_required(true),
+        )
+        .subcommand(
+            Command::new("tag")
+                .about("Add or remove a tag on the chosen To-Do items")
+                .subcommand(
+                    Command::new("add")
+                        .about("Add a tag to the chosen To-Do items")
+                        .arg(arg!(<TAG>).help("Tag to add"))
+                        .arg(timeframe_arg()),
+                )
+                .subcommand(
+                    Command::new("remove")
+                        .about("Remove a tag from the chosen To-Do items")
+                        .arg(arg!(<TAG>).help("Tag to remove"))
+                        .arg(timeframe_arg()),
+                )
+                .arg(
+                    arg!(--project <NAME>)
+                        .help("Project name to filter by")
+                        .value_parser(value_parser!(String))
+                        .global(true)
+                        .require_equals(false),
+                )
+                .args(filter_args())
+                .subcommand_required(true),
+        )
+        .subcommand(
+            Command::new("tags").about("List every tag in use and how many To-Do items have it").arg(
+                arg!(--project <NAME>)
+                    .help("Project name to filter by")
+                    .value_parser(value_parser!(String))
+                    .require_equals(false),
+            ),
+        )
+        .subcommand(
+            Command::new("view")
+                .about("Show everything about a single To-Do item")
+                .arg(
+                    arg!([TASK])
+                        .help("Id, number from `tok show all` or part of the title, leave it out to pick from a list"),
+                )
+                .arg(
+                    arg!(--project <NAME>)
+                        .help("Project name to filter by")
+                        .value_parser(value_parser!(String))
+                        .require_equals(false),
+                )
+                .args(filter_args()),
+        )
+        .subcommand(
+            Command::new("board")
+                .about("Show a kanban project's columns side by side")
+                .arg(arg!(<PROJECT>).help("Name of the project"))
+                .arg(
+                    arg!(--width <COLUMNS>)
+                        .help("How wide to draw the board, defaults to the width of the terminal")
+                        .value_parser(value_parser!(usize)),
+                ),
+        )
+        .subcommand(
+            Command::new("move")
+                .about("Move a To-Do item to another column in its project")
+                .arg(
+                    arg!([TASK])
+                        .help("Id, number from `tok show all` or part of the title, leave it out to pick from a list"),
+                )
+                .arg(
+                    arg!(--column <NAME>)
+                        .help("Name of the column to move it to")
+                        .value_parser(value_parser!(String))
+                        .required(true),
+                )
+                .arg(
+                    arg!(--project <NAME>)
+                        .help("Project name to filter by")
+                        .value_parser(value_parser!(String))
+                        .require_equals(false),
+                ),
+        )
+        .subcommand(
+            Command::new("tui")
+                .about("Browse and triage To-Do items in a full-screen view")
+                .arg(timeframe_arg().help("Which To-Do items to start with"))
+                .arg(
+                    arg!(--project <NAME>)
+                        .help("Project name to filter by")
+                        .value_parser(value_parser!(String))
+                        .require_equals(false),
+                )
+                .arg(
+                    arg!(--refresh <SECONDS>)
+                        .help("How often to fetch everything again in the background, defaults to 60")
+                        .value_parser(value_parser!(u64)),
+                )
+                .args(filter_args()),
+        )
+        .subcommand(Command::new("shell").about("Run commands one after another, fetching everything only once"))
+        .subcommand(
+            Command::new("remind")
+                .about("List, add or remove reminders on the chosen To-Do items")
+                .subcommand(
+                    Command::new("list")
+                        .about("List To-Do items with reminders and when they go off")
+                        .arg(timeframe_arg()),
+                )
+                .subcommand(
+                    Command::new("add")
+                        .about("Add a reminder to the chosen To-Do items")
+                        .arg(arg!(<OFFSET>).help("When to be reminded, e.g. \"30m before\" or \"1d before at 9am\""))
+                        .arg(timeframe_arg()),
+                )
+                .subcommand(
+                    Command::new("remove")
+                        .about("Remove a reminder from the chosen To-Do items")
+                        .arg(arg!(<OFFSET>).help("The reminder to remove, written the same way it was added"))
+                        .arg(timeframe_arg()),
+                )
+                .arg(
+                    arg!(--project <NAME>)
+                        .help("Project name to filter by")
+                        .value_parser(value_parser!(String))
+                        .global(true)
+                        .require_equals(false),
+                )
+                .args(filter_args())
+                .subcommand_required(true),
+        )
+        .subcommand(
+            Command::new("recur")
+                .about("Show the next times a repeating To-Do item comes around")
+                .arg(arg!(<TASK>).help("Id or title of the To-Do item, part of the title is enough"))
+                .arg(
+                    arg!(-n --count <N>)
+                        .help("How many occurrences to show")
+                        .value_parser(value_parser!(usize))
+                        .default_value("5"),
+                )
+                .arg(
+                    arg!(--project <NAME>)
+                        .help("Project name to filter by")
+                        .value_parser(value_parser!(String))
+                        .require_equals(false),
+                ),
+        )
+        .subcommand(
+            Command::new("done")
+                .about("List the To-Do items completed today, this week or between two dates")
+                .arg(
+                    arg!([PERIOD])
+                        .help("today, week for the last seven days, a date or a range like 2024-03-01..2024-03-15")
+                        .default_value("today"),
+                )
+                .arg(
+                    arg!(--project <NAME>)
+                        .help("Project name to filter by")
+                        .value_parser(value_parser!(String))
+                        .require_equals(false),
+                ),
+        )
+        .subcommand(
+            Command::new("undo")
+                .about("Reopen or restore the To-Do items tok completed or deleted last")
+                .arg(
+                    arg!([COUNT])
+                        .help("How many of the latest changes to undo")
+                        .value_parser(value_parser!(usize))
+                        .default_value("1"),
+                ),
+        )
+        .subcommand(
+            Command::new("history")
+                .about("List the To-Do items tok completed or deleted lately, newest first")
+                .arg(
+                    arg!(-n --count <N>)
+                        .help("How many to list")
+                        .value_parser(value_parser!(usize))
+                        .default_value("10"),
+                ),
+        )
+        .subcommand(
+            Command::new("auth")
+                .about("Manage authentication with TickTick")
+                .subcommand(
+                    Command::new("login")
+                        .about("Log in to TickTick, replacing any stored token")
+                        .arg(arg!(--"no-browser").help(
+                            "Print the login url and accept the redirect url or code pasted into the terminal",
+                        ))
+                        .arg(
+                            arg!(--timeout <SECONDS>)
+                                .help("How long to wait for the login to finish, defaults to 300")
+                                .value_parser(value_parser!(u64)),
+                        )
+                        .arg(arg!(--pkce).help("Use PKCE for this login, even if the profile doesn't")),
+                )
+                .subcommand(Command::new("logout").about("Delete the token stored on this machine"))
+                .subcommand(Command::new("status").about("Show whether a token is stored and when it expires"))
+                .subcommand_required(true),
+        )
+        .subcommand(
+            Command::new("profile")
+                .about("Manage profiles for different accounts")
+                .subcommand(Command::new("list").about("List all profiles"))
+                .subcommand(
+                    Command::new("add")
+                        .about("Add a new profile")
+                        .arg(arg!(<NAME>).help("Name of the profile"))
+                        .arg(
+                            arg!(--"client-id" <ID>)
+                                .help("TickTick client id, falls back to TICKTICK_CLIENT_ID")
+                                .value_parser(value_parser!(String)),
+                        )
+                        .arg(
+                            arg!(--"client-secret" <SECRET>)
+                                .help("TickTick client secret, falls back to TICKTICK_CLIENT_SECRET")
+                                .value_parser(value_parser!(String)),
+                        )
+                        .arg(
+                            arg!(--"redirect-url" <URL>)
+                                .help("OAuth redirect url, falls back to TICKTICK_REDIRECT_URL")
+                                .value_parser(value_parser!(String)),
+                        )
+                        .arg(
+                            arg!(--"token-store" <STORE>)
+                                .help("Where to keep the access token")
+                                .value_parser(["file", "secret-service"])
+                                .default_value("file"),
+                        )
+                        .arg(arg!(--pkce).help("Use PKCE when logging in"))
+                        .arg(
+                            arg!(--host <HOST>)
+                                .help("Which service the profile talks to")
+                                .value_parser(["ticktick", "dida365"])
+                                .conflicts_with_all(["auth-url", "api-url"]),
+                        )
+                        .arg(
+                            arg!(--"auth-url" <URL>)
+                                .help("Custom OAuth base url, e.g. http://localhost:8080/oauth")
+                                .value_parser(value_parser!(String))
+                                .requires("api-url"),
+                        )
+                        .arg(
+                            arg!(--"api-url" <URL>)
+                                .help("Custom API base url, e.g. http://localhost:8080")
+                                .value_parser(value_parser!(String))
+                                .requires("auth-url"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("remove")
+                        .about("Remove a profile along with its token and cache")
+                        .arg(arg!(<NAME>).help("Name of the profile")),
+                )
+                .subcommand(
+                    Command::new("default")
+                        .about("Set the default profile")
+                        .arg(arg!(<NAME>).help("Name of the profile")),
+                )
                 .subcommand_required(true),
         )
         .get_matches();
 
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{:#}", err);
+            exit(1)
+        }
+    };
+
+    // Managing profiles should never need a token, so it's dealt with before any auth happens
+    if let Some(("profile", profile_matches)) = matches.subcommand() {
+        manage_profiles(profile_matches, config);
+        return;
+    }
+
+    let profile = match config.resolve(matches.get_one::<String>("profile").map(|x| x.as_str())) {
+        Ok(profile) => profile,
+        Err(err) => {
+            eprintln!("{}", err);
+            exit(1)
+        }
+    };
+
+    if matches.get_flag("verbose") {
+        eprintln!("Using profile: {}", profile.name);
+        eprintln!("Using API: {}", profile.host().api_url());
+    }
+
+    // Nothing logs in until a command actually needs to talk to TickTick
+    let tick = Session::new(&profile);
+    let picker = Picker::new(matches.get_one::<String>("picker").map(|x| x.as_str()));
+
     // Much of this is repetitive but I really don't want to abstract it out to another
     // function. It deals with the commandline directly and I would rather keep the
     // logic right here.
     match matches.subcommand() {
+        Some(("auth", auth_matches)) => manage_auth(auth_matches, &profile),
+        Some(("tags", tags_matches)) => {
+            let project = tags_matches.get_one::<String>("project");
+            list_tags(project.map(|x| x.as_str()), &tick);
+        }
+        Some(("view", view_matches)) => {
+            let project = view_matches.get_one::<String>("project");
+            let filters = read_filters(view_matches);
+            let query = view_matches.get_one::<String>("TASK");
+            view_task(
+                project.map(|x| x.as_str()),
+                query.map(|x| x.as_str()),
+                &filters,
+                &picker,
+                &tick,
+                &now,
+            );
+        }
+        Some(("board", board_matches)) => {
+            let project = board_matches.get_one::<String>("PROJECT").expect("PROJECT is required");
+            let width = board_matches.get_one::<usize>("width").copied();
+            show_board(project, width, &tick);
+        }
+        Some(("move", move_matches)) => {
+            let project = move_matches.get_one::<String>("project");
+            let query = move_matches.get_one::<String>("TASK");
+            let column = move_matches.get_one::<String>("column").expect("column is required");
+            move_task(
+                project.map(|x| x.as_str()),
+                query.map(|x| x.as_str()),
+                column,
+                &picker,
+                &tick,
+                &now,
+            );
+        }
+        Some(("tui", tui_matches)) => {
+            let project = tui_matches.get_one::<String>("project");
+            let frame = read_timeframe(tui_matches);
+            let every = tui_matches
+                .get_one::<u64>("refresh")
+                .copied()
+                .unwrap_or(DEFAULT_REFRESH);
+            run_tui(
+                project.map(|x| x.as_str()),
+                frame,
+                read_filters(tui_matches),
+                Duration::from_secs(every),
+                &tick,
+            );
+        }
+        Some(("shell", _)) => run_shell(&profile, &picker, &tick),
+        Some(("remind", remind_matches)) => {
+            let project = remind_matches.get_one::<String>("project");
+            let filters = read_filters(remind_matches);
+            match remind_matches.subcommand() {
+                Some(("list", list_matches)) => {
+                    let frame = read_timeframe(list_matches);
+                    list_reminders(project.map(|x| x.as_str()), frame, &filters, &tick, &now);
+                }
+                Some(("add", add_matches)) => {
+                    let offset = add_matches.get_one::<String>("OFFSET").expect("OFFSET is required");
+                    let frame = read_timeframe(add_matches);
+                    let edit = ReminderEdit::Add(offset);
+                    edit_reminders(project.map(|x| x.as_str()), frame, &filters, edit, &picker, &tick, &now);
+                }
+                Some(("remove", remove_matches)) => {
+                    let offset = remove_matches.get_one::<String>("OFFSET").expect("OFFSET is required");
+                    let frame = read_timeframe(remove_matches);
+                    let edit = ReminderEdit::Remove(offset);
+                    edit_reminders(project.map(|x| x.as_str()), frame, &filters, edit, &picker, &tick, &now);
+                }
+                _ => unreachable!(),
+            }
+        }
+        Some(("recur", recur_matches)) => {
+            let project = recur_matches.get_one::<String>("project");
+            let query = recur_matches.get_one::<String>("TASK").expect("TASK is required");
+            let count = *recur_matches.get_one::<usize>("count").expect("count has a default");
+            show_recurrence(project.map(|x| x.as_str()), query, count, &picker, &tick, &now);
+        }
+        Some(("done", done_matches)) => {
+            let project = done_matches.get_one::<String>("project");
+            let text = done_matches.get_one::<String>("PERIOD").expect("PERIOD has a default");
+            let period = match Period::parse(text, &now) {
+                Ok(period) => period,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    exit(1)
+                }
+            };
+            show_done(project.map(|x| x.as_str()), &period, &tick);
+        }
+        Some(("undo", undo_matches)) => {
+            let count = *undo_matches.get_one::<usize>("COUNT").expect("COUNT has a default");
+            undo_changes(count, &tick);
+        }
+        Some(("history", history_matches)) => {
+            let count = *history_matches.get_one::<usize>("count").expect("count has a default");
+            show_history(count, &tick);
+        }
+        Some(("tag", tag_matches)) => {
+            let project = tag_matches.get_one::<String>("project");
+            let filters = read_filters(tag_matches);
+            let (adding, edit_matches) = match tag_matches.subcommand() {
+                Some(("add", add_matches)) => (true, add_matches),
+                Some(("remove", remove_matches)) => (false, remove_matches),
+                _ => unreachable!(),
+            };
+            let tag = normalize_tag(edit_matches.get_one::<String>("TAG").expect("TAG is required"));
+            let edit = match adding {
+                true => TagEdit::Add(&tag),
+                false => TagEdit::Remove(&tag),
+            };
+            let frame = read_timeframe(edit_matches);
+            edit_tags(project.map(|x| x.as_str()), frame, &filters, edit, &picker, &tick, &now);
+        }
         Some(("show", show_matches)) => match show_matches.subcommand() {
             Some(("today", _)) => {
                 let project = show_matches.get_one::<String>("project");
-                show_tasks(project.map(|x| x.as_str()), TimeFrame::Today, &tick, &now);
+                let filters = read_filters(show_matches);
+                show_tasks(project.map(|x| x.as_str()), TimeFrame::Today, &filters, &tick, &now);
             }
             Some(("tomorrow", _)) => {
                 let project = show_matches.get_one::<String>("project");
-                show_tasks(project.map(|x| x.as_str()), TimeFrame::Tomorrow, &tick, &now);
+                let filters = read_filters(show_matches);
+                show_tasks(project.map(|x| x.as_str()), TimeFrame::Tomorrow, &filters, &tick, &now);
             }
             Some(("week", _)) => {
                 let project = show_matches.get_one::<String>("project");
-                show_tasks(project.map(|x| x.as_str()), TimeFrame::Week, &tick, &now);
+                let filters = read_filters(show_matches);
+                show_tasks(project.map(|x| x.as_str()), TimeFrame::Week, &filters, &tick, &now);
             }
             Some(("all", _)) => {
                 let project = show_matches.get_one::<String>("project");
-                show_tasks(project.map(|x| x.as_str()), TimeFrame::All, &tick, &now);
+                let filters = read_filters(show_matches);
+                show_tasks(project.map(|x| x.as_str()), TimeFrame::All, &filters, &tick, &now);
             }
             _ => unreachable!(),
         },
         Some(("complete", show_matches)) => match show_matches.subcommand() {
             Some(("today", _)) => {
                 let project = show_matches.get_one::<String>("project");
+                let filters = read_filters(show_matches);
                 show_and_finish_tasks(
                     project.map(|x| x.as_str()),
                     TimeFrame::Today,
-                    TaskAction::Complete,
+                    Action::Complete,
+                    &filters,
+                    &picker,
                     &tick,
                     &now,
                 );
             }
             Some(("tomorrow", _)) => {
                 let project = show_matches.get_one::<String>("project");
+                let filters = read_filters(show_matches);
                 show_and_finish_tasks(
                     project.map(|x| x.as_str()),
                     TimeFrame::Tomorrow,
-                    TaskAction::Complete,
+                    Action::Complete,
+                    &filters,
+                    &picker,
                     &tick,
                     &now,
                 );
             }
             Some(("week", _)) => {
                 let project = show_matches.get_one::<String>("project");
+                let filters = read_filters(show_matches);
                 show_and_finish_tasks(
                     project.map(|x| x.as_str()),
                     TimeFrame::Week,
-                    TaskAction::Complete,
+                    Action::Complete,
+                    &filters,
+                    &picker,
                     &tick,
                     &now,
                 );
             }
             Some(("all", _)) => {
                 let project = show_matches.get_one::<String>("project");
+                let filters = read_filters(show_matches);
                 show_and_finish_tasks(
                     project.map(|x| x.as_str()),
                     TimeFrame::All,
-                    TaskAction::Complete,
+                    Action::Complete,
+                    &filters,
+                    &picker,
                     &tick,
                     &now,
                 );
@@ -143,40 +600,52 @@ fn main() {
         Some(("delete", show_matches)) => match show_matches.subcommand() {
             Some(("today", _)) => {
                 let project = show_matches.get_one::<String>("project");
+                let filters = read_filters(show_matches);
                 show_and_finish_tasks(
                     project.map(|x| x.as_str()),
                     TimeFrame::Today,
-                    TaskAction::Delete,
+                    Action::Delete,
+                    &filters,
+                    &picker,
                     &tick,
                     &now,
                 );
             }
             Some(("tomorrow", _)) => {
                 let project = show_matches.get_one::<String>("project");
+                let filters = read_filters(show_matches);
                 show_and_finish_tasks(
                     project.map(|x| x.as_str()),
                     TimeFrame::Tomorrow,
-                    TaskAction::Delete,
+                    Action::Delete,
+                    &filters,
+                    &picker,
                     &tick,
                     &now,
                 );
             }
             Some(("week", _)) => {
                 let project = show_matches.get_one::<String>("project");
+                let filters = read_filters(show_matches);
                 show_and_finish_tasks(
                     project.map(|x| x.as_str()),
                     TimeFrame::Week,
-                    TaskAction::Delete,
+                    Action::Delete,
+                    &filters,
+                    &picker,
                     &tick,
                     &now,
                 );
             }
             Some(("all", _)) => {
                 let project = show_matches.get_one::<String>("project");
+                let filters = read_filters(show_matches);
                 show_and_finish_tasks(
                     project.map(|x| x.as_str()),
                     TimeFrame::All,
-                    TaskAction::Delete,
+                    Action::Delete,
+                    &filters,
+                    &picker,
                     &tick,
                     &now,
                 );
@@ -187,76 +656,742 @@ fn main() {
     }
 }
 
-#[derive(Clone, Copy)]
-enum TaskAction {
-    Complete,
-    Delete,
+fn manage_auth(matches: &ArgMatches, profile: &ActiveProfile) {
+    match matches.subcommand() {
+        Some(("login", login_matches)) => {
+            let defaults = LoginOptions::default();
+            let options = LoginOptions {
+                no_browser: login_matches.get_flag("no-browser"),
+                timeout: login_matches
+                    .get_one::<u64>("timeout")
+                    .map_or(defaults.timeout, |secs| Duration::from_secs(*secs)),
+                pkce: login_matches.get_flag("pkce") || profile.profile.pkce,
+            };
+            match session::login(profile, &options) {
+                Ok(_) => println!("Logged in to profile '{}'", profile.name),
+                Err(err) => {
+                    eprintln!("Unable to log in: {}", err);
+                    exit(1)
+                }
+            }
+        }
+        Some(("logout", _)) => match session::logout(profile) {
+            Ok(false) => println!("Profile '{}' is not logged in", profile.name),
+            Ok(true) => {
+                println!("Logged out of profile '{}'", profile.name);
+                println!("Only the stored token was removed, TickTick keeps it valid until it expires");
+            }
+            Err(err) => {
+                eprintln!("Unable to log out: {}", err);
+                exit(1)
+            }
+        },
+        Some(("status", _)) => {
+            let token = profile
+                .token_store()
+                .and_then(|store| session::stored_access_token(profile, store.as_ref()));
+            match token {
+                Ok(Some(token)) => {
+                    let expires = token.expires_on.to_zoned(TimeZone::system());
+                    let state = if token.is_expired() { "expired" } else { "valid" };
+                    println!("Profile: {}", profile.name);
+                    println!("Token:   {}", state);
+                    println!("Expires: {}", expires.strftime("%Y-%m-%d %I:%M %p %Z"));
+                    println!("Scopes:  {}", token.scope.as_deref().unwrap_or("unknown"));
+                }
+                Ok(None) => println!("Profile '{}' is not logged in", profile.name),
+                Err(err) => {
+                    eprintln!("Unable to read token: {}", err);
+                    exit(1)
+                }
+            }
+        }
+        _ => unreachable!(),
+    }
 }
 
-fn show_tasks(project: Option<&str>, timeframe: TimeFrame, client: &TickTickClient, now: &Zoned) {
-    let result = match project {
+fn manage_profiles(matches: &ArgMatches, mut config: Config) {
+    let result = match matches.subcommand() {
+        Some(("list", _)) => {
+            let default = config.default_profile();
+            if !config.profiles.contains_key(config::DEFAULT_PROFILE) {
+                let marker = if default == config::DEFAULT_PROFILE { "*" } else { " " };
+                println!("{} {} (environment)", marker, config::DEFAULT_PROFILE);
+            }
+            for name in config.profiles.keys() {
+                let marker = if default == name { "*" } else { " " };
+                println!("{} {}", marker, name);
+            }
+            return;
+        }
+        Some(("add", add_matches)) => {
+            let name = add_matches.get_one::<String>("NAME").expect("NAME is required");
+            let profile = Profile {
+                client_id: add_matches.get_one::<String>("client-id").cloned(),
+                client_secret: add_matches.get_one::<String>("client-secret").cloned(),
+                redirect_url: add_matches.get_one::<String>("redirect-url").cloned(),
+                token_store: match add_matches.get_one::<String>("token-store").map(|x| x.as_str()) {
+                    Some("secret-service") => Some(TokenStoreKind::SecretService),
+                    _ => None,
+                },
+                pkce: add_matches.get_flag("pkce"),
+                host: match (
+                    add_matches.get_one::<String>("host").map(|x| x.as_str()),
+                    add_matches.get_one::<String>("auth-url"),
+                    add_matches.get_one::<String>("api-url"),
+                ) {
+                    (Some("dida365"), _, _) => Some(Host::Dida365),
+                    (_, Some(auth_url), Some(api_url)) => Some(Host::Custom {
+                        auth_url: auth_url.clone(),
+                        api_url: api_url.clone(),
+                    }),
+                    _ => None,
+                },
+            };
+            config
+                .add_profile(name, profile)
+                .and_then(|_| config.save())
+                .map(|_| format!("Profile '{}' added", name))
+        }
+        Some(("remove", remove_matches)) => {
+            let name = remove_matches.get_one::<String>("NAME").expect("NAME is required");
+            config
+                .remove_profile(name)
+                .and_then(|profile| {
+                    config.save()?;
+                    let removed = ActiveProfile {
+                        name: name.clone(),
+                        profile,
+                    };
+                    removed.token_store()?.delete()?;
+                    Journal::new(&removed)?.clear()?;
+                    removed.clear_cache()
+                })
+                .map(|_| format!("Profile '{}' removed", name))
+        }
+        Some(("default", default_matches)) => {
+            let name = default_matches.get_one::<String>("NAME").expect("NAME is required");
+            config
+                .set_default_profile(name)
+                .and_then(|_| config.save())
+                .map(|_| format!("Profile '{}' is now the default", name))
+        }
+        _ => unreachable!(),
+    };
+
+    match result {
+        Ok(message) => println!("{}", message),
+        Err(err) => {
+            eprintln!("{:#}", err);
+            exit(1)
+        }
+    }
+}
+
+fn show_tasks(project: Option<&str>, timeframe: TimeFrame, filters: &Filters, session: &Session, now: &Zoned) {
+    let result = session.call(|client| match project {
         Some(proj) => client.get_single_project_with_data(proj),
         None => client.get_projects_with_data(),
+    });
+
+    let mut projects = match result {
+        Ok(proj) => proj,
+        Err(err) => exit(report_error(&err)),
+    };
+    if filters.include_completed {
+        add_completed(&mut projects, timeframe, session, now);
+    }
+    let tagged_tasks = filter(&projects, timeframe, filters);
+    print_tasks(&tagged_tasks, now);
+}
+
+// Only fetched when asked for, it's one more request
+fn add_completed(projects: &mut [ProjectData], frame: TimeFrame, session: &Session, now: &Zoned) {
+    let added = Period::for_frame(frame, now).and_then(|period| journal::add_completed(projects, &period, session));
+    if let Err(err) = added {
+        exit(report_error(&err))
+    }
+}
+
+fn show_done(project: Option<&str>, period: &Period, session: &Session) {
+    let projects = match session.call(|client| client.get_projects()) {
+        Ok(projects) => projects,
+        Err(err) => exit(report_error(&err)),
     };
+    let projects: Vec<Project> = projects
+        .into_iter()
+        .filter(|proj| project.is_none_or(|name| proj.name == name))
+        .collect();
+    let completed = match journal::completed(&projects, period, session) {
+        Ok(tasks) => tasks,
+        Err(err) => exit(report_error(&err)),
+    };
+    if completed.is_empty() {
+        println!("Nothing was completed then");
+        return;
+    }
+
+    for task in &completed {
+        let proj = projects.iter().find(|proj| proj.id == task.project_id);
+        print_completed(&TaggedTask {
+            project_name: proj.map_or("", |proj| proj.name.as_str()),
+            color: proj.and_then(|proj| proj.color.as_deref()),
+            task,
+            projected: None,
+        });
+    }
+}
+
+fn show_and_finish_tasks(
+    project: Option<&str>,
+    frame: TimeFrame,
+    action: Action,
+    filters: &Filters,
+    picker: &Picker,
+    session: &Session,
+    now: &Zoned,
+) {
+    let result = session.call(|client| match project {
+        Some(proj) => client.get_single_project_with_data(proj),
+        None => client.get_projects_with_data(),
+    });
 
     let projects = match result {
         Ok(proj) => proj,
+        Err(err) => exit(report_error(&err)),
+    };
+    let tagged_tasks = filter(&projects, frame, filters);
+    let chosen = match choose_tasks(&tagged_tasks, picker, true, now) {
+        Ok(tasks) => tasks,
         Err(err) => {
-            eprintln!("{}", err);
-            exit(1)
+            eprintln!("Error with user input: {}", err);
+            exit(1);
         }
     };
-    let tagged_tasks = filter(&projects, timeframe);
-    for (num, task) in tagged_tasks.iter().enumerate() {
-        print_task(num, task, now);
+
+    for task in chosen {
+        match action {
+            Action::Complete => match session.call(|client| client.complete_task(task)) {
+                Ok(_) => println!("Task completed successfully"),
+                Err(err) => {
+                    eprint!("Unable to complete task: ");
+                    exit(report_error(&err))
+                }
+            },
+            Action::Delete => match session.call(|client| client.delete_task(task)) {
+                Ok(_) => println!("Task delete successfully"),
+                Err(err) => {
+                    eprint!("Unable to delete task: ");
+                    exit(report_error(&err))
+                }
+            },
+        }
+        remember(action, &projects, task, session);
     }
 }
 
-fn show_and_finish_tasks(
+fn remember(action: Action, projects: &[ProjectData], task: &Task, session: &Session) {
+    let recorded = Journal::new(session.profile()).and_then(|journal| journal.remember(action, projects, task));
+    if let Err(err) = recorded {
+        eprintln!("Unable to save '{}' for tok undo: {:#}", task.title, err);
+    }
+}
+
+fn undo_changes(count: usize, session: &Session) {
+    let undone = Journal::new(session.profile())
+        .and_then(|journal| journal.undo_latest(count, session, |entry, _| println!("{}", entry.undone())));
+    match undone {
+        Ok(0) => println!("Nothing to undo"),
+        Ok(_) => {}
+        Err(err) => exit(report_error(&err)),
+    }
+}
+
+fn show_history(count: usize, session: &Session) {
+    let entries = match Journal::new(session.profile()).and_then(|journal| journal.entries()) {
+        Ok(entries) => entries,
+        Err(err) => exit(report_error(&err)),
+    };
+    if entries.is_empty() {
+        println!("Nothing has been completed or deleted yet");
+        return;
+    }
+    for entry in entries.iter().rev().take(count) {
+        journal::print_entry(entry);
+    }
+}
+
+fn edit_tags(
     project: Option<&str>,
     frame: TimeFrame,
-    action: TaskAction,
-    client: &TickTickClient,
+    filters: &Filters,
+    edit: TagEdit,
+    picker: &Picker,
+    session: &Session,
     now: &Zoned,
 ) {
-    let result = match project {
+    let (TagEdit::Add(tag) | TagEdit::Remove(tag)) = edit;
+    let result = session.call(|client| match project {
         Some(proj) => client.get_single_project_with_data(proj),
         None => client.get_projects_with_data(),
+    });
+
+    let projects = match result {
+        Ok(proj) => proj,
+        Err(err) => exit(report_error(&err)),
+    };
+    let tagged_tasks = filter(&projects, frame, filters);
+    let chosen = match choose_tasks(&tagged_tasks, picker, true, now) {
+        Ok(tasks) => tasks,
+        Err(err) => {
+            eprintln!("Error with user input: {}", err);
+            exit(1);
+        }
     };
+    if chosen.is_empty() {
+        return;
+    }
+
+    let mut changed = 0;
+    for chosen_task in chosen {
+        let mut task = chosen_task.clone();
+        // Nothing to send when the task already is the way it should be
+        if has_tag(&task, tag) == matches!(edit, TagEdit::Add(_)) {
+            continue;
+        }
+
+        let tags = task.tags.get_or_insert_with(Vec::new);
+        match edit {
+            TagEdit::Add(_) => tags.push(tag.to_string()),
+            TagEdit::Remove(_) => tags.retain(|existing| existing.to_lowercase() != tag.to_lowercase()),
+        }
+
+        if let Err(err) = session.call(|client| client.update_task(&task)) {
+            eprint!("Unable to update '{}': ", task.title);
+            exit(report_error(&err))
+        }
+        changed += 1;
+    }
+
+    match edit {
+        TagEdit::Add(_) => println!("Added {} to {} task(s)", tag_chip(tag), changed),
+        TagEdit::Remove(_) => println!("Removed {} from {} task(s)", tag_chip(tag), changed),
+    }
+}
+
+fn list_tags(project: Option<&str>, session: &Session) {
+    let result = session.call(|client| match project {
+        Some(proj) => client.get_single_project_with_data(proj),
+        None => client.get_projects_with_data(),
+    });
 
     let projects = match result {
         Ok(proj) => proj,
+        Err(err) => exit(report_error(&err)),
+    };
+
+    for (tag, count) in tag_counts(&projects) {
+        println!("{:>4}  {}", count, tag_chip(&tag));
+    }
+}
+
+fn view_task(
+    project: Option<&str>,
+    query: Option<&str>,
+    filters: &Filters,
+    picker: &Picker,
+    session: &Session,
+    now: &Zoned,
+) {
+    let result = session.call(|client| match project {
+        Some(proj) => client.get_single_project_with_data(proj),
+        None => client.get_projects_with_data(),
+    });
+
+    let mut projects = match result {
+        Ok(proj) => proj,
+        Err(err) => exit(report_error(&err)),
+    };
+    if filters.include_completed {
+        add_completed(&mut projects, TimeFrame::All, session, now);
+    }
+    let tagged_tasks = filter(&projects, TimeFrame::All, filters);
+
+    let task = choose_task(&projects, &tagged_tasks, query, picker, now);
+    let project = projects.iter().find(|proj| proj.project.id == task.project_id);
+    print_task_details(task, project, now);
+}
+
+// By id, by its number in `tok show all`, or by part of the title, asking which one when that isn't enough
+fn choose_task<'a>(
+    projects: &'a [ProjectData],
+    tagged_tasks: &[TaggedTask<'a>],
+    query: Option<&str>,
+    picker: &Picker,
+    now: &Zoned,
+) -> &'a Task {
+    let by_id = projects
+        .iter()
+        .flat_map(|proj| &proj.tasks)
+        .find(|task| Some(task.id.as_str()) == query);
+    let number = query.and_then(|query| query.parse::<usize>().ok());
+    match (by_id, number) {
+        (Some(task), _) => task,
+        // Numbered the same way `tok show all` numbers them
+        (None, Some(num)) => match num
+            .checked_sub(1)
+            .and_then(|num| tagged_tasks.iter().filter(|tagged| tagged.selectable()).nth(num))
+        {
+            Some(tagged) => tagged.task,
+            None => {
+                eprintln!("Invalid task number {}", num);
+                exit(1)
+            }
+        },
+        (None, None) => {
+            let query = query.unwrap_or_default();
+            let matching: Vec<TaggedTask> = tagged_tasks
+                .iter()
+                .filter(|tagged| tagged.task.title.to_lowercase().contains(&query.to_lowercase()))
+                .cloned()
+                .collect();
+            match matching.len() {
+                0 => {
+                    eprintln!("No To-Do item matches '{}'", query);
+                    exit(1)
+                }
+                1 if !query.is_empty() => matching[0].task,
+                _ => choose_one(&matching, query, picker, now),
+            }
+        }
+    }
+}
+
+// Only asked once there's more than one to choose from
+fn choose_one<'a>(matching: &[TaggedTask<'a>], query: &str, picker: &Picker, now: &Zoned) -> &'a Task {
+    match choose_tasks(matching, picker, false, now) {
+        Ok(chosen) => match chosen.first() {
+            Some(task) => task,
+            None => {
+                eprintln!("No To-Do item matches '{}'", query);
+                exit(1)
+            }
+        },
+        Err(err) => {
+            eprintln!("Error with user input: {}", err);
+            exit(1);
+        }
+    }
+}
+
+fn show_board(project: &str, width: Option<usize>, session: &Session) {
+    let projects = match session.call(|client| client.get_single_project_with_data(project)) {
+        Ok(proj) => proj,
+        Err(err) => exit(report_error(&err)),
+    };
+    if projects.is_empty() {
+        eprintln!("No project named '{}'", project);
+        exit(1)
+    }
+
+    let width = width
+        .or_else(|| terminal_size::terminal_size().map(|(width, _)| width.0 as usize))
+        .unwrap_or(DEFAULT_WIDTH);
+    for (num, data) in projects.iter().enumerate() {
+        if num > 0 {
+            println!();
+        }
+        print_board(data, width);
+    }
+}
+
+fn move_task(
+    project: Option<&str>,
+    query: Option<&str>,
+    column: &str,
+    picker: &Picker,
+    session: &Session,
+    now: &Zoned,
+) {
+    let result = session.call(|client| match project {
+        Some(proj) => client.get_single_project_with_data(proj),
+        None => client.get_projects_with_data(),
+    });
+
+    let projects = match result {
+        Ok(proj) => proj,
+        Err(err) => exit(report_error(&err)),
+    };
+    let tagged_tasks = filter(&projects, TimeFrame::All, &Filters::default());
+    let mut task = choose_task(&projects, &tagged_tasks, query, picker, now).clone();
+
+    // Columns belong to a project, so a task can only move between the ones in its own
+    let data = projects
+        .iter()
+        .find(|proj| proj.project.id == task.project_id)
+        .expect("The task came from one of these projects");
+    let target = match find_column(data, column) {
+        Ok(target) => target,
         Err(err) => {
             eprintln!("{}", err);
             exit(1)
         }
     };
-    let tagged_tasks = filter(&projects, frame);
-    let num_tasks = tagged_tasks.len();
-    for (num, task) in tagged_tasks.iter().enumerate() {
-        print_task(num, task, now);
-    }
 
-    if num_tasks < 1 {
+    if task.column_id.as_ref() == Some(&target.id) {
+        println!("'{}' is already in {}", task.title, target.name);
         return;
     }
+    task.column_id = Some(target.id.clone());
+    match session.call(|client| client.update_task(&task)) {
+        Ok(_) => println!("Moved '{}' to {}", task.title, target.name),
+        Err(err) => {
+            eprint!("Unable to move '{}': ", task.title);
+            exit(report_error(&err))
+        }
+    }
+}
+
+// Once a minute is plenty for To-Do items and stays well clear of TickTick's rate limits
+const DEFAULT_REFRESH: u64 = 60;
+
+fn run_tui(project: Option<&str>, frame: TimeFrame, filters: Filters, every: Duration, session: &Session) {
+    if !io::stdout().is_terminal() {
+        eprintln!("tok tui needs a terminal to draw in");
+        exit(1)
+    }
+
+    // Loaded before the screen is taken over, so logging in again can still ask for things
+    let result = session.call(|client| match project {
+        Some(proj) => client.get_single_project_with_data(proj),
+        None => client.get_projects_with_data(),
+    });
+    let mut projects = match result {
+        Ok(proj) => proj,
+        Err(err) => exit(report_error(&err)),
+    };
+    // The timeframe can be switched on screen, and week reaches back as far as any of them
+    if filters.include_completed {
+        add_completed(&mut projects, TimeFrame::Week, session, &Zoned::now());
+    }
+    let client = match session.client() {
+        Ok(client) => client,
+        Err(err) => exit(report_error(&err)),
+    };
+    let journal = match Journal::new(session.profile()) {
+        Ok(journal) => journal,
+        Err(err) => exit(report_error(&err)),
+    };
+
+    if let Err(err) = tui::run(
+        client,
+        projects,
+        project.map(str::to_string),
+        frame,
+        filters,
+        every,
+        journal,
+    ) {
+        eprintln!("{:#}", err);
+        exit(1)
+    }
+}
+
+fn run_shell(profile: &ActiveProfile, picker: &Picker, session: &Session) {
+    let projects = match session.call(|client| client.get_projects_with_data()) {
+        Ok(proj) => proj,
+        Err(err) => exit(report_error(&err)),
+    };
+
+    if let Err(err) = shell::run(profile, session, picker, projects) {
+        eprintln!("{:#}", err);
+        exit(1)
+    }
+}
+
+fn list_reminders(project: Option<&str>, frame: TimeFrame, filters: &Filters, session: &Session, now: &Zoned) {
+    let result = session.call(|client| match project {
+        Some(proj) => client.get_single_project_with_data(proj),
+        None => client.get_projects_with_data(),
+    });
+
+    let mut projects = match result {
+        Ok(proj) => proj,
+        Err(err) => exit(report_error(&err)),
+    };
+    if filters.include_completed {
+        add_completed(&mut projects, frame, session, now);
+    }
+    let tagged_tasks = filter(&projects, frame, filters);
+    let with_reminders = tagged_tasks
+        .iter()
+        .filter(|tagged| tagged.projected.is_none())
+        .filter(|tagged| {
+            tagged
+                .task
+                .reminders
+                .as_ref()
+                .is_some_and(|reminders| !reminders.is_empty())
+        });
+    for (num, tagged) in with_reminders.enumerate() {
+        print_task(num, tagged, now);
+        print_reminders(tagged.task);
+    }
+}
+
+// Along with the offset, as it was typed, being added or removed
+#[derive(Clone, Copy)]
+enum ReminderEdit<'a> {
+    Add(&'a str),
+    Remove(&'a str),
+}
 
-    let remove = match get_number(tagged_tasks.len() + 1) {
-        Ok(num) => num,
+fn edit_reminders(
+    project: Option<&str>,
+    frame: TimeFrame,
+    filters: &Filters,
+    edit: ReminderEdit,
+    picker: &Picker,
+    session: &Session,
+    now: &Zoned,
+) {
+    let (ReminderEdit::Add(offset) | ReminderEdit::Remove(offset)) = edit;
+    let result = session.call(|client| match project {
+        Some(proj) => client.get_single_project_with_data(proj),
+        None => client.get_projects_with_data(),
+    });
+
+    let projects = match result {
+        Ok(proj) => proj,
+        Err(err) => exit(report_error(&err)),
+    };
+    let tagged_tasks = filter(&projects, frame, filters);
+    let chosen = match choose_tasks(&tagged_tasks, picker, true, now) {
+        Ok(tasks) => tasks,
         Err(err) => {
             eprintln!("Error with user input: {}", err);
             exit(1);
         }
     };
+    if chosen.is_empty() {
+        return;
+    }
 
-    match action {
-        TaskAction::Complete => match client.complete_task(tagged_tasks[remove].task) {
-            Ok(_) => println!("Task completed successfully"),
-            Err(err) => eprintln!("Unable to complete task: {}", err),
-        },
-        TaskAction::Delete => match client.delete_task(tagged_tasks[remove].task) {
-            Ok(_) => println!("Task delete successfully"),
-            Err(err) => eprintln!("Unable to delete task: {}", err),
-        },
+    let mut changed = 0;
+    for chosen_task in chosen {
+        let mut task = chosen_task.clone();
+        // Reminders are relative to the due date, so there has to be one
+        let Some(due) = &task.due_date else {
+            eprintln!("'{}' has no due date to be reminded about", task.title);
+            continue;
+        };
+        let reminder = match Reminder::from_phrase(offset, due) {
+            Ok(reminder) => Reminder {
+                all_day: task.is_all_day,
+                ..reminder
+            },
+            Err(err) => {
+                eprintln!("{}", err);
+                exit(1)
+            }
+        };
+
+        // Compared by offset since TickTick doesn't always write the same offset the same way
+        let reminders = task.reminders.get_or_insert_with(Vec::new);
+        let same = |trigger: &String| {
+            trigger
+                .parse::<Reminder>()
+                .is_ok_and(|existing| existing.offset == reminder.offset)
+        };
+        match edit {
+            ReminderEdit::Add(_) if reminders.iter().any(same) => continue,
+            ReminderEdit::Add(_) => reminders.push(reminder.to_string()),
+            ReminderEdit::Remove(_) if !reminders.iter().any(same) => continue,
+            ReminderEdit::Remove(_) => reminders.retain(|trigger| !same(trigger)),
+        }
+
+        if let Err(err) = session.call(|client| client.update_task(&task)) {
+            eprint!("Unable to update '{}': ", task.title);
+            exit(report_error(&err))
+        }
+        changed += 1;
+    }
+
+    match edit {
+        ReminderEdit::Add(_) => println!("Added the reminder to {} task(s)", changed),
+        ReminderEdit::Remove(_) => println!("Removed the reminder from {} task(s)", changed),
+    }
+}
+
+fn show_recurrence(project: Option<&str>, query: &str, count: usize, picker: &Picker, session: &Session, now: &Zoned) {
+    let result = session.call(|client| match project {
+        Some(proj) => client.get_single_project_with_data(proj),
+        None => client.get_projects_with_data(),
+    });
+
+    let projects = match result {
+        Ok(proj) => proj,
+        Err(err) => exit(report_error(&err)),
+    };
+
+    // An exact id wins, otherwise anything with the query somewhere in its title
+    let repeating: Vec<TaggedTask> = projects
+        .iter()
+        .flat_map(|proj| {
+            proj.tasks.iter().filter(|task| repeats(task)).map(|task| TaggedTask {
+                project_name: &proj.project.name,
+                color: proj.project.color.as_deref(),
+                task,
+                projected: None,
+            })
+        })
+        .collect();
+    let by_id: Vec<TaggedTask> = repeating
+        .iter()
+        .filter(|tagged| tagged.task.id == query)
+        .cloned()
+        .collect();
+    let matching = match by_id.is_empty() {
+        false => by_id,
+        true => repeating
+            .iter()
+            .filter(|tagged| tagged.task.title.to_lowercase().contains(&query.to_lowercase()))
+            .cloned()
+            .collect(),
+    };
+
+    let task = match matching.len() {
+        0 => {
+            eprintln!("No repeating To-Do item matches '{}'", query);
+            exit(1)
+        }
+        1 => matching[0].task,
+        _ => choose_one(&matching, query, picker, now),
+    };
+
+    let rule = match task.recurrence() {
+        Ok(rule) => rule.expect("Only repeating tasks are matched"),
+        Err(err) => {
+            eprintln!("Unable to follow the repeat rule for '{}': {}", task.title, err);
+            exit(1)
+        }
+    };
+    let Some(start) = task.start_date.as_ref().or(task.due_date.as_ref()) else {
+        eprintln!("'{}' has no date to repeat from", task.title);
+        exit(1)
+    };
+
+    // All day tasks don't have a meaningful time
+    let format = match task.is_all_day {
+        true => "%a %m/%d/%Y",
+        false => "%a %m/%d/%Y %I:%M %p",
+    };
+    println!("{}{}", task.title, repeat_marker(task));
+    let upcoming: Vec<Zoned> = rule.occurrences(start).filter(|next| next >= now).take(count).collect();
+    if upcoming.is_empty() {
+        println!("  No more occurrences");
+    }
+    for next in upcoming {
+        println!("  {}", next.strftime(format));
     }
 }
@@ -0,0 +1,279 @@
+use kolorz::HexKolorize;
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+
+// Only ever turned off with their own codes, so nesting e.g. italics inside a heading doesn't undo the heading
+const BOLD: &str = "\x1b[1m";
+const NO_BOLD: &str = "\x1b[22m";
+const ITALIC: &str = "\x1b[3m";
+const NO_ITALIC: &str = "\x1b[23m";
+const UNDERLINE: &str = "\x1b[4m";
+const NO_UNDERLINE: &str = "\x1b[24m";
+const STRIKE: &str = "\x1b[9m";
+const NO_STRIKE: &str = "\x1b[29m";
+
+const CODE_COLOR: &str = "#F2B04B";
+const QUOTE_COLOR: &str = "#8F7CF2";
+
+/*
+Task content is markdown, this turns the bits people actually use in a task
+(headings, lists, checkboxes, links, emphasis and code) into something readable
+in a terminal, wrapped at `width`. Anything fancier comes through as plain text
+*/
+pub fn render(markdown: &str, width: usize) -> String {
+    let mut out = String::new();
+    // One entry per nested list, the next number for ordered ones
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    let mut links: Vec<String> = Vec::new();
+    let mut quotes = 0;
+    let mut in_code_block = false;
+    // What a wrapped line starts with, so it lines up under the text above it
+    let mut hang = String::new();
+
+    let options = Options::ENABLE_TASKLISTS | Options::ENABLE_STRIKETHROUGH;
+    for event in Parser::new_ext(markdown, options) {
+        match event {
+            Event::Start(Tag::Heading { .. }) => {
+                block_gap(&mut out);
+                out.push_str(BOLD);
+                hang.clear();
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                out.push_str(NO_BOLD);
+                out.push('\n');
+            }
+            Event::Start(Tag::Paragraph) => {
+                if lists.is_empty() {
+                    block_gap(&mut out);
+                    hang.clear();
+                }
+                if quotes > 0 {
+                    hang = "│ ".repeat(quotes).kolorize(QUOTE_COLOR).to_string();
+                    out.push_str(&hang);
+                }
+            }
+            Event::End(TagEnd::Paragraph) => out.push('\n'),
+            Event::Start(Tag::BlockQuote(_)) => quotes += 1,
+            Event::End(TagEnd::BlockQuote(_)) => quotes -= 1,
+            Event::Start(Tag::List(start)) => {
+                match lists.is_empty() {
+                    true => block_gap(&mut out),
+                    false => line_break(&mut out),
+                }
+                lists.push(start);
+            }
+            Event::End(TagEnd::List(_)) => {
+                lists.pop();
+            }
+            Event::Start(Tag::Item) => {
+                line_break(&mut out);
+                let marker = match lists.last_mut() {
+                    Some(Some(num)) => {
+                        *num += 1;
+                        format!("{}.", *num - 1)
+                    }
+                    _ => "•".to_string(),
+                };
+                out.push_str(&"  ".repeat(lists.len().saturating_sub(1)));
+                out.push_str(&marker);
+                out.push(' ');
+                hang = " ".repeat(column(&out));
+            }
+            Event::End(TagEnd::Item) => line_break(&mut out),
+            Event::TaskListMarker(done) => {
+                out.push_str(if done { "[x] " } else { "[ ] " });
+                hang = " ".repeat(column(&out));
+            }
+            Event::Start(Tag::Emphasis) => out.push_str(ITALIC),
+            Event::End(TagEnd::Emphasis) => out.push_str(NO_ITALIC),
+            Event::Start(Tag::Strong) => out.push_str(BOLD),
+            Event::End(TagEnd::Strong) => out.push_str(NO_BOLD),
+            Event::Start(Tag::Strikethrough) => out.push_str(STRIKE),
+            Event::End(TagEnd::Strikethrough) => out.push_str(NO_STRIKE),
+            Event::Start(Tag::Link { dest_url, .. }) => {
+                links.push(dest_url.to_string());
+                out.push_str(UNDERLINE);
+            }
+            // Terminals can't click most links, so the url is spelled out after the text
+            Event::End(TagEnd::Link) => {
+                out.push_str(NO_UNDERLINE);
+                if let Some(url) = links.pop() {
+                    push_wrapped(&mut out, &format!(" ({})", url), width, &hang);
+                }
+            }
+            Event::Start(Tag::CodeBlock(_)) => {
+                block_gap(&mut out);
+                in_code_block = true;
+            }
+            Event::End(TagEnd::CodeBlock) => in_code_block = false,
+            Event::Text(text) if in_code_block => {
+                for line in text.lines() {
+                    out.push_str(&format!("    {}\n", line.kolorize(CODE_COLOR)));
+                }
+            }
+            Event::Text(text) | Event::Html(text) | Event::InlineHtml(text) => {
+                push_wrapped(&mut out, &text, width, &hang)
+            }
+            Event::Code(code) => push_wrapped(&mut out, &code.kolorize(CODE_COLOR).to_string(), width, &hang),
+            Event::SoftBreak => out.push(' '),
+            Event::HardBreak => {
+                out.push('\n');
+                out.push_str(&hang);
+            }
+            Event::Rule => {
+                block_gap(&mut out);
+                out.push_str("────────\n");
+            }
+            _ => {}
+        }
+    }
+
+    out.trim_end().to_string()
+}
+
+// A blank line between blocks, but not before the first one
+fn block_gap(out: &mut String) {
+    if out.is_empty() {
+        return;
+    }
+    while !out.ends_with("\n\n") {
+        out.push('\n');
+    }
+}
+
+fn line_break(out: &mut String) {
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+}
+
+// Breaks at the last space once a word would run past `width`, carrying on after `hang`
+fn push_wrapped(out: &mut String, text: &str, width: usize, hang: &str) {
+    for (num, word) in text.split(' ').enumerate() {
+        if num > 0 {
+            out.push(' ');
+        }
+        let past_hang = column(out) > visible_width(hang);
+        if let Some(space) = trailing_space(out).filter(|_| past_hang) {
+            if column(out) + visible_width(word) > width {
+                out.replace_range(space..=space, &format!("\n{}", hang));
+            }
+        }
+        out.push_str(word);
+    }
+}
+
+// Where the space at the end is, looking past any styles turned on or off after it
+fn trailing_space(out: &str) -> Option<usize> {
+    let mut end = out.len();
+    while out[..end].ends_with('m') {
+        let start = out[..end].rfind("\x1b[")?;
+        if !out[start + 2..end - 1].chars().all(|c| c.is_ascii_digit() || c == ';') {
+            break;
+        }
+        end = start;
+    }
+    out[..end].ends_with(' ').then(|| end - 1)
+}
+
+// How far along the last line is, not counting styles
+fn column(out: &str) -> usize {
+    visible_width(out.rsplit('\n').next().unwrap_or_default())
+}
+
+fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut in_code = false;
+    for c in text.chars() {
+        match (in_code, c) {
+            (false, '\x1b') => in_code = true,
+            (false, _) => width += 1,
+            (true, 'm') => in_code = false,
+            (true, _) => {}
+        }
+    }
+    width
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::DEFAULT_WIDTH;
+
+    fn plain(text: &str) -> String {
+        let mut out = String::new();
+        let mut in_code = false;
+        for c in text.chars() {
+            match (in_code, c) {
+                (false, '\x1b') => in_code = true,
+                (false, _) => out.push(c),
+                (true, 'm') => in_code = false,
+                (true, _) => {}
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn makes_headings_bold() {
+        let out = render("# Groceries\nFrom the market", DEFAULT_WIDTH);
+        assert!(out.starts_with(&format!("{}Groceries{}\n", BOLD, NO_BOLD)));
+        assert_eq!(plain(&out), "Groceries\n\nFrom the market");
+    }
+
+    #[test]
+    fn marks_list_items() {
+        let out = render("- milk\n  1. oat\n  2. soy\n- [x] eggs\n- [ ] bread", DEFAULT_WIDTH);
+        assert_eq!(out, "• milk\n  1. oat\n  2. soy\n• [x] eggs\n• [ ] bread");
+    }
+
+    #[test]
+    fn colors_inline_code() {
+        let out = render("Run `tok sync` first", DEFAULT_WIDTH);
+        assert_eq!(out, format!("Run {} first", "tok sync".kolorize(CODE_COLOR)));
+    }
+
+    #[test]
+    fn spells_out_links() {
+        let out = render("See [the docs](https://example.com)", DEFAULT_WIDTH);
+        assert_eq!(
+            out,
+            format!("See {}the docs{} (https://example.com)", UNDERLINE, NO_UNDERLINE)
+        );
+    }
+
+    #[test]
+    fn wraps_at_the_width() {
+        let words = "Pick up the **dry cleaning** on the way home, then call `the landlord` about the leaking tap";
+        let out = render(&format!("{}\n\n- {}\n> {}", words, words, words), DEFAULT_WIDTH);
+        let lines: Vec<String> = out.lines().map(plain).collect();
+
+        assert!(
+            lines.iter().all(|line| line.chars().count() <= DEFAULT_WIDTH),
+            "{:#?}",
+            lines
+        );
+        assert_eq!(
+            lines[0],
+            "Pick up the dry cleaning on the way home, then call the landlord about the"
+        );
+        assert_eq!(lines[1], "leaking tap");
+        assert_eq!(
+            lines[3],
+            "• Pick up the dry cleaning on the way home, then call the landlord about the"
+        );
+        assert_eq!(lines[4], "  leaking tap");
+        assert_eq!(
+            lines[6],
+            "│ Pick up the dry cleaning on the way home, then call the landlord about the"
+        );
+        assert_eq!(lines[7], "│ leaking tap");
+        // Words are only ever broken between, never inside
+        assert_eq!(
+            plain(&out)
+                .split_whitespace()
+                .filter(|word| *word == "landlord")
+                .count(),
+            3
+        );
+    }
+}
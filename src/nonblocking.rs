@@ -0,0 +1,158 @@
+//! An async client for the TickTick open API, for use from inside a tokio runtime.
+//!
+//! It has the same requests as the blocking [`crate::TickTickClient`] and is configured with the
+//! same [`ClientBuilder`], finished off with [`ClientBuilder::build_async`].
+
+use jiff::Zoned;
+use reqwest::{Client, RequestBuilder};
+use serde::de::DeserializeOwned;
+use serde_json::json;
+
+use crate::client::{ClientBuilder, Host};
+use crate::data::{format_dt, Project, ProjectData, Task};
+use crate::error::TickTickError;
+
+/// An async TickTick client, build one with [`TickTickClient::builder`].
+#[derive(Debug, Clone)]
+pub struct TickTickClient {
+    http_client: Client,
+    host: Host,
+}
+
+impl TickTickClient {
+    pub(crate) fn new(http_client: Client, host: Host) -> Self {
+        Self { http_client, host }
+    }
+
+    /// The same builder the blocking client uses, finished with [`ClientBuilder::build_async`].
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::default()
+    }
+
+    /// The service this client talks to.
+    pub fn host(&self) -> &Host {
+        &self.host
+    }
+}
+
+// API requests
+impl TickTickClient {
+    /// Every project the token can see, without their tasks.
+    pub async fn get_projects(&self) -> Result<Vec<Project>, TickTickError> {
+        self.send(self.http_client.get(format!("{}/open/v1/project", self.host.api_url())))
+            .await
+    }
+
+    /// Tasks and columns for the projects named `project_name`, which is matched exactly.
+    pub async fn get_single_project_with_data(&self, project_name: &str) -> Result<Vec<ProjectData>, TickTickError> {
+        let projects = self.get_projects().await?;
+
+        let mut data = Vec::new();
+        for project in projects.iter().filter(|proj| proj.name == project_name) {
+            data.push(self.get_project_data(&project.id).await?);
+        }
+        Ok(data)
+    }
+
+    /// Tasks and columns for every project, which takes one request per project.
+    ///
+    /// The requests go one at a time like the blocking client's, since sending them all at once
+    /// is a good way to get rate limited.
+    pub async fn get_projects_with_data(&self) -> Result<Vec<ProjectData>, TickTickError> {
+        let projects = self.get_projects().await?;
+
+        let mut data = Vec::with_capacity(projects.len());
+        for project in &projects {
+            data.push(self.get_project_data(&project.id).await?);
+        }
+        Ok(data)
+    }
+
+    /// Tasks and columns for a single project.
+    pub async fn get_project_data(&self, project_id: &str) -> Result<ProjectData, TickTickError> {
+        self.send(
+            self.http_client
+                .get(format!("{}/open/v1/project/{}/data", self.host.api_url(), project_id)),
+        )
+        .await
+    }
+
+    /// Tasks in these projects that were completed between `start` and `end`.
+    pub async fn get_completed_tasks(
+        &self,
+        project_ids: &[String],
+        start: &Zoned,
+        end: &Zoned,
+    ) -> Result<Vec<Task>, TickTickError> {
+        self.send(
+            self.http_client
+                .post(format!("{}/open/v1/task/completed", self.host.api_url()))
+                .json(&json!({
+                    "projectIds": project_ids,
+                    "startDate": format_dt(start),
+                    "endDate": format_dt(end),
+                })),
+        )
+        .await
+    }
+
+    /// Marks a task as done.
+    pub async fn complete_task(&self, task: &Task) -> Result<(), TickTickError> {
+        self.send_without_response(self.http_client.post(format!(
+            "{}/open/v1/project/{}/task/{}/complete",
+            self.host.api_url(),
+            task.project_id,
+            task.id
+        )))
+        .await
+    }
+
+    /// Deletes a task for good.
+    pub async fn delete_task(&self, task: &Task) -> Result<(), TickTickError> {
+        self.send_without_response(self.http_client.delete(format!(
+            "{}/open/v1/project/{}/task/{}",
+            self.host.api_url(),
+            task.project_id,
+            task.id
+        )))
+        .await
+    }
+
+    /// Creates a task in `task.project_id`, returning it with the id TickTick gave it.
+    pub async fn create_task(&self, task: &Task) -> Result<Task, TickTickError> {
+        self.send(
+            self.http_client
+                .post(format!("{}/open/v1/task", self.host.api_url()))
+                .json(task),
+        )
+        .await
+    }
+
+    /// Saves changes to a task, returning the task as TickTick has it now.
+    pub async fn update_task(&self, task: &Task) -> Result<Task, TickTickError> {
+        self.send(
+            self.http_client
+                .post(format!("{}/open/v1/task/{}", self.host.api_url(), task.id))
+                .json(task),
+        )
+        .await
+    }
+
+    async fn send<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T, TickTickError> {
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(TickTickError::from_async_response(response).await);
+        }
+
+        let body = response.text().await?;
+        serde_json::from_str(&body).map_err(|err| TickTickError::decode(err, &body))
+    }
+
+    async fn send_without_response(&self, request: RequestBuilder) -> Result<(), TickTickError> {
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(TickTickError::from_async_response(response).await);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,315 @@
+use anyhow::{anyhow, Context, Result};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use ratatui::layout::{Constraint, Layout, Position};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{List, ListItem, ListState, Paragraph};
+use ratatui::Frame;
+use std::cell::OnceCell;
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+// Tried in this order for --picker=external
+const EXTERNAL_PICKERS: &[&str] = &["fzf", "sk"];
+const MATCH_COLOR: &str = "#F2B04B";
+
+/*
+The picker that was asked for, only worked out the first time a task actually needs choosing
+so commands that never choose don't go looking for fzf or warn that it's missing
+*/
+pub struct Picker {
+    wanted: Option<String>,
+    resolved: OnceCell<PickerKind>,
+}
+
+impl Picker {
+    pub fn new(wanted: Option<&str>) -> Self {
+        Self {
+            wanted: wanted.map(str::to_string),
+            resolved: OnceCell::new(),
+        }
+    }
+
+    pub fn kind(&self) -> &PickerKind {
+        self.resolved
+            .get_or_init(|| PickerKind::resolve(self.wanted.as_deref()))
+    }
+}
+
+// How a command asks which To-Do items to go with
+#[derive(Debug, Clone)]
+pub enum PickerKind {
+    // Type to narrow the list down, drawn by tok itself
+    Fuzzy,
+    // fzf or skim, whichever was found first
+    External(PathBuf),
+    // The numbered list and a prompt, the only thing that works without a terminal
+    Number,
+}
+
+impl PickerKind {
+    /*
+    What was asked for, as far as it's possible. Anything interactive needs both ends to be a
+    terminal, otherwise it's the numeric prompt so piping numbers in keeps working
+    */
+    fn resolve(wanted: Option<&str>) -> Self {
+        let interactive = io::stdin().is_terminal() && io::stdout().is_terminal();
+        match wanted {
+            Some("number") => Self::Number,
+            Some("external") => match EXTERNAL_PICKERS.iter().find_map(|name| find_program(name)) {
+                Some(path) => Self::External(path),
+                None => {
+                    eprintln!("Neither fzf nor sk is installed, using the built-in picker instead");
+                    Self::resolve(None)
+                }
+            },
+            _ if interactive => Self::Fuzzy,
+            _ => Self::Number,
+        }
+    }
+}
+
+fn find_program(name: &str) -> Option<PathBuf> {
+    let path = env::var_os("PATH")?;
+    env::split_paths(&path)
+        .map(|dir| dir.join(name))
+        .find(|candidate| candidate.is_file())
+}
+
+// One line to choose from, only `text` is matched against
+pub struct Candidate {
+    pub text: String,
+    pub note: String,
+}
+
+/*
+Each candidate goes in with its position in front, which fzf and skim are told to hide,
+so it comes back out with whatever was chosen
+*/
+pub fn external(program: &PathBuf, candidates: &[Candidate], multiple: bool) -> Result<Vec<usize>> {
+    let mut command = Command::new(program);
+    command.args(["--delimiter", "\t", "--with-nth", "2.."]);
+    if multiple {
+        command.arg("--multi");
+    }
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Unable to start {}", program.display()))?;
+
+    let mut input = String::new();
+    for (num, candidate) in candidates.iter().enumerate() {
+        input.push_str(&format!("{}\t{}\t{}\n", num, candidate.text, candidate.note));
+    }
+    // A picker that quits before reading everything closes the pipe early, that's fine
+    let _ = child.stdin.take().expect("stdin was piped").write_all(input.as_bytes());
+
+    let output = child
+        .wait_with_output()
+        .with_context(|| format!("Unable to read from {}", program.display()))?;
+    let mut chosen: Vec<usize> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split('\t').next()?.parse().ok())
+        .filter(|num| *num < candidates.len())
+        .collect();
+    chosen.sort();
+    chosen.dedup();
+    finished(chosen)
+}
+
+// Positions of the chosen candidates, in the order they were given
+fn finished(chosen: Vec<usize>) -> Result<Vec<usize>> {
+    match chosen.is_empty() {
+        true => Err(anyhow!("Nothing was chosen")),
+        false => Ok(chosen),
+    }
+}
+
+struct Fuzzy<'a> {
+    candidates: &'a [Candidate],
+    multiple: bool,
+    query: String,
+    // Candidates matching the query, best first, along with which of their characters matched
+    matches: Vec<(usize, Vec<usize>)>,
+    list: ListState,
+    picked: Vec<bool>,
+    matcher: SkimMatcherV2,
+}
+
+enum Outcome {
+    Going,
+    Done,
+    Cancelled,
+}
+
+pub fn fuzzy(candidates: &[Candidate], multiple: bool) -> Result<Vec<usize>> {
+    let mut state = Fuzzy {
+        candidates,
+        multiple,
+        query: String::new(),
+        matches: Vec::new(),
+        list: ListState::default(),
+        picked: vec![false; candidates.len()],
+        matcher: SkimMatcherV2::default().ignore_case(),
+    };
+    state.rematch();
+
+    let mut terminal = ratatui::try_init().context("Unable to set up the terminal")?;
+    let outcome = loop {
+        if let Err(err) = terminal.draw(|frame| state.draw(frame)) {
+            break Err(err).context("Unable to draw to the terminal");
+        }
+        match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => match state.handle_key(key) {
+                Outcome::Going => {}
+                Outcome::Done => break Ok(state.chosen()),
+                Outcome::Cancelled => break Ok(Vec::new()),
+            },
+            Ok(_) => {}
+            Err(err) => break Err(err).context("Unable to read from the terminal"),
+        }
+    };
+    ratatui::restore();
+    finished(outcome?)
+}
+
+impl Fuzzy<'_> {
+    fn rematch(&mut self) {
+        self.matches = match self.query.is_empty() {
+            true => (0..self.candidates.len()).map(|num| (num, Vec::new())).collect(),
+            false => {
+                let mut scored: Vec<(i64, usize, Vec<usize>)> = self
+                    .candidates
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(num, candidate)| {
+                        let (score, indices) = self.matcher.fuzzy_indices(&candidate.text, &self.query)?;
+                        Some((score, num, indices))
+                    })
+                    .collect();
+                scored.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+                scored.into_iter().map(|(_, num, indices)| (num, indices)).collect()
+            }
+        };
+        self.list.select((!self.matches.is_empty()).then_some(0));
+    }
+
+    fn step(&mut self, by: isize) {
+        if let Some(current) = self.list.selected() {
+            let next = current.saturating_add_signed(by).min(self.matches.len() - 1);
+            self.list.select(Some(next));
+        }
+    }
+
+    // Whatever was picked with space, or just the highlighted one when nothing was
+    fn chosen(&self) -> Vec<usize> {
+        let picked: Vec<usize> = (0..self.picked.len()).filter(|num| self.picked[*num]).collect();
+        match picked.is_empty() {
+            true => self
+                .list
+                .selected()
+                .and_then(|index| self.matches.get(index))
+                .map(|(num, _)| vec![*num])
+                .unwrap_or_default(),
+            false => picked,
+        }
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Outcome {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => return Outcome::Cancelled,
+            KeyCode::Char('c') if ctrl => return Outcome::Cancelled,
+            KeyCode::Enter => return Outcome::Done,
+            KeyCode::Up => self.step(-1),
+            KeyCode::Char('p') if ctrl => self.step(-1),
+            KeyCode::Down => self.step(1),
+            KeyCode::Char('n') if ctrl => self.step(1),
+            KeyCode::Char(' ') | KeyCode::Tab if self.multiple => {
+                if let Some((num, _)) = self.list.selected().and_then(|index| self.matches.get(index)) {
+                    self.picked[*num] = !self.picked[*num];
+                    self.step(1);
+                }
+            }
+            KeyCode::Char('u') if ctrl => {
+                self.query.clear();
+                self.rematch();
+            }
+            KeyCode::Backspace => {
+                self.query.pop();
+                self.rematch();
+            }
+            KeyCode::Char(c) if !ctrl => {
+                self.query.push(c);
+                self.rematch();
+            }
+            _ => {}
+        }
+        Outcome::Going
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [prompt, list, help] =
+            Layout::vertical([Constraint::Length(1), Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+
+        let count = format!("  {}/{}", self.matches.len(), self.candidates.len());
+        frame.render_widget(
+            Paragraph::new(Line::from(vec![
+                Span::raw("> ").bold(),
+                Span::raw(self.query.clone()),
+                Span::styled(count, Style::new().fg(Color::DarkGray)),
+            ])),
+            prompt,
+        );
+        frame.set_cursor_position(Position::new(
+            prompt.x + 2 + self.query.chars().count() as u16,
+            prompt.y,
+        ));
+
+        let highlight = Style::new()
+            .fg(Color::from_str(MATCH_COLOR).unwrap_or(Color::Reset))
+            .bold();
+        let items: Vec<ListItem> = self
+            .matches
+            .iter()
+            .map(|(num, indices)| {
+                let candidate = &self.candidates[*num];
+                let marker = match (self.multiple, self.picked[*num]) {
+                    (true, true) => "● ",
+                    (true, false) => "○ ",
+                    (false, _) => "",
+                };
+                let mut spans = vec![Span::raw(marker)];
+                spans.extend(
+                    candidate
+                        .text
+                        .chars()
+                        .enumerate()
+                        .map(|(at, c)| match indices.contains(&at) {
+                            true => Span::styled(c.to_string(), highlight),
+                            false => Span::raw(c.to_string()),
+                        }),
+                );
+                spans.push(Span::styled(
+                    format!("  {}", candidate.note),
+                    Style::new().fg(Color::DarkGray),
+                ));
+                ListItem::new(Line::from(spans))
+            })
+            .collect();
+        let items = List::new(items).highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(items, list, &mut self.list);
+
+        let keys = match self.multiple {
+            true => "type to filter  ↑↓ move  space pick  enter confirm  esc cancel",
+            false => "type to filter  ↑↓ move  enter confirm  esc cancel",
+        };
+        frame.render_widget(Paragraph::new(keys).fg(Color::DarkGray), help);
+    }
+}
@@ -0,0 +1,563 @@
+//! Repeat rules, the part of RFC 5545 RRULEs that TickTick puts in
+//! [`Task::repeat_flag`](crate::data::Task::repeat_flag).
+//!
+//! ```
+//! use tok::recurrence::Recurrence;
+//!
+//! let rule: Recurrence = "RRULE:FREQ=WEEKLY;INTERVAL=2;BYDAY=MO".parse().unwrap();
+//! assert_eq!(rule.to_string(), "every 2 weeks on Mon");
+//! ```
+
+use anyhow::{anyhow, Context, Error, Result};
+use jiff::civil::{Date, DateTime, Weekday};
+use jiff::{Timestamp, ToSpan, Zoned};
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+// A rule that hasn't matched anything in this many periods never will
+const MAX_EMPTY_PERIODS: u32 = 1000;
+
+/// How often the rule comes around, before `interval` is taken into account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// A BYDAY entry, `nth` is set for things like the 2nd Monday (`2MO`) or the last Friday (`-1FR`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByDay {
+    pub nth: Option<i8>,
+    pub weekday: Weekday,
+}
+
+/// When the rule stops, UNTIL with a Z is an instant and anything else is in the task's own time zone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Until {
+    Utc(Timestamp),
+    Floating(DateTime),
+}
+
+/// A parsed repeat rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recurrence {
+    pub frequency: Frequency,
+    pub interval: i32,
+    pub by_day: Vec<ByDay>,
+    /// Days of the month, negative ones count back from the end so -1 is the last day.
+    pub by_month_day: Vec<i8>,
+    pub count: Option<u32>,
+    pub until: Option<Until>,
+}
+
+impl FromStr for Recurrence {
+    type Err = Error;
+
+    fn from_str(rule: &str) -> Result<Self> {
+        let body = rule.trim();
+        let body = body.strip_prefix("RRULE:").unwrap_or(body);
+
+        let mut frequency = None;
+        let mut recurrence = Recurrence {
+            frequency: Frequency::Daily,
+            interval: 1,
+            by_day: Vec::new(),
+            by_month_day: Vec::new(),
+            count: None,
+            until: None,
+        };
+
+        for part in body.split(';').filter(|part| !part.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or(anyhow!("Malformed repeat rule part '{}'", part))?;
+            match key {
+                "FREQ" => {
+                    frequency = Some(match value {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        _ => return Err(anyhow!("Unsupported repeat frequency '{}'", value)),
+                    })
+                }
+                "INTERVAL" => {
+                    recurrence.interval = value
+                        .parse()
+                        .ok()
+                        .filter(|interval| *interval > 0)
+                        .ok_or(anyhow!("Invalid repeat interval '{}'", value))?
+                }
+                "BYDAY" => {
+                    recurrence.by_day = value.split(',').map(parse_by_day).collect::<Result<_>>()?;
+                }
+                "BYMONTHDAY" => {
+                    recurrence.by_month_day = value
+                        .split(',')
+                        .map(|day| {
+                            day.parse()
+                                .ok()
+                                .filter(|day: &i8| (1..=31).contains(&day.abs()))
+                                .ok_or(anyhow!("Invalid day of the month '{}'", day))
+                        })
+                        .collect::<Result<_>>()?;
+                }
+                "COUNT" => {
+                    recurrence.count = Some(
+                        value
+                            .parse()
+                            .with_context(|| format!("Invalid repeat count '{}'", value))?,
+                    )
+                }
+                "UNTIL" => recurrence.until = Some(parse_until(value)?),
+                // The week always starts on Monday here, and TT_ keys are TickTick's own (e.g. skipping holidays)
+                "WKST" => {}
+                key if key.starts_with("TT_") => {}
+                _ => return Err(anyhow!("Unsupported repeat rule part '{}'", key)),
+            }
+        }
+
+        recurrence.frequency = frequency.ok_or(anyhow!("Repeat rule '{}' has no FREQ", rule))?;
+        Ok(recurrence)
+    }
+}
+
+fn parse_by_day(value: &str) -> Result<ByDay> {
+    let invalid = || anyhow!("Invalid day '{}'", value);
+    let split = value.len().checked_sub(2).ok_or_else(invalid)?;
+    let (nth, day) = value.split_at_checked(split).ok_or_else(invalid)?;
+
+    let weekday = match day {
+        "MO" => Weekday::Monday,
+        "TU" => Weekday::Tuesday,
+        "WE" => Weekday::Wednesday,
+        "TH" => Weekday::Thursday,
+        "FR" => Weekday::Friday,
+        "SA" => Weekday::Saturday,
+        "SU" => Weekday::Sunday,
+        _ => return Err(invalid()),
+    };
+    let nth = match nth.trim_start_matches('+') {
+        "" => None,
+        nth => Some(
+            nth.parse()
+                .ok()
+                .filter(|nth: &i8| (1..=5).contains(&nth.abs()))
+                .ok_or_else(invalid)?,
+        ),
+    };
+    Ok(ByDay { nth, weekday })
+}
+
+fn parse_until(value: &str) -> Result<Until> {
+    let invalid = |_| anyhow!("Invalid repeat end '{}'", value);
+    match value.strip_suffix('Z') {
+        Some(utc) => {
+            let datetime = DateTime::strptime("%Y%m%dT%H%M%S", utc).map_err(invalid)?;
+            Ok(Until::Utc(
+                datetime.to_zoned(jiff::tz::TimeZone::UTC).map_err(invalid)?.timestamp(),
+            ))
+        }
+        None if value.contains('T') => Ok(Until::Floating(
+            DateTime::strptime("%Y%m%dT%H%M%S", value).map_err(invalid)?,
+        )),
+        // A bare date includes the whole day
+        None => Ok(Until::Floating(
+            Date::strptime("%Y%m%d", value).map_err(invalid)?.at(23, 59, 59, 0),
+        )),
+    }
+}
+
+impl Recurrence {
+    /// Every time the rule comes around at or after `start`, keeping `start`'s time of day and time zone.
+    ///
+    /// `start` is usually the task's start date, and `count` counts from there.
+    pub fn occurrences(&self, start: &Zoned) -> Occurrences<'_> {
+        Occurrences {
+            rule: self,
+            start: start.clone(),
+            period: 0,
+            empty_periods: 0,
+            pending: VecDeque::new(),
+            emitted: 0,
+            done: false,
+        }
+    }
+
+    fn matches_day(&self, date: Date) -> bool {
+        let weekday = self.by_day.is_empty() || self.by_day.iter().any(|day| day.weekday == date.weekday());
+        let month_day = self.by_month_day.is_empty()
+            || self
+                .by_month_day
+                .iter()
+                .any(|day| resolve_month_day(date, *day) == Some(date));
+        weekday && month_day
+    }
+
+    // The days a rule picks out of one month, `default_day` is used when it doesn't pick any itself
+    fn days_in(&self, first: Date, default_day: i8) -> Vec<Date> {
+        let mut days: Vec<Date> = match (self.by_month_day.is_empty(), self.by_day.is_empty()) {
+            (true, true) => Date::new(first.year(), first.month(), default_day)
+                .ok()
+                .into_iter()
+                .collect(),
+            (false, _) => self
+                .by_month_day
+                .iter()
+                .filter_map(|day| resolve_month_day(first, *day))
+                .filter(|date| self.matches_day(*date))
+                .collect(),
+            (true, false) => self
+                .by_day
+                .iter()
+                .flat_map(|day| match day.nth {
+                    Some(nth) => first.nth_weekday_of_month(nth, day.weekday).ok().into_iter().collect(),
+                    None => (0..first.days_in_month())
+                        .filter_map(|offset| first.checked_add(offset.days()).ok())
+                        .filter(|date| date.weekday() == day.weekday)
+                        .collect::<Vec<_>>(),
+                })
+                .collect(),
+        };
+        days.sort();
+        days.dedup();
+        days
+    }
+
+    // Every day in the `period`th stretch of the rule after `start`, some of which can be before `start`
+    fn days_for_period(&self, start: Date, period: i64) -> Option<Vec<Date>> {
+        let step = i64::from(self.interval) * period;
+        let days = match self.frequency {
+            Frequency::Daily => {
+                let day = start.checked_add(step.days()).ok()?;
+                self.matches_day(day).then_some(day).into_iter().collect()
+            }
+            Frequency::Weekly => {
+                let monday = start
+                    .checked_sub(i64::from(start.weekday().to_monday_zero_offset()).days())
+                    .ok()?;
+                let monday = monday.checked_add((step * 7).days()).ok()?;
+                let mut offsets: Vec<i8> = match self.by_day.is_empty() {
+                    true => vec![start.weekday().to_monday_zero_offset()],
+                    false => self
+                        .by_day
+                        .iter()
+                        .map(|day| day.weekday.to_monday_zero_offset())
+                        .collect(),
+                };
+                offsets.sort();
+                offsets.dedup();
+                offsets
+                    .into_iter()
+                    .filter_map(|offset| monday.checked_add(i64::from(offset).days()).ok())
+                    .collect()
+            }
+            Frequency::Monthly => {
+                let first = start.first_of_month().checked_add(step.months()).ok()?;
+                self.days_in(first, start.day())
+            }
+            Frequency::Yearly => {
+                let first = start.first_of_month().checked_add(step.years()).ok()?;
+                self.days_in(first, start.day())
+            }
+        };
+        Some(days)
+    }
+
+    fn ended_by(&self, occurrence: &Zoned) -> bool {
+        match self.until {
+            Some(Until::Utc(until)) => occurrence.timestamp() > until,
+            Some(Until::Floating(until)) => occurrence.datetime() > until,
+            None => false,
+        }
+    }
+}
+
+fn resolve_month_day(date: Date, day: i8) -> Option<Date> {
+    let day = match day {
+        day if day < 0 => date.days_in_month() + day + 1,
+        day => day,
+    };
+    Date::new(date.year(), date.month(), day).ok()
+}
+
+/// The occurrences of a [`Recurrence`], in order. Made by [`Recurrence::occurrences`].
+pub struct Occurrences<'a> {
+    rule: &'a Recurrence,
+    start: Zoned,
+    period: i64,
+    empty_periods: u32,
+    pending: VecDeque<Zoned>,
+    emitted: u32,
+    done: bool,
+}
+
+impl Iterator for Occurrences<'_> {
+    type Item = Zoned;
+
+    fn next(&mut self) -> Option<Zoned> {
+        loop {
+            if let Some(occurrence) = self.pending.pop_front() {
+                if self.rule.ended_by(&occurrence) || self.rule.count.is_some_and(|count| self.emitted >= count) {
+                    self.done = true;
+                    self.pending.clear();
+                    return None;
+                }
+                self.emitted += 1;
+                return Some(occurrence);
+            }
+
+            if self.done || self.empty_periods >= MAX_EMPTY_PERIODS {
+                return None;
+            }
+
+            let Some(days) = self.rule.days_for_period(self.start.date(), self.period) else {
+                // Ran off the end of the calendar
+                self.done = true;
+                return None;
+            };
+            self.period += 1;
+
+            let time = self.start.time();
+            self.pending.extend(
+                days.into_iter()
+                    .filter_map(|day| {
+                        DateTime::from_parts(day, time)
+                            .to_zoned(self.start.time_zone().clone())
+                            .ok()
+                    })
+                    .filter(|occurrence| *occurrence >= self.start),
+            );
+            match self.pending.is_empty() {
+                true => self.empty_periods += 1,
+                false => self.empty_periods = 0,
+            }
+        }
+    }
+}
+
+// Reads the way you'd say it, e.g. "every 2 weeks on Mon, Wed" or "every month on the last Fri"
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unit = match self.frequency {
+            Frequency::Daily => "day",
+            Frequency::Weekly => "week",
+            Frequency::Monthly => "month",
+            Frequency::Yearly => "year",
+        };
+        let weekdays_only = self.by_day.len() == 5
+            && self.by_day.iter().all(|day| day.nth.is_none())
+            && self.by_day.iter().all(|day| day.weekday.to_monday_zero_offset() < 5);
+
+        match self.interval {
+            1 if weekdays_only && matches!(self.frequency, Frequency::Daily | Frequency::Weekly) => {
+                write!(f, "every weekday")?
+            }
+            1 => write!(f, "every {}", unit)?,
+            interval => write!(f, "every {} {}s", interval, unit)?,
+        }
+
+        let mut on = Vec::new();
+        on.extend(self.by_month_day.iter().map(|day| match day {
+            -1 => "the last day".to_string(),
+            day if *day < 0 => format!("the {} to last day", ordinal(-day)),
+            day => format!("the {}", ordinal(*day)),
+        }));
+        if !weekdays_only || self.interval != 1 {
+            on.extend(self.by_day.iter().map(|day| match day.nth {
+                Some(-1) => format!("the last {}", short_name(day.weekday)),
+                Some(nth) if nth < 0 => format!("the {} to last {}", ordinal(-nth), short_name(day.weekday)),
+                Some(nth) => format!("the {} {}", ordinal(nth), short_name(day.weekday)),
+                None => short_name(day.weekday).to_string(),
+            }));
+        }
+        if !on.is_empty() {
+            write!(f, " on {}", on.join(", "))?;
+        }
+
+        if let Some(count) = self.count {
+            write!(f, ", {} times", count)?;
+        }
+        match self.until {
+            Some(Until::Utc(until)) => write!(f, ", until {}", until.strftime("%Y-%m-%d"))?,
+            Some(Until::Floating(until)) => write!(f, ", until {}", until.strftime("%Y-%m-%d"))?,
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+fn short_name(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Monday => "Mon",
+        Weekday::Tuesday => "Tue",
+        Weekday::Wednesday => "Wed",
+        Weekday::Thursday => "Thu",
+        Weekday::Friday => "Fri",
+        Weekday::Saturday => "Sat",
+        Weekday::Sunday => "Sun",
+    }
+}
+
+fn ordinal(num: i8) -> String {
+    let suffix = match (num % 10, num % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", num, suffix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(rule: &str) -> Recurrence {
+        rule.parse().unwrap_or_else(|err| panic!("{}: {}", rule, err))
+    }
+
+    fn next(rule_str: &str, start: &str, take: usize) -> Vec<String> {
+        let start: Zoned = start.parse().unwrap();
+        rule(rule_str)
+            .occurrences(&start)
+            .take(take)
+            .map(|occurrence| occurrence.strftime("%Y-%m-%d %a %H:%M").to_string())
+            .collect()
+    }
+
+    #[test]
+    fn parses_the_parts_ticktick_uses() {
+        let parsed = rule("RRULE:FREQ=MONTHLY;INTERVAL=3;BYDAY=2MO,-1FR;COUNT=4;WKST=SU;TT_SKIP=HOLIDAY");
+        assert_eq!(parsed.frequency, Frequency::Monthly);
+        assert_eq!(parsed.interval, 3);
+        assert_eq!(
+            parsed.by_day,
+            [
+                ByDay {
+                    nth: Some(2),
+                    weekday: Weekday::Monday
+                },
+                ByDay {
+                    nth: Some(-1),
+                    weekday: Weekday::Friday
+                },
+            ]
+        );
+        assert_eq!(parsed.count, Some(4));
+
+        assert_eq!(rule("FREQ=DAILY").interval, 1);
+        assert_eq!(rule("RRULE:FREQ=MONTHLY;BYMONTHDAY=1,-1").by_month_day, [1, -1]);
+        assert_eq!(
+            rule("RRULE:FREQ=DAILY;UNTIL=20240401T000000Z").until,
+            Some(Until::Utc("2024-04-01T00:00:00Z".parse().unwrap()))
+        );
+        assert_eq!(
+            rule("RRULE:FREQ=DAILY;UNTIL=20240401").until,
+            Some(Until::Floating("2024-04-01T23:59:59".parse().unwrap()))
+        );
+    }
+
+    #[test]
+    fn rejects_rules_it_cannot_follow() {
+        for bad in [
+            "RRULE:INTERVAL=2",
+            "RRULE:FREQ=HOURLY",
+            "RRULE:FREQ=DAILY;INTERVAL=0",
+            "RRULE:FREQ=WEEKLY;BYDAY=XX",
+            "RRULE:FREQ=WEEKLY;BYDAY=9MO",
+            "RRULE:FREQ=MONTHLY;BYMONTHDAY=32",
+            "RRULE:FREQ=DAILY;UNTIL=tomorrow",
+            "RRULE:FREQ=YEARLY;BYMONTH=3",
+            "ERULE:NAME=CUSTOM;BYDATE=20240318",
+        ] {
+            assert!(bad.parse::<Recurrence>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn describes_rules() {
+        let cases = [
+            ("RRULE:FREQ=DAILY;INTERVAL=1", "every day"),
+            ("RRULE:FREQ=DAILY;INTERVAL=14", "every 14 days"),
+            ("RRULE:FREQ=WEEKLY;INTERVAL=2;BYDAY=MO", "every 2 weeks on Mon"),
+            ("RRULE:FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR", "every weekday"),
+            ("RRULE:FREQ=WEEKLY;BYDAY=SA,SU", "every week on Sat, Sun"),
+            ("RRULE:FREQ=MONTHLY;BYMONTHDAY=-1", "every month on the last day"),
+            ("RRULE:FREQ=MONTHLY;BYMONTHDAY=1,22", "every month on the 1st, the 22nd"),
+            ("RRULE:FREQ=MONTHLY;BYDAY=2MO", "every month on the 2nd Mon"),
+            ("RRULE:FREQ=MONTHLY;BYDAY=-1FR", "every month on the last Fri"),
+            ("RRULE:FREQ=YEARLY;COUNT=3", "every year, 3 times"),
+            ("RRULE:FREQ=DAILY;UNTIL=20240401T000000Z", "every day, until 2024-04-01"),
+        ];
+        for (rule_str, expected) in cases {
+            assert_eq!(rule(rule_str).to_string(), expected, "{}", rule_str);
+        }
+    }
+
+    #[test]
+    fn follows_daily_and_weekly_rules() {
+        let start = "2024-03-18T09:00[UTC]";
+        assert_eq!(
+            next("RRULE:FREQ=DAILY;INTERVAL=14", start, 3),
+            ["2024-03-18 Mon 09:00", "2024-04-01 Mon 09:00", "2024-04-15 Mon 09:00"]
+        );
+        assert_eq!(
+            next("RRULE:FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR", "2024-03-22T09:00[UTC]", 3),
+            ["2024-03-22 Fri 09:00", "2024-03-25 Mon 09:00", "2024-03-26 Tue 09:00"]
+        );
+        // Monday of the start week is skipped because it's before the start
+        assert_eq!(
+            next("RRULE:FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,TH", "2024-03-19T09:00[UTC]", 3),
+            ["2024-03-21 Thu 09:00", "2024-04-01 Mon 09:00", "2024-04-04 Thu 09:00"]
+        );
+    }
+
+    #[test]
+    fn follows_monthly_and_yearly_rules() {
+        assert_eq!(
+            next("RRULE:FREQ=MONTHLY;BYMONTHDAY=-1", "2024-01-31T07:00[UTC]", 3),
+            ["2024-01-31 Wed 07:00", "2024-02-29 Thu 07:00", "2024-03-31 Sun 07:00"]
+        );
+        // Months without a 31st are skipped, same as TickTick
+        assert_eq!(
+            next("RRULE:FREQ=MONTHLY", "2024-01-31T07:00[UTC]", 3),
+            ["2024-01-31 Wed 07:00", "2024-03-31 Sun 07:00", "2024-05-31 Fri 07:00"]
+        );
+        assert_eq!(
+            next("RRULE:FREQ=MONTHLY;BYDAY=2MO", "2024-03-01T07:00[UTC]", 2),
+            ["2024-03-11 Mon 07:00", "2024-04-08 Mon 07:00"]
+        );
+        assert_eq!(
+            next("RRULE:FREQ=YEARLY", "2024-02-29T07:00[UTC]", 2),
+            ["2024-02-29 Thu 07:00", "2028-02-29 Tue 07:00"]
+        );
+    }
+
+    #[test]
+    fn stops_at_count_and_until() {
+        assert_eq!(next("RRULE:FREQ=DAILY;COUNT=2", "2024-03-18T09:00[UTC]", 5).len(), 2);
+        assert_eq!(
+            next("RRULE:FREQ=WEEKLY;UNTIL=20240401", "2024-03-18T09:00[UTC]", 5),
+            ["2024-03-18 Mon 09:00", "2024-03-25 Mon 09:00", "2024-04-01 Mon 09:00"]
+        );
+        assert_eq!(
+            next("RRULE:FREQ=WEEKLY;UNTIL=20240401T080000Z", "2024-03-18T09:00[UTC]", 5).len(),
+            2
+        );
+        // Never matches, but doesn't spin forever either
+        assert!(next("RRULE:FREQ=YEARLY;INTERVAL=4;BYMONTHDAY=30", "2024-02-01T09:00[UTC]", 1).is_empty());
+    }
+
+    #[test]
+    fn keeps_the_local_time_across_dst() {
+        assert_eq!(
+            next("RRULE:FREQ=WEEKLY", "2024-03-04T09:00[America/New_York]", 2),
+            ["2024-03-04 Mon 09:00", "2024-03-11 Mon 09:00"]
+        );
+    }
+}
@@ -0,0 +1,368 @@
+//! Reminders, the iCalendar TRIGGER strings TickTick keeps in
+//! [`Task::reminders`](crate::data::Task::reminders).
+//!
+//! A trigger is an offset from the task's due date, `TRIGGER:-PT30M` is half an hour before it.
+//! All day tasks are due at midnight, so TickTick's `TRIGGER:-P0DT15H0M0S` is the day before at 9am.
+//!
+//! ```
+//! use jiff::SignedDuration;
+//! use tok::reminder::Reminder;
+//!
+//! let reminder: Reminder = "TRIGGER:-PT30M".parse().unwrap();
+//! assert_eq!(reminder.offset, SignedDuration::from_mins(-30));
+//! assert_eq!(reminder.to_string(), "TRIGGER:-PT30M");
+//! ```
+
+use anyhow::{anyhow, Error, Result};
+use jiff::civil::Time;
+use jiff::{SignedDuration, ToSpan, Zoned};
+use std::fmt;
+use std::str::FromStr;
+
+const MINUTE: i64 = 60;
+const HOUR: i64 = 60 * MINUTE;
+const DAY: i64 = 24 * HOUR;
+const WEEK: i64 = 7 * DAY;
+
+/// When to be reminded, relative to the due date. Negative offsets are before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reminder {
+    pub offset: SignedDuration,
+    /// Written in the `PnDTnHnMnS` form TickTick uses for all day tasks, even without any whole days.
+    pub all_day: bool,
+}
+
+impl FromStr for Reminder {
+    type Err = Error;
+
+    fn from_str(trigger: &str) -> Result<Self> {
+        let invalid = || anyhow!("Unrecognized reminder '{}'", trigger);
+        let duration = trigger.trim().strip_prefix("TRIGGER:").ok_or_else(invalid)?;
+
+        let (sign, duration) = match duration.as_bytes().first() {
+            Some(b'-') => (-1, &duration[1..]),
+            Some(b'+') => (1, &duration[1..]),
+            _ => (1, duration),
+        };
+        let duration = duration.strip_prefix('P').ok_or_else(invalid)?;
+        let (date, time) = duration.split_once('T').unwrap_or((duration, ""));
+        if date.is_empty() && time.is_empty() {
+            return Err(invalid());
+        }
+
+        let secs = sum_units(date, &[('W', WEEK), ('D', DAY)]).ok_or_else(invalid)?
+            + sum_units(time, &[('H', HOUR), ('M', MINUTE), ('S', 1)]).ok_or_else(invalid)?;
+        Ok(Self {
+            offset: SignedDuration::from_secs(sign * secs),
+            all_day: date.contains('D'),
+        })
+    }
+}
+
+// Adds up something like 1H30M, units have to come in the order given and each at most once
+fn sum_units(text: &str, units: &[(char, i64)]) -> Option<i64> {
+    let mut rest = text;
+    let mut total: i64 = 0;
+    for (unit, secs) in units {
+        if let Some((amount, after)) = rest.split_once(*unit) {
+            let amount: i64 = amount.parse().ok()?;
+            total = total.checked_add(amount.checked_mul(*secs)?)?;
+            rest = after;
+        }
+    }
+    rest.is_empty().then_some(total)
+}
+
+// Written the way TickTick writes them, days only show up when there are some or it's for an all day task
+impl fmt::Display for Reminder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.offset.as_secs();
+        let sign = if secs < 0 { "-" } else { "" };
+        let secs = secs.abs();
+        let (days, hours, mins, secs) = (secs / DAY, secs % DAY / HOUR, secs % HOUR / MINUTE, secs % MINUTE);
+
+        if days > 0 || self.all_day {
+            return write!(f, "TRIGGER:{}P{}DT{}H{}M{}S", sign, days, hours, mins, secs);
+        }
+        write!(f, "TRIGGER:{}PT", sign)?;
+        if hours > 0 {
+            write!(f, "{}H", hours)?;
+        }
+        if mins > 0 {
+            write!(f, "{}M", mins)?;
+        }
+        if secs > 0 || (hours == 0 && mins == 0) {
+            write!(f, "{}S", secs)?;
+        }
+        Ok(())
+    }
+}
+
+impl Reminder {
+    /// When the reminder goes off for a task due at `due`.
+    pub fn at(&self, due: &Zoned) -> Option<Zoned> {
+        due.checked_add(self.offset).ok()
+    }
+
+    /// Reads the way people say it, relative to `due`:
+    /// `at due`, `30m before`, `2 hours after`, `1d before at 9am` or `on the day at 9:30am`.
+    pub fn from_phrase(phrase: &str, due: &Zoned) -> Result<Self> {
+        let invalid = || {
+            anyhow!(
+                "Unrecognized reminder '{}', try something like '30m before' or '1d before at 9am'",
+                phrase
+            )
+        };
+        let phrase = phrase.trim().to_lowercase();
+        let words: Vec<&str> = phrase.split_whitespace().collect();
+
+        let (amount, direction, at) = match words.as_slice() {
+            ["at", "due"] | ["on", "time"] => return Ok(Self::default()),
+            ["on", "the", "day", "at", time @ ..] => (0, -1, Some(time.concat())),
+            _ => {
+                let split = words
+                    .iter()
+                    .position(|word| *word == "before" || *word == "after")
+                    .ok_or_else(invalid)?;
+                let direction = if words[split] == "before" { -1 } else { 1 };
+                let amount = parse_amount(&words[..split].concat()).ok_or_else(invalid)?;
+                let at = match &words[split + 1..] {
+                    [] => None,
+                    ["at", time @ ..] => Some(time.concat()),
+                    _ => return Err(invalid()),
+                };
+                (amount, direction, at)
+            }
+        };
+
+        let Some(at) = at else {
+            return Ok(Self {
+                offset: SignedDuration::from_secs(direction * amount),
+                ..Self::default()
+            });
+        };
+
+        // A time of day only makes sense a whole number of days away
+        if amount % DAY != 0 {
+            return Err(anyhow!("'{}' can only be used with days or weeks", phrase));
+        }
+        let time = parse_time(&at).ok_or_else(invalid)?;
+        let day = due
+            .date()
+            .checked_add((direction * amount / DAY).days())
+            .map_err(|_| invalid())?;
+        let target = day
+            .to_datetime(time)
+            .to_zoned(due.time_zone().clone())
+            .map_err(|_| invalid())?;
+        Ok(Self {
+            offset: due.duration_until(&target),
+            ..Self::default()
+        })
+    }
+
+    /// A short description like "30 minutes before", or "1 day before at 09:00 AM" for all day tasks.
+    pub fn describe(&self, due: &Zoned, all_day: bool) -> String {
+        match (all_day, self.at(due)) {
+            (true, Some(at)) => {
+                let days = at.date().until(due.date()).map_or(0, |span| span.get_days());
+                let time = at.strftime("%I:%M %p");
+                match days {
+                    0 => format!("on the day at {}", time),
+                    days if days > 0 => format!("{} before at {}", plural(days.into(), "day"), time),
+                    days => format!("{} after at {}", plural((-days).into(), "day"), time),
+                }
+            }
+            _ => {
+                let secs = self.offset.as_secs();
+                if secs == 0 {
+                    return "at the due time".to_string();
+                }
+
+                let abs = secs.abs();
+                let parts: Vec<String> = [
+                    (abs / DAY, "day"),
+                    (abs % DAY / HOUR, "hour"),
+                    (abs % HOUR / MINUTE, "minute"),
+                ]
+                .into_iter()
+                .filter(|(amount, _)| *amount > 0)
+                .map(|(amount, unit)| plural(amount, unit))
+                .collect();
+                let parts = match parts.is_empty() {
+                    true => plural(abs, "second"),
+                    false => parts.join(" "),
+                };
+                format!("{} {}", parts, if secs < 0 { "before" } else { "after" })
+            }
+        }
+    }
+}
+
+// At the due time
+impl Default for Reminder {
+    fn default() -> Self {
+        Self {
+            offset: SignedDuration::ZERO,
+            all_day: false,
+        }
+    }
+}
+
+fn plural(amount: i64, unit: &str) -> String {
+    match amount {
+        1 => format!("1 {}", unit),
+        amount => format!("{} {}s", amount, unit),
+    }
+}
+
+// Something like 30m, 2h, 1d, 1w, or the same with the unit spelled out
+fn parse_amount(text: &str) -> Option<i64> {
+    let digits = text.find(|c: char| !c.is_ascii_digit())?;
+    let (amount, unit) = text.split_at(digits);
+    let amount: i64 = amount.parse().ok()?;
+    let secs = match unit {
+        "m" | "min" | "mins" | "minute" | "minutes" => MINUTE,
+        "h" | "hr" | "hrs" | "hour" | "hours" => HOUR,
+        "d" | "day" | "days" => DAY,
+        "w" | "week" | "weeks" => WEEK,
+        _ => return None,
+    };
+    amount.checked_mul(secs)
+}
+
+// 9am, 9:30pm, 21:00
+fn parse_time(text: &str) -> Option<Time> {
+    let (clock, offset) = match (text.strip_suffix("am"), text.strip_suffix("pm")) {
+        (Some(clock), _) => (clock, Some(0)),
+        (_, Some(clock)) => (clock, Some(12)),
+        _ => (text, None),
+    };
+    let (hour, minute) = clock.split_once(':').unwrap_or((clock, "0"));
+    let (hour, minute): (i8, i8) = (hour.parse().ok()?, minute.parse().ok()?);
+
+    let hour = match offset {
+        Some(offset) if (1..=12).contains(&hour) => hour % 12 + offset,
+        Some(_) => return None,
+        None => hour,
+    };
+    Time::new(hour, minute, 0, 0).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn due() -> Zoned {
+        "2024-03-18T16:30[UTC]".parse().unwrap()
+    }
+
+    fn midnight() -> Zoned {
+        "2024-03-18T00:00[UTC]".parse().unwrap()
+    }
+
+    #[test]
+    fn reads_ticktick_triggers() {
+        let cases = [
+            ("TRIGGER:PT0S", 0),
+            ("TRIGGER:-PT15M", -15 * MINUTE),
+            ("TRIGGER:-PT1H30M", -(HOUR + 30 * MINUTE)),
+            ("TRIGGER:P0DT9H0M0S", 9 * HOUR),
+            ("TRIGGER:-P1DT15H0M0S", -(DAY + 15 * HOUR)),
+            ("TRIGGER:-P1W", -WEEK),
+        ];
+        for (trigger, secs) in cases {
+            let reminder: Reminder = trigger.parse().unwrap();
+            assert_eq!(reminder.offset.as_secs(), secs, "{}", trigger);
+        }
+
+        for bad in [
+            "",
+            "TRIGGER:",
+            "TRIGGER:P",
+            "TRIGGER:-PT30X",
+            "TRIGGER:PT5M5H",
+            "-PT30M",
+        ] {
+            assert!(bad.parse::<Reminder>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn writes_triggers_back_the_ticktick_way() {
+        for trigger in [
+            "TRIGGER:PT0S",
+            "TRIGGER:-PT15M",
+            "TRIGGER:-PT1H30M",
+            "TRIGGER:-P1DT15H0M0S",
+        ] {
+            assert_eq!(trigger.parse::<Reminder>().unwrap().to_string(), trigger);
+        }
+    }
+
+    #[test]
+    fn keeps_the_all_day_form() {
+        for trigger in ["TRIGGER:P0DT9H0M0S", "TRIGGER:-P0DT15H0M0S", "TRIGGER:P0DT0H0M0S"] {
+            let reminder: Reminder = trigger.parse().unwrap();
+            assert!(reminder.all_day, "{}", trigger);
+            assert_eq!(reminder.to_string(), trigger);
+        }
+
+        let timed: Reminder = "TRIGGER:PT9H".parse().unwrap();
+        assert!(!timed.all_day);
+        let all_day = Reminder { all_day: true, ..timed };
+        assert_eq!(all_day.to_string(), "TRIGGER:P0DT9H0M0S");
+        assert_eq!(all_day.offset, timed.offset);
+    }
+
+    #[test]
+    fn reads_phrases() {
+        let cases = [
+            ("at due", 0),
+            ("30m before", -30 * MINUTE),
+            ("30 minutes before", -30 * MINUTE),
+            ("2 hours after", 2 * HOUR),
+            ("1w before", -WEEK),
+            // Timed tasks count from the due time, so this is 1 day 7:30 before 16:30
+            ("1d before at 9am", -(DAY + 7 * HOUR + 30 * MINUTE)),
+            ("on the day at 9:30 pm", 5 * HOUR),
+        ];
+        for (phrase, secs) in cases {
+            let reminder = Reminder::from_phrase(phrase, &due()).unwrap();
+            assert_eq!(reminder.offset.as_secs(), secs, "{}", phrase);
+        }
+
+        let all_day = Reminder::from_phrase("1d before at 9am", &midnight()).unwrap();
+        assert_eq!(all_day.offset.as_secs(), -15 * HOUR);
+
+        for bad in [
+            "soon",
+            "30 before",
+            "30m",
+            "2h before at 9am",
+            "1d before at 13pm",
+            "1d before 9am",
+        ] {
+            assert!(Reminder::from_phrase(bad, &due()).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn describes_reminders() {
+        let describe =
+            |trigger: &str, due: &Zoned, all_day| trigger.parse::<Reminder>().unwrap().describe(due, all_day);
+
+        assert_eq!(describe("TRIGGER:PT0S", &due(), false), "at the due time");
+        assert_eq!(describe("TRIGGER:-PT15M", &due(), false), "15 minutes before");
+        assert_eq!(describe("TRIGGER:-P1DT1H0M0S", &due(), false), "1 day 1 hour before");
+        assert_eq!(describe("TRIGGER:PT2H", &due(), false), "2 hours after");
+        assert_eq!(
+            describe("TRIGGER:P0DT9H0M0S", &midnight(), true),
+            "on the day at 09:00 AM"
+        );
+        assert_eq!(
+            describe("TRIGGER:-P1DT15H0M0S", &midnight(), true),
+            "2 days before at 09:00 AM"
+        );
+    }
+}
@@ -0,0 +1,257 @@
+use anyhow::{anyhow, Context, Result};
+use jiff::Timestamp;
+use platform_dirs::AppDirs;
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::fs::{self, OpenOptions};
+use std::io::{BufReader, ErrorKind};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use tok::auth::redirect::{parse_pasted_redirect, AuthRedirect, RedirectListener};
+use tok::auth::{self, generate_code_verifier, generate_state_token, AccessToken};
+use tok::store::TokenStore;
+use tok::{TickTickClient, TickTickError};
+
+use crate::config::{ActiveProfile, DEFAULT_PROFILE};
+
+const DEFAULT_LOGIN_TIMEOUT: u64 = 300;
+
+#[derive(Debug, Clone)]
+pub struct LoginOptions {
+    // Print the authorization url instead of opening it, and accept the redirect pasted into the terminal
+    pub no_browser: bool,
+    // How long to wait on the redirect before giving up
+    pub timeout: Duration,
+    // Send a PKCE challenge along with the authorization request
+    pub pkce: bool,
+}
+
+impl Default for LoginOptions {
+    fn default() -> Self {
+        Self {
+            no_browser: false,
+            timeout: Duration::from_secs(DEFAULT_LOGIN_TIMEOUT),
+            pkce: false,
+        }
+    }
+}
+
+/*
+A client for the profile that only logs in once a command actually talks to TickTick.
+When TickTick stops accepting the token, the user is logged in again and the request
+is retried once with the new token
+*/
+pub struct Session<'a> {
+    profile: &'a ActiveProfile,
+    client: RefCell<Option<TickTickClient>>,
+}
+
+impl<'a> Session<'a> {
+    pub fn new(profile: &'a ActiveProfile) -> Self {
+        Self {
+            profile,
+            client: RefCell::new(None),
+        }
+    }
+
+    pub fn call<T>(&self, request: impl Fn(&TickTickClient) -> Result<T, TickTickError>) -> Result<T> {
+        let client = self.client()?;
+        match request(&client) {
+            Err(TickTickError::Unauthorized) => {
+                eprintln!("TickTick rejected the stored token, logging in again");
+                let options = LoginOptions {
+                    pkce: self.profile.profile.pkce,
+                    ..LoginOptions::default()
+                };
+                let token = login(self.profile, &options)?;
+                let client = build_client(self.profile, &token)?;
+                self.client.replace(Some(client.clone()));
+                Ok(request(&client)?)
+            }
+            result => Ok(result?),
+        }
+    }
+
+    pub fn profile(&self) -> &'a ActiveProfile {
+        self.profile
+    }
+
+    // The client itself, for requests that can't stop to log in again, e.g. from another thread
+    pub fn client(&self) -> Result<TickTickClient> {
+        if let Some(client) = self.client.borrow().as_ref() {
+            return Ok(client.clone());
+        }
+
+        let client = connect(self.profile)?;
+        self.client.replace(Some(client.clone()));
+        Ok(client)
+    }
+}
+
+// A client for the profile, logging in first if there's no usable token
+fn connect(profile: &ActiveProfile) -> Result<TickTickClient> {
+    let store = profile.token_store()?;
+    let access_token = match read_access_token(profile, store.as_ref()) {
+        Ok(token) => token,
+        Err(_) => {
+            let options = LoginOptions {
+                pkce: profile.profile.pkce,
+                ..LoginOptions::default()
+            };
+            let token = get_access_token_from_user(profile, &options)?;
+            let _ = store.save(&token);
+            token
+        }
+    };
+
+    build_client(profile, &access_token)
+}
+
+fn build_client(profile: &ActiveProfile, token: &AccessToken) -> Result<TickTickClient> {
+    TickTickClient::builder()
+        .host(profile.host())
+        .access_token(token.access_token.clone())
+        .build()
+}
+
+// Always goes through the OAuth flow, even if there's a perfectly good token stored already
+pub fn login(profile: &ActiveProfile, options: &LoginOptions) -> Result<AccessToken> {
+    let store = profile.token_store()?;
+    let token = get_access_token_from_user(profile, options)?;
+    store.save(&token)?;
+    Ok(token)
+}
+
+/*
+Only the local copy goes away. TickTick doesn't document a way to revoke a token,
+so it stays valid until it expires. False when there was nothing to delete
+*/
+pub fn logout(profile: &ActiveProfile) -> Result<bool> {
+    let store = profile.token_store()?;
+    if stored_access_token(profile, store.as_ref())?.is_none() {
+        return Ok(false);
+    }
+    store.delete()?;
+    Ok(true)
+}
+
+// The stored token as is, expired or not
+pub fn stored_access_token(profile: &ActiveProfile, store: &dyn TokenStore) -> Result<Option<AccessToken>> {
+    match store.load()? {
+        Some(token) => Ok(Some(token)),
+        // Only the default profile existed back when the token lived in the cache dir
+        None if profile.name == DEFAULT_PROFILE => migrate_legacy_token(store),
+        None => Ok(None),
+    }
+}
+
+pub fn read_access_token(profile: &ActiveProfile, store: &dyn TokenStore) -> Result<AccessToken> {
+    let token = stored_access_token(profile, store)?.ok_or(anyhow!("No token found for profile"))?;
+
+    if token.is_expired() {
+        return Err(anyhow!("Token expired"));
+    }
+    Ok(token)
+}
+
+fn get_access_token_from_user(profile: &ActiveProfile, options: &LoginOptions) -> Result<AccessToken> {
+    /*
+    I really don't think we need to bother too much with the state token.
+    The server is quite literally meant for a oneshot and any user
+    is going to be rolling their own credentials, this is not going to be a
+    long running service
+    */
+
+    let state = generate_state_token();
+    let credentials = profile.credentials()?;
+    let redirect_url = &credentials.redirect_url;
+    let host = profile.host();
+
+    let code_verifier = options.pkce.then(generate_code_verifier);
+    let auth_url = auth::authorize_url(&host, &credentials, &state, code_verifier.as_deref())?;
+
+    // Both the listener and the terminal send whatever they get here, first one wins
+    let (tx, rx) = mpsc::channel();
+    let listening = RedirectListener::bind(redirect_url).map(|listener| {
+        let address = listener.local_addr();
+        listener.spawn(options.timeout, tx.clone());
+        address
+    });
+
+    if options.no_browser {
+        println!("Open the following url in a browser to log in:\n\n{}\n", auth_url);
+        match listening {
+            Ok(address) => println!(
+                "Waiting for the redirect on {}, or paste the url you were redirected to (or just the code):",
+                address.map_or(redirect_url.to_string(), |addr| addr.to_string())
+            ),
+            Err(err) => println!(
+                "Could not listen for the redirect ({:#}), paste the url you were redirected to (or just the code):",
+                err
+            ),
+        }
+        read_redirect_from_terminal(state.clone(), tx);
+    } else {
+        listening?;
+        drop(tx);
+        open::that(auth_url.as_str())?;
+    }
+
+    let auth_redirect = rx.recv().context("Failed to receive data from the redirect")??;
+    auth::exchange_code_for_token(&host, &credentials, &auth_redirect, &state, code_verifier.as_deref())
+}
+
+fn read_redirect_from_terminal(state: String, tx: mpsc::Sender<Result<AuthRedirect>>) {
+    thread::spawn(move || {
+        let mut input = String::new();
+        loop {
+            input.clear();
+            let redirect = match std::io::stdin().read_line(&mut input) {
+                // Nothing will ever be pasted, leave it up to the listener
+                Ok(0) => return,
+                Ok(_) if input.trim().is_empty() => continue,
+                Ok(_) => parse_pasted_redirect(&input, &state),
+                Err(err) => Err(anyhow!("Could not read the redirect from the terminal: {}", err)),
+            };
+            let _ = tx.send(redirect);
+            return;
+        }
+    });
+}
+
+// The token exactly as older versions of tok wrote it, bincode can't skip over missing fields
+#[derive(Deserialize)]
+struct LegacyAccessToken {
+    access_token: String,
+    #[serde(with = "jiff::fmt::serde::timestamp::second::required")]
+    expires_on: Timestamp,
+}
+
+/*
+Older versions of tok kept a single bincode token in the cache dir.
+If it's still around, it's moved into the given store and removed from the cache
+*/
+fn migrate_legacy_token(store: &dyn TokenStore) -> Result<Option<AccessToken>> {
+    let app_dirs = AppDirs::new(Some("tok"), true).context("Unable to get cache directory")?;
+    let legacy_path = app_dirs.cache_dir.join("tok");
+
+    let file = match OpenOptions::new().read(true).open(&legacy_path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).context("Unable to open legacy token file"),
+    };
+
+    let legacy: LegacyAccessToken =
+        bincode::deserialize_from(BufReader::new(file)).context("Unable to read legacy token file")?;
+    let token = AccessToken {
+        access_token: legacy.access_token,
+        expires_on: legacy.expires_on,
+        scope: None,
+    };
+    store.save(&token)?;
+    fs::remove_file(&legacy_path).context("Unable to remove legacy token file")?;
+
+    Ok(Some(token))
+}
@@ -0,0 +1,646 @@
+/*
+A prompt that keeps the session and everything fetched around between commands, so TickTick is
+only asked for the projects once, and again on `refresh`. Writes update the projects in place
+instead of fetching them again, and numbers always point into the last `show`, so they don't
+shift under the user when something gets completed
+*/
+
+use anyhow::{anyhow, Context as _, Result};
+use clap::{arg, value_parser, Arg, ArgAction, ArgMatches, Command};
+use jiff::civil::Date;
+use jiff::Zoned;
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::FileHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use std::borrow::Cow;
+use std::fs::create_dir_all;
+
+use tok::data::{ProjectData, Task};
+
+use crate::args::{filter_args, read_filters, read_timeframe, timeframe_arg};
+use crate::config::ActiveProfile;
+use crate::helpers::{
+    choose_tasks, filter, find_column, has_tag, normalize_tag, print_board, print_task_details, print_tasks,
+    report_error, tag_chip, tag_counts, Filters, Period, TagEdit, TaggedTask, TimeFrame, DEFAULT_WIDTH,
+};
+use crate::journal::{self, Action, Journal};
+use crate::picker::Picker;
+use crate::session::Session;
+
+const PROMPT: &str = "tok> ";
+const TIMEFRAMES: &[&str] = &["today", "tomorrow", "week", "all"];
+
+pub fn run(profile: &ActiveProfile, session: &Session, picker: &Picker, projects: Vec<ProjectData>) -> Result<()> {
+    let mut editor: Editor<Completions, FileHistory> = Editor::new().context("Unable to set up the prompt")?;
+    editor.set_helper(Some(Completions::default()));
+    // Kept per profile, there's nothing to load the first time around
+    let history = profile.cache_dir()?.join("shell_history");
+    let _ = editor.load_history(&history);
+
+    let mut shell = Shell::new(session, picker, projects, Journal::new(profile)?);
+    while !shell.quit {
+        if let Some(completions) = editor.helper_mut() {
+            completions.update(&shell.projects);
+        }
+        let line = match editor.readline(PROMPT) {
+            Ok(line) => line,
+            // Ctrl-C only throws away what was typed, like any other shell
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err).context("Unable to read from the terminal"),
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(line.as_str());
+
+        if let Err(err) = shell.run_line(&line) {
+            report_error(&err);
+        }
+    }
+
+    if let Some(dir) = history.parent() {
+        create_dir_all(dir).context("Unable to create cache directory")?;
+    }
+    editor
+        .save_history(&history)
+        .context("Unable to save the shell history")
+}
+
+// What can be typed at the prompt, parsed the same way as the command line
+fn commands() -> Command {
+    Command::new("tok")
+        .no_binary_name(true)
+        .subcommand_required(true)
+        .disable_version_flag(true)
+        .override_usage("<COMMAND> [ARGS]")
+        .subcommand(
+            Command::new("show")
+                .about("List To-Do items and number them for the commands that follow")
+                .arg(timeframe_arg().help("Which To-Do items to list"))
+                .arg(project_arg())
+                .args(filter_args()),
+        )
+        .subcommand(
+            Command::new("view")
+                .about("Show everything about a single To-Do item")
+                .arg(arg!(<TASK>).help("Number from the last show, id or part of the title")),
+        )
+        .subcommand(
+            Command::new("add")
+                .about("Add a To-Do item")
+                .arg(
+                    arg!(<TITLE>)
+                        .help("What needs doing")
+                        .value_parser(value_parser!(String))
+                        .num_args(1..),
+                )
+                .arg(project_arg().help("Project to add it to").required(true))
+                .arg(
+                    arg!(--due <DATE>)
+                        .help("today, tomorrow or a date like 2024-03-18")
+                        .value_parser(value_parser!(String)),
+                )
+                .arg(
+                    arg!(--tag <TAG>)
+                        .help("Tag it right away, repeat for more")
+                        .value_parser(value_parser!(String))
+                        .action(ArgAction::Append),
+                ),
+        )
+        .subcommand(Command::new("complete").about("Complete To-Do items").arg(tasks_arg()))
+        .subcommand(Command::new("delete").about("Delete To-Do items").arg(tasks_arg()))
+        .subcommand(
+            Command::new("tag")
+                .about("Add or remove a tag on To-Do items")
+                .subcommand(
+                    Command::new("add")
+                        .about("Add a tag to To-Do items")
+                        .arg(arg!(<TAG>).help("Tag to add"))
+                        .arg(tasks_arg()),
+                )
+                .subcommand(
+                    Command::new("remove")
+                        .about("Remove a tag from To-Do items")
+                        .arg(arg!(<TAG>).help("Tag to remove"))
+                        .arg(tasks_arg()),
+                )
+                .subcommand_required(true),
+        )
+        .subcommand(Command::new("tags").about("List every tag in use and how many To-Do items have it"))
+        .subcommand(
+            Command::new("move")
+                .about("Move a To-Do item to another column in its project")
+                .arg(arg!(<TASK>).help("Number from the last show, id or part of the title"))
+                .arg(
+                    arg!(--column <NAME>)
+                        .help("Name of the column to move it to")
+                        .value_parser(value_parser!(String))
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            Command::new("board")
+                .about("Show a kanban project's columns side by side")
+                .arg(arg!(<PROJECT>).help("Name of the project"))
+                .arg(
+                    arg!(--width <COLUMNS>)
+                        .help("How wide to draw the board, defaults to the width of the terminal")
+                        .value_parser(value_parser!(usize)),
+                ),
+        )
+        .subcommand(
+            Command::new("undo")
+                .about("Reopen or restore the To-Do items tok completed or deleted last")
+                .arg(
+                    arg!([COUNT])
+                        .help("How many of the latest changes to undo")
+                        .value_parser(value_parser!(usize))
+                        .default_value("1"),
+                ),
+        )
+        .subcommand(
+            Command::new("history")
+                .about("List the To-Do items tok completed or deleted lately, newest first")
+                .arg(
+                    arg!(-n --count <N>)
+                        .help("How many to list")
+                        .value_parser(value_parser!(usize))
+                        .default_value("10"),
+                ),
+        )
+        .subcommand(Command::new("refresh").about("Fetch everything from TickTick again"))
+        .subcommand(Command::new("exit").visible_alias("quit").about("Leave the shell"))
+}
+
+fn project_arg() -> Arg {
+    arg!(--project <NAME>)
+        .help("Project name to filter by")
+        .value_parser(value_parser!(String))
+}
+
+fn tasks_arg() -> Arg {
+    arg!(<TASK>)
+        .help("Numbers from the last show, ids or parts of titles")
+        .value_parser(value_parser!(String))
+        .num_args(1..)
+}
+
+struct Shell<'a> {
+    session: &'a Session<'a>,
+    picker: &'a Picker,
+    projects: Vec<ProjectData>,
+    // Ids of the tasks the last `show` numbered, in order
+    listed: Vec<String>,
+    journal: Journal,
+    quit: bool,
+}
+
+impl<'a> Shell<'a> {
+    fn new(session: &'a Session<'a>, picker: &'a Picker, projects: Vec<ProjectData>, journal: Journal) -> Self {
+        // Until the first `show`, numbers mean the same as they do in `tok show all`
+        let listed = filter(&projects, TimeFrame::All, &Filters::default())
+            .iter()
+            .map(|tagged| tagged.task.id.clone())
+            .collect();
+        Self {
+            session,
+            picker,
+            projects,
+            listed,
+            journal,
+            quit: false,
+        }
+    }
+
+    fn run_line(&mut self, line: &str) -> Result<()> {
+        let words = shlex::split(line).ok_or(anyhow!("There's a quote without an end"))?;
+        let matches = match commands().try_get_matches_from(words) {
+            Ok(matches) => matches,
+            // Help included, clap knows where each of them should go
+            Err(err) => {
+                let _ = err.print();
+                return Ok(());
+            }
+        };
+        let now = Zoned::now();
+
+        match matches.subcommand() {
+            Some(("show", show_matches)) => self.show(show_matches, &now),
+            Some(("view", view_matches)) => {
+                let task = self.find(&one(view_matches, "TASK"), &now)?;
+                print_task_details(&task, self.project(&task.project_id), &now);
+                Ok(())
+            }
+            Some(("add", add_matches)) => self.add(add_matches, &now),
+            Some(("complete", complete_matches)) => self.finish(complete_matches, Action::Complete, &now),
+            Some(("delete", delete_matches)) => self.finish(delete_matches, Action::Delete, &now),
+            Some(("tag", tag_matches)) => match tag_matches.subcommand() {
+                Some(("add", add_matches)) => {
+                    let tag = normalize_tag(&one(add_matches, "TAG"));
+                    self.edit_tag(add_matches, TagEdit::Add(&tag), &now)
+                }
+                Some(("remove", remove_matches)) => {
+                    let tag = normalize_tag(&one(remove_matches, "TAG"));
+                    self.edit_tag(remove_matches, TagEdit::Remove(&tag), &now)
+                }
+                _ => unreachable!(),
+            },
+            Some(("tags", _)) => {
+                for (tag, count) in tag_counts(&self.projects) {
+                    println!("{:>4}  {}", count, tag_chip(&tag));
+                }
+                Ok(())
+            }
+            Some(("move", move_matches)) => self.move_task(move_matches, &now),
+            Some(("board", board_matches)) => {
+                let name = one(board_matches, "PROJECT");
+                let boards: Vec<&ProjectData> = self.projects.iter().filter(|proj| proj.project.name == name).collect();
+                if boards.is_empty() {
+                    return Err(anyhow!("No project named '{}'", name));
+                }
+                let width = board_matches
+                    .get_one::<usize>("width")
+                    .copied()
+                    .or_else(|| terminal_size::terminal_size().map(|(width, _)| width.0 as usize))
+                    .unwrap_or(DEFAULT_WIDTH);
+                for (num, data) in boards.iter().enumerate() {
+                    if num > 0 {
+                        println!();
+                    }
+                    print_board(data, width);
+                }
+                Ok(())
+            }
+            Some(("undo", undo_matches)) => {
+                let count = *undo_matches.get_one::<usize>("COUNT").expect("COUNT has a default");
+                self.undo(count)
+            }
+            Some(("history", history_matches)) => {
+                let count = *history_matches.get_one::<usize>("count").expect("count has a default");
+                let entries = self.journal.entries()?;
+                if entries.is_empty() {
+                    println!("Nothing has been completed or deleted yet");
+                }
+                for entry in entries.iter().rev().take(count) {
+                    journal::print_entry(entry);
+                }
+                Ok(())
+            }
+            Some(("refresh", _)) => {
+                self.projects = self.session.call(|client| client.get_projects_with_data())?;
+                println!("Fetched {} project(s) from TickTick", self.projects.len());
+                Ok(())
+            }
+            Some(("exit", _)) => {
+                self.quit = true;
+                Ok(())
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn show(&mut self, matches: &ArgMatches, now: &Zoned) -> Result<()> {
+        // Every project, or only the ones with this name like `--project` everywhere else
+        let projects: Cow<[ProjectData]> = match matches.get_one::<String>("project") {
+            Some(name) => Cow::Owned(
+                self.projects
+                    .iter()
+                    .filter(|proj| &proj.project.name == name)
+                    .cloned()
+                    .collect(),
+            ),
+            None => Cow::Borrowed(&self.projects),
+        };
+        let frame = read_timeframe(matches);
+        let filters = read_filters(matches);
+        // Never kept in the shell's projects, completing something takes it out of there too
+        let projects = match filters.include_completed {
+            true => {
+                let mut projects = projects.into_owned();
+                journal::add_completed(&mut projects, &Period::for_frame(frame, now)?, self.session)?;
+                Cow::Owned(projects)
+            }
+            false => projects,
+        };
+        let tagged_tasks = filter(&projects, frame, &filters);
+        self.listed = print_tasks(&tagged_tasks, now)
+            .iter()
+            .map(|task| task.id.clone())
+            .collect();
+        Ok(())
+    }
+
+    // By its number in the last `show`, by id, or by part of the title, asking which one when that isn't enough
+    fn find(&self, query: &str, now: &Zoned) -> Result<Task> {
+        if let Ok(num) = query.parse::<usize>() {
+            let id = num
+                .checked_sub(1)
+                .and_then(|index| self.listed.get(index))
+                .ok_or(anyhow!("Invalid task number {}", num))?;
+            return self
+                .task(id)
+                .cloned()
+                .ok_or(anyhow!("Task {} was completed or deleted since it was listed", num));
+        }
+        if let Some(task) = self.task(query) {
+            return Ok(task.clone());
+        }
+
+        let matching: Vec<TaggedTask> = filter(&self.projects, TimeFrame::All, &Filters::default())
+            .into_iter()
+            .filter(|tagged| tagged.task.title.to_lowercase().contains(&query.to_lowercase()))
+            .collect();
+        let task = match matching.len() {
+            0 => None,
+            1 => Some(matching[0].task),
+            _ => choose_tasks(&matching, self.picker, false, now)?.first().copied(),
+        };
+        task.cloned().ok_or(anyhow!("No To-Do item matches '{}'", query))
+    }
+
+    // Every one of them is looked up before anything is sent, so a typo doesn't leave things half done
+    fn find_all(&self, matches: &ArgMatches, now: &Zoned) -> Result<Vec<Task>> {
+        let mut tasks: Vec<Task> = Vec::new();
+        for query in matches.get_many::<String>("TASK").into_iter().flatten() {
+            let task = self.find(query, now)?;
+            if !tasks.iter().any(|existing| existing.id == task.id) {
+                tasks.push(task);
+            }
+        }
+        Ok(tasks)
+    }
+
+    fn task(&self, id: &str) -> Option<&Task> {
+        self.projects
+            .iter()
+            .flat_map(|proj| &proj.tasks)
+            .find(|task| task.id == id)
+    }
+
+    fn project(&self, project_id: &str) -> Option<&ProjectData> {
+        self.projects.iter().find(|proj| proj.project.id == project_id)
+    }
+
+    fn project_mut(&mut self, project_id: &str) -> Option<&mut ProjectData> {
+        self.projects.iter_mut().find(|proj| proj.project.id == project_id)
+    }
+
+    // Swaps in the task as TickTick has it after a change
+    fn replace(&mut self, task: Task) {
+        if let Some(data) = self.project_mut(&task.project_id) {
+            if let Some(existing) = data.tasks.iter_mut().find(|existing| existing.id == task.id) {
+                *existing = task;
+            }
+        }
+    }
+
+    fn add(&mut self, matches: &ArgMatches, now: &Zoned) -> Result<()> {
+        let title: Vec<&str> = matches
+            .get_many::<String>("TITLE")
+            .into_iter()
+            .flatten()
+            .map(|word| word.as_str())
+            .collect();
+        let name = one(matches, "project");
+        let data = self
+            .projects
+            .iter()
+            .find(|proj| proj.project.name == name)
+            .ok_or(anyhow!("No project named '{}'", name))?;
+
+        let due = matches
+            .get_one::<String>("due")
+            .map(|when| parse_due(when, now))
+            .transpose()?;
+        let tags: Vec<String> = matches
+            .get_many::<String>("tag")
+            .into_iter()
+            .flatten()
+            .map(|tag| normalize_tag(tag))
+            .collect();
+        let task = Task {
+            project_id: data.project.id.clone(),
+            title: title.join(" "),
+            is_all_day: due.is_some(),
+            due_date: due,
+            time_zone: now.time_zone().iana_name().unwrap_or("UTC").to_string(),
+            tags: (!tags.is_empty()).then_some(tags),
+            ..Task::default()
+        };
+
+        let created = match self.session.call(|client| client.create_task(&task)) {
+            Ok(created) => created,
+            Err(err) => {
+                eprint!("Unable to add '{}': ", task.title);
+                return Err(err);
+            }
+        };
+        println!("Added '{}' to {}", created.title, name);
+        if let Some(data) = self.project_mut(&created.project_id) {
+            data.tasks.push(created);
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, matches: &ArgMatches, action: Action, now: &Zoned) -> Result<()> {
+        for task in self.find_all(matches, now)? {
+            let result = match action {
+                Action::Complete => self.session.call(|client| client.complete_task(&task)),
+                Action::Delete => self.session.call(|client| client.delete_task(&task)),
+            };
+            match (result, action) {
+                (Ok(_), Action::Complete) => println!("Completed '{}'", task.title),
+                (Ok(_), Action::Delete) => println!("Deleted '{}'", task.title),
+                (Err(err), Action::Complete) => {
+                    eprint!("Unable to complete '{}': ", task.title);
+                    return Err(err);
+                }
+                (Err(err), Action::Delete) => {
+                    eprint!("Unable to delete '{}': ", task.title);
+                    return Err(err);
+                }
+            }
+            self.remember(action, &task);
+            if let Some(data) = self.project_mut(&task.project_id) {
+                data.tasks.retain(|existing| existing.id != task.id);
+            }
+        }
+        Ok(())
+    }
+
+    fn remember(&self, action: Action, task: &Task) {
+        if let Err(err) = self.journal.remember(action, &self.projects, task) {
+            eprintln!("Unable to save '{}' for undo: {:#}", task.title, err);
+        }
+    }
+
+    fn undo(&mut self, count: usize) -> Result<()> {
+        let mut reopened = Vec::new();
+        let undone = self.journal.undo_latest(count, self.session, |entry, task| {
+            println!("{}", entry.undone());
+            reopened.push(task);
+        });
+        for task in reopened {
+            if let Some(data) = self.project_mut(&task.project_id) {
+                data.tasks.retain(|existing| existing.id != task.id);
+                data.tasks.push(task);
+            }
+        }
+        if undone? == 0 {
+            println!("Nothing to undo");
+        }
+        Ok(())
+    }
+
+    fn edit_tag(&mut self, matches: &ArgMatches, edit: TagEdit, now: &Zoned) -> Result<()> {
+        let (TagEdit::Add(tag) | TagEdit::Remove(tag)) = edit;
+        let mut changed = 0;
+        for mut task in self.find_all(matches, now)? {
+            // Nothing to send when the task already is the way it should be
+            if has_tag(&task, tag) == matches!(edit, TagEdit::Add(_)) {
+                continue;
+            }
+
+            let tags = task.tags.get_or_insert_with(Vec::new);
+            match edit {
+                TagEdit::Add(_) => tags.push(tag.to_string()),
+                TagEdit::Remove(_) => tags.retain(|existing| existing.to_lowercase() != tag.to_lowercase()),
+            }
+            match self.session.call(|client| client.update_task(&task)) {
+                Ok(saved) => self.replace(saved),
+                Err(err) => {
+                    eprint!("Unable to update '{}': ", task.title);
+                    return Err(err);
+                }
+            }
+            changed += 1;
+        }
+
+        match edit {
+            TagEdit::Add(_) => println!("Added {} to {} task(s)", tag_chip(tag), changed),
+            TagEdit::Remove(_) => println!("Removed {} from {} task(s)", tag_chip(tag), changed),
+        }
+        Ok(())
+    }
+
+    fn move_task(&mut self, matches: &ArgMatches, now: &Zoned) -> Result<()> {
+        let mut task = self.find(&one(matches, "TASK"), now)?;
+        let data = self
+            .project(&task.project_id)
+            .expect("The task came from one of these projects");
+        let target = find_column(data, &one(matches, "column"))?;
+
+        if task.column_id.as_ref() == Some(&target.id) {
+            println!("'{}' is already in {}", task.title, target.name);
+            return Ok(());
+        }
+        let name = target.name.clone();
+        task.column_id = Some(target.id.clone());
+        match self.session.call(|client| client.update_task(&task)) {
+            Ok(saved) => {
+                println!("Moved '{}' to {}", task.title, name);
+                self.replace(saved);
+                Ok(())
+            }
+            Err(err) => {
+                eprint!("Unable to move '{}': ", task.title);
+                Err(err)
+            }
+        }
+    }
+}
+
+// Only for arguments clap already made sure are there
+fn one(matches: &ArgMatches, name: &str) -> String {
+    matches
+        .get_one::<String>(name)
+        .cloned()
+        .expect("clap requires this argument")
+}
+
+// today, tomorrow or a date like 2024-03-18, all of them all day
+fn parse_due(when: &str, now: &Zoned) -> Result<Zoned> {
+    let date = match when.to_lowercase().as_str() {
+        "today" => now.date(),
+        "tomorrow" => now.date().tomorrow()?,
+        other => other
+            .parse::<Date>()
+            .map_err(|_| anyhow!("'{}' isn't a due date, try today, tomorrow or 2024-03-18", when))?,
+    };
+    Ok(date.to_zoned(now.time_zone().clone())?)
+}
+
+// Tab completion for command names and whatever the flag before the cursor wants
+#[derive(Default)]
+struct Completions {
+    projects: Vec<String>,
+    tags: Vec<String>,
+    columns: Vec<String>,
+}
+
+impl Completions {
+    fn update(&mut self, projects: &[ProjectData]) {
+        self.projects = projects.iter().map(|proj| proj.project.name.clone()).collect();
+        self.tags = tag_counts(projects).into_iter().map(|(tag, _)| tag).collect();
+        self.columns = Vec::new();
+        for column in projects.iter().flat_map(|proj| &proj.columns) {
+            if !self.columns.contains(&column.name) {
+                self.columns.push(column.name.clone());
+            }
+        }
+    }
+}
+
+impl Completer for Completions {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let before = &line[..pos];
+        let start = before.rfind(' ').map_or(0, |at| at + 1);
+        let previous: Vec<&str> = before[..start].split_whitespace().collect();
+
+        let options: Vec<String> = match (previous.first().copied(), previous.last().copied()) {
+            (None, _) => commands()
+                .get_subcommands()
+                .map(|command| command.get_name().to_string())
+                .collect(),
+            (_, Some("--project")) => self.projects.clone(),
+            (_, Some("--tag" | "--no-tag")) => self.tags.clone(),
+            (_, Some("--column")) => self.columns.clone(),
+            (Some("board"), _) if previous.len() == 1 => self.projects.clone(),
+            (Some("tag"), _) if previous.len() == 1 => vec!["add".to_string(), "remove".to_string()],
+            (Some("tag"), _) if previous.len() == 2 => self.tags.clone(),
+            (Some("show"), _) => TIMEFRAMES.iter().map(|frame| frame.to_string()).collect(),
+            _ => Vec::new(),
+        };
+
+        let word = before[start..].to_lowercase();
+        let candidates = options
+            .into_iter()
+            .filter(|option| option.to_lowercase().starts_with(&word))
+            .map(|option| Pair {
+                replacement: match shlex::try_quote(&option) {
+                    Ok(quoted) => format!("{} ", quoted),
+                    Err(_) => format!("{} ", option),
+                },
+                display: option,
+            })
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for Completions {
+    type Hint = String;
+}
+
+impl Highlighter for Completions {}
+
+impl Validator for Completions {}
+
+impl Helper for Completions {}
@@ -0,0 +1,152 @@
+//! Places to keep an [`AccessToken`] between runs.
+
+#[cfg(feature = "secret-service")]
+use anyhow::anyhow;
+use anyhow::{Context, Result};
+use std::fs::{self, OpenOptions};
+use std::io::{BufReader, BufWriter, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::auth::AccessToken;
+
+/// Storage for a single access token.
+///
+/// A missing token isn't an error, `load` returns `None` and the user has to log in.
+pub trait TokenStore {
+    /// The stored token, or `None` when there isn't one.
+    fn load(&self) -> Result<Option<AccessToken>>;
+    /// Replaces whatever was stored.
+    fn save(&self, token: &AccessToken) -> Result<()>;
+    /// Removes the stored token, there being none to remove is fine.
+    fn delete(&self) -> Result<()>;
+}
+
+/// A JSON file that only its owner can read.
+///
+/// It belongs in the data dir rather than the cache, where cache cleaners won't delete it.
+pub struct FileTokenStore {
+    path: PathBuf,
+}
+
+impl FileTokenStore {
+    /// A store at `path`, which is only created once a token is saved.
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+impl TokenStore for FileTokenStore {
+    fn load(&self) -> Result<Option<AccessToken>> {
+        let file = match OpenOptions::new().read(true).open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err).context("Unable to open token file"),
+        };
+
+        let token = serde_json::from_reader(BufReader::new(file)).context("Unable to read token from file")?;
+        Ok(Some(token))
+    }
+
+    fn save(&self, token: &AccessToken) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            create_private_dir(parent)?;
+        }
+
+        let file = BufWriter::new(open_private(&self.path).context("Unable to create token file")?);
+        serde_json::to_writer(file, token).context("Unable to save token to file")
+    }
+
+    fn delete(&self) -> Result<()> {
+        match fs::remove_file(&self.path) {
+            Ok(_) => Ok(()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err).context("Unable to delete token file"),
+        }
+    }
+}
+
+/// Creates the directory and any missing parents, with only its owner allowed in on unix.
+#[cfg(unix)]
+pub fn create_private_dir(path: &Path) -> Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+    if !path.exists() {
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(path)
+            .context("Unable to create directory")?;
+    }
+    fs::set_permissions(path, fs::Permissions::from_mode(0o700)).context("Unable to secure directory")
+}
+
+/// Creates the directory and any missing parents.
+#[cfg(not(unix))]
+pub fn create_private_dir(path: &Path) -> Result<()> {
+    fs::create_dir_all(path).context("Unable to create directory")
+}
+
+/// Opens the file for writing from scratch, readable only by its owner on unix.
+///
+/// Files that already exist are tightened up as well.
+#[cfg(unix)]
+pub fn open_private(path: &Path) -> std::io::Result<fs::File> {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    // The mode only applies to new files, so tighten up any file left behind by an older tok
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    Ok(file)
+}
+
+/// Opens the file for writing from scratch.
+#[cfg(not(unix))]
+pub fn open_private(path: &Path) -> std::io::Result<fs::File> {
+    OpenOptions::new().write(true).create(true).truncate(true).open(path)
+}
+
+/// The OS secret store, keyed by profile name.
+#[cfg(feature = "secret-service")]
+pub struct SecretServiceTokenStore {
+    entry: keyring::Entry,
+}
+
+#[cfg(feature = "secret-service")]
+impl SecretServiceTokenStore {
+    pub fn new(profile_name: &str) -> Result<Self> {
+        let entry = keyring::Entry::new("tok", profile_name).context("Unable to open secret service entry")?;
+        Ok(Self { entry })
+    }
+}
+
+#[cfg(feature = "secret-service")]
+impl TokenStore for SecretServiceTokenStore {
+    fn load(&self) -> Result<Option<AccessToken>> {
+        match self.entry.get_password() {
+            Ok(secret) => Ok(Some(
+                serde_json::from_str(&secret).context("Unable to read token from secret service")?,
+            )),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(err) => Err(anyhow!("Unable to read token from secret service: {}", err)),
+        }
+    }
+
+    fn save(&self, token: &AccessToken) -> Result<()> {
+        let secret = serde_json::to_string(token).context("Unable to serialize token")?;
+        self.entry
+            .set_password(&secret)
+            .map_err(|err| anyhow!("Unable to save token to secret service: {}", err))
+    }
+
+    fn delete(&self) -> Result<()> {
+        match self.entry.delete_credential() {
+            Ok(_) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(err) => Err(anyhow!("Unable to delete token from secret service: {}", err)),
+        }
+    }
+}
@@ -0,0 +1,497 @@
+/*
+A full-screen way to browse and triage To-Do items. It goes through the same client, filters
+and timeframes as every other command, the projects are just kept around between keys and
+fetched again in the background every so often
+*/
+
+mod draw;
+
+use anyhow::{Context, Result};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use jiff::{ToSpan, Zoned};
+use ratatui::widgets::ListState;
+use ratatui::DefaultTerminal;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+use tok::data::{ProjectData, Task};
+use tok::{TickTickClient, TickTickError};
+
+use crate::helpers::{filter, find_column, Filters, TaggedTask, TimeFrame};
+use crate::journal::{Action, Journal};
+
+// How long to wait on a key before checking whether a refresh came in
+const TICK: Duration = Duration::from_millis(250);
+
+type Update = Result<Vec<ProjectData>, TickTickError>;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Focus {
+    Projects,
+    Tasks,
+}
+
+// Anything but Normal is waiting on the user to type something
+enum Mode {
+    Normal,
+    Search,
+    ConfirmDelete,
+    Edit(String),
+    Move(String),
+}
+
+enum Input {
+    Typing(String),
+    Done(String),
+    Cancelled,
+}
+
+pub struct App {
+    projects: Vec<ProjectData>,
+    // The project picked in the sidebar, every project when None
+    project: Option<String>,
+    frame: TimeFrame,
+    filters: Filters,
+    // Only titles containing this are listed, updated as it's typed
+    search: String,
+    tasks: ListState,
+    focus: Focus,
+    mode: Mode,
+    // How the last action went, shown until the next key
+    status: Option<String>,
+    refreshed: Zoned,
+    journal: Journal,
+    quit: bool,
+}
+
+pub fn run(
+    client: TickTickClient,
+    projects: Vec<ProjectData>,
+    project: Option<String>,
+    frame: TimeFrame,
+    filters: Filters,
+    every: Duration,
+    journal: Journal,
+) -> Result<()> {
+    let (refresh, updates) = spawn_refresher(client.clone(), project, every);
+    let mut app = App::new(projects, frame, filters, journal);
+
+    let mut terminal = ratatui::try_init().context("Unable to set up the terminal")?;
+    let result = app.event_loop(&mut terminal, &client, &refresh, &updates);
+    ratatui::restore();
+    result
+}
+
+/*
+Fetches everything again every `every`, or as soon as something is sent on the returned Sender.
+The thread goes away once the app drops its end of either channel
+*/
+fn spawn_refresher(client: TickTickClient, project: Option<String>, every: Duration) -> (Sender<()>, Receiver<Update>) {
+    let (refresh, requests) = mpsc::channel();
+    let (results, updates) = mpsc::channel();
+
+    thread::spawn(move || loop {
+        if let Err(RecvTimeoutError::Disconnected) = requests.recv_timeout(every) {
+            break;
+        }
+        let result = match &project {
+            Some(name) => client.get_single_project_with_data(name),
+            None => client.get_projects_with_data(),
+        };
+        if results.send(result).is_err() {
+            break;
+        }
+    });
+    (refresh, updates)
+}
+
+impl App {
+    fn new(projects: Vec<ProjectData>, frame: TimeFrame, filters: Filters, journal: Journal) -> Self {
+        let mut app = Self {
+            projects,
+            project: None,
+            frame,
+            filters,
+            search: String::new(),
+            tasks: ListState::default(),
+            focus: Focus::Tasks,
+            mode: Mode::Normal,
+            status: None,
+            refreshed: Zoned::now(),
+            journal,
+            quit: false,
+        };
+        app.keep_selection(None);
+        app
+    }
+
+    fn event_loop(
+        &mut self,
+        terminal: &mut DefaultTerminal,
+        client: &TickTickClient,
+        refresh: &Sender<()>,
+        updates: &Receiver<Update>,
+    ) -> Result<()> {
+        while !self.quit {
+            terminal
+                .draw(|frame| draw::draw(frame, self))
+                .context("Unable to draw to the terminal")?;
+
+            if event::poll(TICK).context("Unable to read from the terminal")? {
+                if let Event::Key(key) = event::read().context("Unable to read from the terminal")? {
+                    if key.kind == KeyEventKind::Press {
+                        self.handle_key(key, client, refresh);
+                    }
+                }
+            }
+            while let Ok(update) = updates.try_recv() {
+                match update {
+                    Ok(projects) => self.replace_projects(projects),
+                    Err(err) => self.status = Some(format!("Unable to refresh: {}", err)),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // What's in the task list right now, in the order it's shown
+    fn listing(&self) -> Vec<TaggedTask<'_>> {
+        let projects = match &self.project {
+            Some(id) => match self.projects.iter().position(|proj| &proj.project.id == id) {
+                Some(index) => &self.projects[index..=index],
+                None => &self.projects[..0],
+            },
+            None => &self.projects[..],
+        };
+        let search = self.search.to_lowercase();
+
+        let mut listed = filter(projects, self.frame, &self.filters);
+        listed.retain(|tagged| tagged.task.title.to_lowercase().contains(&search));
+        listed
+    }
+
+    fn selected(&self) -> Option<TaggedTask<'_>> {
+        let index = self.tasks.selected()?;
+        self.listing().into_iter().nth(index)
+    }
+
+    // The selected task, as long as it's one that can actually be changed
+    fn chosen(&mut self) -> Option<Task> {
+        let chosen = self
+            .selected()
+            .map(|tagged| (tagged.task.clone(), tagged.projected.is_some()));
+        match chosen {
+            Some((_, true)) => {
+                self.status = Some("Projected occurrences can't be changed, pick the task itself".to_string());
+                None
+            }
+            Some((task, false)) if task.status != 0 => {
+                self.status = Some(format!("'{}' is already completed", task.title));
+                None
+            }
+            Some((task, false)) => Some(task),
+            None => {
+                self.status = Some("There's no To-Do item selected".to_string());
+                None
+            }
+        }
+    }
+
+    fn project_data(&self, project_id: &str) -> Option<&ProjectData> {
+        self.projects.iter().find(|proj| proj.project.id == project_id)
+    }
+
+    // Goes back to the task with this id if it's still listed, otherwise stays as close as possible
+    fn keep_selection(&mut self, id: Option<String>) {
+        let listed = self.listing();
+        let found = id.and_then(|id| {
+            listed
+                .iter()
+                .position(|tagged| tagged.task.id == id && tagged.projected.is_none())
+        });
+        let index = match (found, listed.len()) {
+            (_, 0) => None,
+            (Some(index), _) => Some(index),
+            (None, len) => Some(self.tasks.selected().unwrap_or(0).min(len - 1)),
+        };
+        self.tasks.select(index);
+    }
+
+    fn selected_id(&self) -> Option<String> {
+        self.selected().map(|tagged| tagged.task.id.clone())
+    }
+
+    fn replace_projects(&mut self, mut projects: Vec<ProjectData>) {
+        let id = self.selected_id();
+        // Completed tasks were only fetched up front, a refresh doesn't bring them back
+        for data in &mut projects {
+            let Some(old) = self.project_data(&data.project.id) else {
+                continue;
+            };
+            let completed: Vec<Task> = old
+                .tasks
+                .iter()
+                .filter(|task| task.status != 0 && !data.tasks.iter().any(|fresh| fresh.id == task.id))
+                .cloned()
+                .collect();
+            data.tasks.extend(completed);
+        }
+        self.projects = projects;
+        if let Some(project) = &self.project {
+            if self.project_data(project).is_none() {
+                self.project = None;
+            }
+        }
+        self.refreshed = Zoned::now();
+        self.keep_selection(id);
+    }
+
+    // Swaps in the task as TickTick has it after a change
+    fn replace_task(&mut self, task: Task) {
+        let id = task.id.clone();
+        if let Some(data) = self.projects.iter_mut().find(|proj| proj.project.id == task.project_id) {
+            if let Some(existing) = data.tasks.iter_mut().find(|existing| existing.id == task.id) {
+                *existing = task;
+            }
+        }
+        self.keep_selection(Some(id));
+    }
+
+    fn remove_task(&mut self, task: &Task) {
+        if let Some(data) = self.projects.iter_mut().find(|proj| proj.project.id == task.project_id) {
+            data.tasks.retain(|existing| existing.id != task.id);
+        }
+        self.keep_selection(None);
+    }
+
+    fn handle_key(&mut self, key: KeyEvent, client: &TickTickClient, refresh: &Sender<()>) {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.quit = true;
+            return;
+        }
+        self.status = None;
+
+        match std::mem::replace(&mut self.mode, Mode::Normal) {
+            Mode::Normal => self.normal_key(key, client, refresh),
+            Mode::Search => {
+                let id = self.selected_id();
+                match input(self.search.clone(), key) {
+                    Input::Typing(search) => {
+                        self.search = search;
+                        self.mode = Mode::Search;
+                    }
+                    Input::Done(_) => {}
+                    Input::Cancelled => self.search.clear(),
+                }
+                self.keep_selection(id);
+            }
+            Mode::ConfirmDelete => match key.code {
+                KeyCode::Char('y') | KeyCode::Char('Y') => self.delete(client),
+                _ => self.status = Some("Nothing was deleted".to_string()),
+            },
+            Mode::Edit(title) => match input(title, key) {
+                Input::Typing(title) => self.mode = Mode::Edit(title),
+                Input::Done(title) => self.rename(title.trim(), client),
+                Input::Cancelled => {}
+            },
+            Mode::Move(column) => match input(column, key) {
+                Input::Typing(column) => self.mode = Mode::Move(column),
+                Input::Done(column) => self.move_to(column.trim(), client),
+                Input::Cancelled => {}
+            },
+        }
+    }
+
+    fn normal_key(&mut self, key: KeyEvent, client: &TickTickClient, refresh: &Sender<()>) {
+        match key.code {
+            KeyCode::Char('q') => self.quit = true,
+            KeyCode::Esc => match self.search.is_empty() {
+                true => self.quit = true,
+                false => {
+                    let id = self.selected_id();
+                    self.search.clear();
+                    self.keep_selection(id);
+                }
+            },
+            KeyCode::Tab | KeyCode::BackTab => {
+                self.focus = match self.focus {
+                    Focus::Projects => Focus::Tasks,
+                    Focus::Tasks => Focus::Projects,
+                }
+            }
+            KeyCode::Char('j') | KeyCode::Down => self.step(1),
+            KeyCode::Char('k') | KeyCode::Up => self.step(-1),
+            KeyCode::Char('1') => self.set_frame(TimeFrame::Today),
+            KeyCode::Char('2') => self.set_frame(TimeFrame::Tomorrow),
+            KeyCode::Char('3') => self.set_frame(TimeFrame::Week),
+            KeyCode::Char('4') => self.set_frame(TimeFrame::All),
+            KeyCode::Char('/') => self.mode = Mode::Search,
+            KeyCode::Char('r') => {
+                let _ = refresh.send(());
+                self.status = Some("Refreshing...".to_string());
+            }
+            KeyCode::Char('c') => self.complete(client),
+            KeyCode::Char('d') => {
+                if self.chosen().is_some() {
+                    self.mode = Mode::ConfirmDelete;
+                }
+            }
+            KeyCode::Char('s') => self.snooze(client),
+            KeyCode::Char('e') => {
+                if let Some(task) = self.chosen() {
+                    self.mode = Mode::Edit(task.title);
+                }
+            }
+            KeyCode::Char('m') => {
+                if self.chosen().is_some() {
+                    self.mode = Mode::Move(String::new());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Moves through whichever list has focus, the sidebar picks the project as it goes
+    fn step(&mut self, by: isize) {
+        match self.focus {
+            Focus::Projects => {
+                let mut entries: Vec<Option<String>> = vec![None];
+                entries.extend(self.projects.iter().map(|proj| Some(proj.project.id.clone())));
+                let current = entries.iter().position(|entry| entry == &self.project).unwrap_or(0);
+                let next = current.saturating_add_signed(by).min(entries.len() - 1);
+                self.project = entries.swap_remove(next);
+                self.tasks.select(None);
+                self.keep_selection(None);
+            }
+            Focus::Tasks => {
+                let len = self.listing().len();
+                if len > 0 {
+                    let current = self.tasks.selected().unwrap_or(0);
+                    self.tasks.select(Some(current.saturating_add_signed(by).min(len - 1)));
+                }
+            }
+        }
+    }
+
+    fn set_frame(&mut self, frame: TimeFrame) {
+        let id = self.selected_id();
+        self.frame = frame;
+        self.keep_selection(id);
+    }
+
+    fn complete(&mut self, client: &TickTickClient) {
+        let Some(task) = self.chosen() else {
+            return;
+        };
+        match client.complete_task(&task) {
+            Ok(_) => {
+                self.status = Some(self.remember(Action::Complete, &task, format!("Completed '{}'", task.title)));
+                self.remove_task(&task);
+            }
+            Err(err) => self.status = Some(format!("Unable to complete '{}': {}", task.title, err)),
+        }
+    }
+
+    fn delete(&mut self, client: &TickTickClient) {
+        let Some(task) = self.chosen() else {
+            return;
+        };
+        match client.delete_task(&task) {
+            Ok(_) => {
+                self.status = Some(self.remember(Action::Delete, &task, format!("Deleted '{}'", task.title)));
+                self.remove_task(&task);
+            }
+            Err(err) => self.status = Some(format!("Unable to delete '{}': {}", task.title, err)),
+        }
+    }
+
+    fn remember(&self, action: Action, task: &Task, done: String) -> String {
+        match self.journal.remember(action, &self.projects, task) {
+            Ok(_) => done,
+            Err(err) => format!("{}, but it can't be undone: {:#}", done, err),
+        }
+    }
+
+    // Pushes the task back a day, the start date moves along with it so the length stays the same
+    fn snooze(&mut self, client: &TickTickClient) {
+        let Some(mut task) = self.chosen() else {
+            return;
+        };
+        let Some(due) = task.due_date.as_ref().and_then(|due| due.checked_add(1.day()).ok()) else {
+            self.status = Some(format!("'{}' has no due date to snooze", task.title));
+            return;
+        };
+        task.start_date = task.start_date.and_then(|start| start.checked_add(1.day()).ok());
+        task.due_date = Some(due.clone());
+
+        let done = format!("Snoozed '{}' until {}", task.title, due.strftime("%a %m/%d"));
+        self.save(task, "snooze", done, client);
+    }
+
+    fn rename(&mut self, title: &str, client: &TickTickClient) {
+        let Some(mut task) = self.chosen() else {
+            return;
+        };
+        if title.is_empty() || title == task.title {
+            self.status = Some("The title stayed the same".to_string());
+            return;
+        }
+
+        let done = format!("Renamed '{}' to '{}'", task.title, title);
+        task.title = title.to_string();
+        self.save(task, "rename", done, client);
+    }
+
+    // Same rules as `tok move`
+    fn move_to(&mut self, column: &str, client: &TickTickClient) {
+        let Some(mut task) = self.chosen() else {
+            return;
+        };
+        let Some(data) = self.project_data(&task.project_id) else {
+            return;
+        };
+        let target = match find_column(data, column) {
+            Ok(target) => target,
+            Err(err) => {
+                self.status = Some(err.to_string());
+                return;
+            }
+        };
+
+        if task.column_id.as_ref() == Some(&target.id) {
+            self.status = Some(format!("'{}' is already in {}", task.title, target.name));
+            return;
+        }
+        let done = format!("Moved '{}' to {}", task.title, target.name);
+        task.column_id = Some(target.id.clone());
+        self.save(task, "move", done, client);
+    }
+
+    fn save(&mut self, task: Task, verb: &str, done: String, client: &TickTickClient) {
+        match client.update_task(&task) {
+            Ok(saved) => {
+                self.replace_task(saved);
+                self.status = Some(done);
+            }
+            Err(err) => self.status = Some(format!("Unable to {} '{}': {}", verb, task.title, err)),
+        }
+    }
+}
+
+// A single line of typing, Enter finishes it and Esc throws it away
+fn input(mut text: String, key: KeyEvent) -> Input {
+    match key.code {
+        KeyCode::Enter => Input::Done(text),
+        KeyCode::Esc => Input::Cancelled,
+        KeyCode::Backspace => {
+            text.pop();
+            Input::Typing(text)
+        }
+        KeyCode::Char(c) => {
+            text.push(c);
+            Input::Typing(text)
+        }
+        _ => Input::Typing(text),
+    }
+}
@@ -0,0 +1,272 @@
+use jiff::Zoned;
+use ratatui::layout::{Constraint, Layout, Position, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::Frame;
+use std::str::FromStr;
+
+use tok::data::{ProjectData, Task};
+
+use super::{App, Focus, Mode};
+use crate::helpers::{describe_date, reminder_lines, repeat_marker, repeats, tag_color, TaggedTask, TimeFrame};
+
+const SIDEBAR_WIDTH: u16 = 24;
+const ACCENT: &str = "#8F7CF2";
+const DIM: Color = Color::DarkGray;
+
+const FRAMES: [(&str, TimeFrame); 4] = [
+    ("Today", TimeFrame::Today),
+    ("Tomorrow", TimeFrame::Tomorrow),
+    ("Week", TimeFrame::Week),
+    ("All", TimeFrame::All),
+];
+
+const HELP: &str =
+    "q quit  tab switch  1-4 timeframe  / search  c complete  d delete  s snooze  e edit  m move  r refresh";
+
+// Project and tag colors are hex strings, anything that doesn't parse is left uncolored
+fn color(hex: &str) -> Color {
+    Color::from_str(hex).unwrap_or(Color::Reset)
+}
+
+pub fn draw(frame: &mut Frame, app: &mut App) {
+    let [main, footer] = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+    let [sidebar, list, details] = Layout::horizontal([
+        Constraint::Length(SIDEBAR_WIDTH),
+        Constraint::Percentage(45),
+        Constraint::Min(0),
+    ])
+    .areas(main);
+
+    draw_projects(frame, app, sidebar);
+    draw_tasks(frame, app, list);
+    draw_details(frame, app, details);
+    draw_footer(frame, app, footer);
+}
+
+fn block(title: Line<'static>, focused: bool) -> Block<'static> {
+    let border = match focused {
+        true => Style::new().fg(color(ACCENT)),
+        false => Style::new().fg(DIM),
+    };
+    Block::bordered().title(title).border_style(border)
+}
+
+fn draw_projects(frame: &mut Frame, app: &App, area: Rect) {
+    let mut items = vec![ListItem::new("All projects")];
+    items.extend(app.projects.iter().map(|data| {
+        let style = data
+            .project
+            .color
+            .as_deref()
+            .map_or(Style::new(), |hex| Style::new().fg(color(hex)));
+        ListItem::new(Span::styled(data.project.name.clone(), style))
+    }));
+
+    let selected = match &app.project {
+        Some(id) => app
+            .projects
+            .iter()
+            .position(|data| &data.project.id == id)
+            .map(|index| index + 1),
+        None => Some(0),
+    };
+    let list = List::new(items)
+        .block(block(Line::from(" Projects "), app.focus == Focus::Projects))
+        .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(list, area, &mut ListState::default().with_selected(selected));
+}
+
+fn task_line(tagged: &TaggedTask) -> Line<'static> {
+    let when = match tagged.projected.as_ref().or(tagged.task.due_date.as_ref()) {
+        Some(when) => when.strftime("%m/%d %I:%M %p").to_string(),
+        None => String::new(),
+    };
+    let project = match tagged.color {
+        Some(hex) => Span::styled(format!(" [{}]", tagged.project_name), Style::new().fg(color(hex))),
+        None => Span::raw(format!(" [{}]", tagged.project_name)),
+    };
+
+    let mut spans = vec![
+        Span::styled(format!("{:<14} ", when), Style::new().fg(DIM)),
+        match tagged.task.status {
+            0 => Span::raw(tagged.task.title.clone()),
+            _ => Span::styled(
+                tagged.task.title.clone(),
+                Style::new().add_modifier(Modifier::CROSSED_OUT),
+            ),
+        },
+        project,
+    ];
+    for tag in tagged.task.tags.iter().flatten() {
+        spans.push(Span::styled(
+            format!(" #{}", tag),
+            Style::new().fg(color(tag_color(tag))),
+        ));
+    }
+    spans.push(Span::raw(repeat_marker(tagged.task)));
+    if tagged.projected.is_some() {
+        spans.push(Span::styled(" (projected)", Style::new().fg(DIM)));
+    }
+    Line::from(spans)
+}
+
+fn draw_tasks(frame: &mut Frame, app: &mut App, area: Rect) {
+    let listed = app.listing();
+    let items: Vec<ListItem> = listed.iter().map(|tagged| ListItem::new(task_line(tagged))).collect();
+    let empty = items.is_empty();
+
+    let mut title = vec![Span::raw(" ")];
+    for (num, (name, frame)) in FRAMES.iter().enumerate() {
+        let label = format!("{} {} ", num + 1, name);
+        match *frame == app.frame {
+            true => title.push(Span::styled(label, Style::new().bold().fg(color(ACCENT)))),
+            false => title.push(Span::styled(label, Style::new().fg(DIM))),
+        }
+    }
+    if !app.search.is_empty() {
+        title.push(Span::raw(format!("/{} ", app.search)));
+    }
+
+    let block = block(Line::from(title), app.focus == Focus::Tasks);
+    if empty {
+        let message = Paragraph::new("Nothing to do here").fg(DIM).block(block);
+        frame.render_widget(message, area);
+        return;
+    }
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(list, area, &mut app.tasks);
+}
+
+fn field(label: &str, value: impl Into<String>) -> Line<'static> {
+    Line::from(vec![
+        Span::styled(format!("{:<11} ", format!("{}:", label)), Style::new().fg(DIM)),
+        Span::raw(value.into()),
+    ])
+}
+
+// A shorter take on `tok view`, the ids and timestamps are left for the full thing
+fn details(task: &Task, project: Option<&ProjectData>, now: &Zoned) -> Vec<Line<'static>> {
+    let mut lines = vec![Line::from(task.title.clone()).bold(), Line::default()];
+
+    if let Some(data) = project {
+        lines.push(field("Project", data.project.name.clone()));
+        let column = task
+            .column_id
+            .as_ref()
+            .and_then(|id| data.columns.iter().find(|column| &column.id == id));
+        if let Some(column) = column {
+            lines.push(field("Column", column.name.clone()));
+        }
+    }
+    match task.priority {
+        0 => {}
+        1 => lines.push(field("Priority", "Low")),
+        3 => lines.push(field("Priority", "Medium")),
+        5 => lines.push(field("Priority", "High")),
+        priority => lines.push(field("Priority", priority.to_string())),
+    }
+    if let Some(tags) = task.tags.as_ref().filter(|tags| !tags.is_empty()) {
+        let mut spans = vec![Span::styled(format!("{:<11} ", "Tags:"), Style::new().fg(DIM))];
+        for tag in tags {
+            spans.push(Span::styled(
+                format!("#{} ", tag),
+                Style::new().fg(color(tag_color(tag))),
+            ));
+        }
+        lines.push(Line::from(spans));
+    }
+    if let Some(start) = &task.start_date {
+        lines.push(field("Start", describe_date(start, task.is_all_day, now)));
+    }
+    if let Some(due) = &task.due_date {
+        lines.push(field("Due", describe_date(due, task.is_all_day, now)));
+    }
+    if repeats(task) {
+        lines.push(field("Repeats", repeat_marker(task).trim_start()));
+    }
+    for (num, line) in reminder_lines(task).into_iter().enumerate() {
+        match num {
+            0 => lines.push(field("Reminders", line)),
+            _ => lines.push(Line::from(format!("{:<11} {}", "", line))),
+        }
+    }
+
+    if let Some(items) = task.items.as_ref().filter(|items| !items.is_empty()) {
+        lines.push(Line::default());
+        for item in items {
+            let done = if item.status == 0 { "[ ]" } else { "[x]" };
+            lines.push(Line::from(format!("{} {}", done, item.title)));
+        }
+    }
+    for text in [&task.desc, &task.content].into_iter().flatten() {
+        if !text.trim().is_empty() {
+            lines.push(Line::default());
+            lines.extend(text.trim_end().lines().map(|line| Line::from(line.to_string())));
+        }
+    }
+    lines
+}
+
+fn draw_details(frame: &mut Frame, app: &App, area: Rect) {
+    let lines = match app.selected() {
+        Some(tagged) => details(tagged.task, app.project_data(&tagged.task.project_id), &Zoned::now()),
+        None => Vec::new(),
+    };
+    let paragraph = Paragraph::new(lines)
+        .wrap(Wrap { trim: false })
+        .block(block(Line::from(" Details "), false));
+    frame.render_widget(paragraph, area);
+}
+
+// Prompts while typing, otherwise how the last action went or which keys do what
+fn draw_footer(frame: &mut Frame, app: &App, area: Rect) {
+    let title = app
+        .selected()
+        .map(|tagged| tagged.task.title.clone())
+        .unwrap_or_default();
+    let (prompt, typed) = match &app.mode {
+        Mode::Normal => {
+            let line = match &app.status {
+                Some(status) => Line::from(status.clone()),
+                None => Line::from(vec![
+                    Span::styled(HELP, Style::new().fg(DIM)),
+                    Span::styled(
+                        format!("  updated {}", app.refreshed.strftime("%I:%M %p")),
+                        Style::new().fg(DIM),
+                    ),
+                ]),
+            };
+            frame.render_widget(Paragraph::new(line), area);
+            return;
+        }
+        Mode::ConfirmDelete => (format!("Delete '{}'? (y/n) ", title), String::new()),
+        Mode::Search => ("/".to_string(), app.search.clone()),
+        Mode::Edit(text) => ("Title: ".to_string(), text.clone()),
+        Mode::Move(text) => {
+            let columns: Vec<String> = app
+                .selected()
+                .and_then(|tagged| app.project_data(&tagged.task.project_id))
+                .map(|data| {
+                    let mut columns: Vec<_> = data.columns.iter().collect();
+                    columns.sort_by_key(|column| column.sort_order);
+                    columns.iter().map(|column| column.name.clone()).collect()
+                })
+                .unwrap_or_default();
+            (format!("Move to ({}): ", columns.join(", ")), text.clone())
+        }
+    };
+
+    let cursor = (prompt.chars().count() + typed.chars().count()) as u16;
+    frame.render_widget(
+        Paragraph::new(Line::from(vec![
+            Span::styled(prompt, Style::new().bold()),
+            Span::raw(typed),
+        ])),
+        area,
+    );
+    frame.set_cursor_position(Position::new(area.x + cursor.min(area.width.saturating_sub(1)), area.y));
+}
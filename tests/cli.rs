@@ -0,0 +1,1606 @@
+/*
+Runs the tok binary against the mock server.
+Every run gets its own config, data and cache dirs, with a profile pointing at the mock
+and a token already stored, so nothing ever touches the real TickTick or the user's setup
+*/
+
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::{project, task, MockServer, TOKEN};
+use jiff::tz::TimeZone;
+use jiff::{Timestamp, ToSpan, Zoned};
+use portable_pty::{native_pty_system, Child, CommandBuilder, MasterPty, PtySize};
+use serde_json::{json, Value};
+use std::ffi::OsString;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+const PROFILE: &str = "mock";
+
+struct Tok {
+    server: MockServer,
+    home: TempDir,
+}
+
+struct Output {
+    code: i32,
+    stdout: String,
+    stderr: String,
+}
+
+impl Tok {
+    // A logged in profile with tasks due today, tomorrow, later this week and whenever
+    fn new() -> Self {
+        let server = MockServer::start();
+        let today = Timestamp::now().to_zoned(TimeZone::UTC).date();
+        let due = |days: i64| {
+            today
+                .checked_add(days.days())
+                .unwrap()
+                .at(12, 0, 0, 0)
+                .to_zoned(TimeZone::UTC)
+                .unwrap()
+        };
+        let mut tasks = vec![
+            due_task("t1", "p1", "Write report", &due(0)),
+            due_task("t2", "p1", "Review PR", &due(1)),
+            due_task("t3", "p2", "Water plants", &due(3)),
+            task("t4", "p2", "Someday"),
+        ];
+        tasks[0]["tags"] = json!(["work", "urgent"]);
+        tasks[1]["tags"] = json!(["work"]);
+        tasks[2]["tags"] = json!(["home"]);
+        tasks[0]["reminders"] = json!(["TRIGGER:-PT30M"]);
+        // TickTick's way of saying a task doesn't repeat
+        tasks[1]["repeatFlag"] = json!("");
+        tasks[2]["repeatFlag"] = json!("RRULE:FREQ=DAILY;INTERVAL=2");
+        tasks[3]["repeatFlag"] = json!("RRULE:FREQ=WEEKLY;BYDAY=SA");
+        server.replace(vec![project("p1", "Work"), project("p2", "Home")], tasks);
+
+        let tok = Self {
+            server,
+            home: TempDir::new().unwrap(),
+        };
+        tok.write_config(json!({
+            "default_profile": PROFILE,
+            "profiles": {
+                PROFILE: {
+                    "client_id": "client",
+                    "client_secret": "secret",
+                    "redirect_url": "http://127.0.0.1:0/",
+                    "host": tok.server.host()
+                }
+            }
+        }));
+        tok.write_token(PROFILE, TOKEN);
+        tok
+    }
+
+    fn dir(&self, name: &str) -> PathBuf {
+        self.home.path().join(name)
+    }
+
+    fn config_path(&self) -> PathBuf {
+        self.dir("config").join("tok").join("config.json")
+    }
+
+    fn token_path(&self, profile: &str) -> PathBuf {
+        self.dir("data")
+            .join("tok")
+            .join("profiles")
+            .join(profile)
+            .join("token.json")
+    }
+
+    fn write_config(&self, config: Value) {
+        let path = self.config_path();
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, config.to_string()).unwrap();
+    }
+
+    fn config(&self) -> Value {
+        serde_json::from_str(&std::fs::read_to_string(self.config_path()).unwrap()).unwrap()
+    }
+
+    fn write_token(&self, profile: &str, token: &str) {
+        let path = self.token_path(profile);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let expires_on = Timestamp::now().as_second() + 3600;
+        std::fs::write(
+            path,
+            json!({"access_token": token, "expires_on": expires_on}).to_string(),
+        )
+        .unwrap();
+    }
+
+    // A clean environment, so TICKTICK_* and TOK_PROFILE from the outside don't leak in
+    fn run(&self, args: &[&str], stdin: &str) -> Output {
+        self.run_in_tz("UTC", args, stdin)
+    }
+
+    fn run_in_tz(&self, tz: &str, args: &[&str], stdin: &str) -> Output {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_tok"))
+            .args(args)
+            .env_clear()
+            .envs(self.env(tz))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+
+        let output = child.wait_with_output().unwrap();
+        Output {
+            code: output.status.code().unwrap_or(-1),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        }
+    }
+
+    fn env(&self, tz: &str) -> Vec<(&'static str, OsString)> {
+        vec![
+            ("HOME", self.home.path().into()),
+            ("XDG_CONFIG_HOME", self.dir("config").into()),
+            ("XDG_DATA_HOME", self.dir("data").into()),
+            ("XDG_CACHE_HOME", self.dir("cache").into()),
+            ("TZ", tz.into()),
+            // Empty unless a test puts a stand in for fzf or the like there
+            ("PATH", self.dir("bin").into()),
+        ]
+    }
+
+    // tok in a pseudo terminal, so it draws just like it would for a person
+    fn interactive(&self, args: &[&str]) -> Screen {
+        let pair = native_pty_system()
+            .openpty(PtySize {
+                rows: SCREEN_ROWS,
+                cols: SCREEN_COLS,
+                pixel_width: 0,
+                pixel_height: 0,
+            })
+            .unwrap();
+        let mut command = CommandBuilder::new(env!("CARGO_BIN_EXE_tok"));
+        command.args(args);
+        command.cwd(self.home.path());
+        command.env_clear();
+        for (key, value) in self.env("UTC") {
+            command.env(key, value);
+        }
+        let child = pair.slave.spawn_command(command).unwrap();
+        drop(pair.slave);
+
+        let parser = Arc::new(Mutex::new(vt100::Parser::new(SCREEN_ROWS, SCREEN_COLS, 0)));
+        let mut reader = pair.master.try_clone_reader().unwrap();
+        let screen = Arc::clone(&parser);
+        thread::spawn(move || {
+            let mut buf = [0; 4096];
+            while let Ok(read @ 1..) = reader.read(&mut buf) {
+                screen.lock().unwrap().process(&buf[..read]);
+            }
+        });
+
+        Screen {
+            writer: pair.master.take_writer().unwrap(),
+            child,
+            parser,
+            _master: pair.master,
+        }
+    }
+
+    fn last_request(&self) -> common::Recorded {
+        self.server.requests().pop().unwrap()
+    }
+}
+
+const SCREEN_ROWS: u16 = 30;
+const SCREEN_COLS: u16 = 140;
+// Generous, since the binary also has to start up and fetch everything from the mock first
+const PATIENCE: Duration = Duration::from_secs(10);
+
+// What tok has drawn so far, as a terminal would show it
+struct Screen {
+    child: Box<dyn Child + Send + Sync>,
+    writer: Box<dyn Write + Send>,
+    parser: Arc<Mutex<vt100::Parser>>,
+    _master: Box<dyn MasterPty + Send>,
+}
+
+impl Screen {
+    fn text(&self) -> String {
+        self.parser.lock().unwrap().screen().contents()
+    }
+
+    // A short pause after each write, so a lone escape isn't read as the start of another key
+    fn send(&mut self, keys: &str) {
+        self.writer.write_all(keys.as_bytes()).unwrap();
+        self.writer.flush().unwrap();
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    fn wait_until(&self, what: &str, done: impl Fn(&str) -> bool) {
+        let deadline = Instant::now() + PATIENCE;
+        while Instant::now() < deadline {
+            if done(&self.text()) {
+                return;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        panic!("Gave up waiting for {}, the screen was:\n{}", what, self.text());
+    }
+
+    fn wait_for(&self, text: &str) {
+        self.wait_until(&format!("'{}'", text), |screen| screen.contains(text));
+    }
+
+    fn exit_code(&mut self) -> u32 {
+        let deadline = Instant::now() + PATIENCE;
+        while Instant::now() < deadline {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                return status.exit_code();
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        panic!("tok never exited, the screen was:\n{}", self.text());
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+    }
+}
+
+fn due_task(id: &str, project_id: &str, title: &str, due: &Zoned) -> Value {
+    let mut task = task(id, project_id, title);
+    task["dueDate"] = json!(due.strftime("%Y-%m-%dT%H:%M:%S.000%z").to_string());
+    task
+}
+
+// The task titles in the order tok listed them
+fn titles(stdout: &str) -> Vec<&str> {
+    stdout
+        .lines()
+        .filter(|line| line.starts_with('('))
+        .map(|line| line.split("] ").nth(1).unwrap().split(" [").next().unwrap())
+        .collect()
+}
+
+#[test]
+fn shows_tasks_for_each_timeframe() {
+    let tok = Tok::new();
+    let cases = [
+        ("today", vec!["Write report"]),
+        ("tomorrow", vec!["Review PR"]),
+        ("week", vec!["Write report", "Review PR", "Water plants"]),
+        ("all", vec!["Write report", "Review PR", "Water plants", "Someday"]),
+    ];
+
+    for (timeframe, expected) in cases {
+        let output = tok.run(&["show", timeframe], "");
+        assert_eq!(output.code, 0, "{}: {}", timeframe, output.stderr);
+        assert_eq!(titles(&output.stdout), expected, "{}", timeframe);
+    }
+}
+
+// A POSIX TZ string has no IANA name, which used to be a panic
+#[test]
+fn shows_tasks_without_an_iana_time_zone() {
+    let tok = Tok::new();
+    let output = tok.run_in_tz("<+0330>-3:30", &["show", "all", "--project", "Work"], "");
+
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    assert!(output.stdout.contains("03:30 PM] Write report"), "{}", output.stdout);
+}
+
+#[test]
+fn shows_a_single_project() {
+    let tok = Tok::new();
+    let output = tok.run(&["show", "all", "--project", "Home"], "");
+
+    assert_eq!(titles(&output.stdout), ["Water plants", "Someday"]);
+    assert!(tok
+        .server
+        .requests()
+        .iter()
+        .all(|request| request.path != "/open/v1/project/p1/data"));
+}
+
+#[test]
+fn completes_the_chosen_task() {
+    let cases = [
+        ("today", "1", "t1"),
+        ("tomorrow", "1", "t2"),
+        ("week", "2", "t2"),
+        ("all", "4", "t4"),
+    ];
+
+    for (timeframe, choice, id) in cases {
+        let tok = Tok::new();
+        let output = tok.run(&["complete", timeframe], &format!("{}\n", choice));
+
+        assert_eq!(output.code, 0, "{}: {}", timeframe, output.stderr);
+        assert!(output.stdout.contains("Task completed successfully"));
+        let request = tok.last_request();
+        assert_eq!(request.method, "POST");
+        assert!(
+            request.path.ends_with(&format!("/task/{}/complete", id)),
+            "{}",
+            request.path
+        );
+        assert!(tok.server.task(id).is_none());
+    }
+}
+
+#[test]
+fn deletes_the_chosen_task() {
+    let cases = [
+        ("today", "1", "t1"),
+        ("tomorrow", "1", "t2"),
+        ("week", "3", "t3"),
+        ("all", "4", "t4"),
+    ];
+
+    for (timeframe, choice, id) in cases {
+        let tok = Tok::new();
+        let output = tok.run(&["delete", timeframe], &format!("{}\n", choice));
+
+        assert_eq!(output.code, 0, "{}: {}", timeframe, output.stderr);
+        assert!(output.stdout.contains("Task delete successfully"));
+        let request = tok.last_request();
+        assert_eq!(request.method, "DELETE");
+        assert!(request.path.ends_with(&format!("/task/{}", id)), "{}", request.path);
+        assert!(tok.server.task(id).is_none());
+    }
+}
+
+#[test]
+fn completes_within_a_project() {
+    let tok = Tok::new();
+    let output = tok.run(&["complete", "all", "--project", "Home"], "2\n");
+
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    assert_eq!(tok.last_request().path, "/open/v1/project/p2/task/t4/complete");
+}
+
+#[test]
+fn rejects_bad_task_numbers() {
+    let tok = Tok::new();
+    let output = tok.run(&["complete", "today"], "7\n");
+
+    assert_eq!(output.code, 1);
+    assert!(output.stderr.contains("Invalid task number"));
+    assert_eq!(tok.server.task_ids().len(), 4);
+}
+
+#[test]
+fn filters_by_tags() {
+    let tok = Tok::new();
+    let cases: [(&[&str], Vec<&str>); 5] = [
+        (&["--tag", "work"], vec!["Write report", "Review PR"]),
+        (&["--tag", "work", "--tag", "urgent"], vec!["Write report"]),
+        (
+            &["--tag", "urgent", "--tag", "home", "--any-tag"],
+            vec!["Write report", "Water plants"],
+        ),
+        (&["--no-tag", "work"], vec!["Water plants", "Someday"]),
+        (&["--tag", "WORK", "--no-tag", "urgent"], vec!["Review PR"]),
+    ];
+
+    for (filters, expected) in cases {
+        let args = [&["show", "all"], filters].concat();
+        let output = tok.run(&args, "");
+        assert_eq!(output.code, 0, "{:?}: {}", filters, output.stderr);
+        assert_eq!(titles(&output.stdout), expected, "{:?}", filters);
+    }
+}
+
+#[test]
+fn shows_tag_chips() {
+    let tok = Tok::new();
+    let output = tok.run(&["show", "today"], "");
+
+    assert!(output.stdout.contains("#work"));
+    assert!(output.stdout.contains("#urgent"));
+}
+
+#[test]
+fn adds_a_tag_to_the_chosen_tasks() {
+    let tok = Tok::new();
+    let output = tok.run(&["tag", "add", "#errands", "all"], "1, 3 4\n");
+
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    assert!(output.stdout.contains("to 3 task(s)"), "{}", output.stdout);
+    assert_eq!(
+        tok.server.task("t1").unwrap()["tags"],
+        json!(["work", "urgent", "errands"])
+    );
+    assert_eq!(tok.server.task("t3").unwrap()["tags"], json!(["home", "errands"]));
+    assert_eq!(tok.server.task("t4").unwrap()["tags"], json!(["errands"]));
+    assert_eq!(tok.server.task("t2").unwrap()["tags"], json!(["work"]));
+}
+
+#[test]
+fn removes_a_tag_from_the_chosen_tasks() {
+    let tok = Tok::new();
+    let output = tok.run(&["tag", "remove", "work", "--tag", "work"], "1 2\n");
+
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    assert_eq!(tok.server.task("t1").unwrap()["tags"], json!(["urgent"]));
+    assert_eq!(tok.server.task("t2").unwrap()["tags"], json!([]));
+
+    // Already gone, so there's nothing to send
+    let before = tok.server.requests().len();
+    let output = tok.run(&["tag", "remove", "work", "today"], "1\n");
+    assert!(output.stdout.contains("from 0 task(s)"), "{}", output.stdout);
+    assert!(tok.server.requests()[before..]
+        .iter()
+        .all(|request| request.method == "GET"));
+}
+
+#[test]
+fn lists_tags_with_counts() {
+    let tok = Tok::new();
+    let output = tok.run(&["tags"], "");
+
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    let lines: Vec<_> = output.stdout.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("   2") && lines[0].contains("#work"));
+    assert!(lines[1].starts_with("   1") && lines[1].contains("#home"));
+    assert!(lines[2].starts_with("   1") && lines[2].contains("#urgent"));
+}
+
+#[test]
+fn shows_only_repeating_tasks() {
+    let tok = Tok::new();
+    let output = tok.run(&["show", "all", "--recurring"], "");
+
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    assert_eq!(titles(&output.stdout), ["Water plants", "Someday"]);
+    assert!(output.stdout.contains("↻ every 2 days"), "{}", output.stdout);
+    assert!(output.stdout.contains("↻ every week on Sat"), "{}", output.stdout);
+
+    let output = tok.run(&["show", "tomorrow"], "");
+    assert_eq!(titles(&output.stdout), ["Review PR"]);
+    assert!(!output.stdout.contains('↻'), "{}", output.stdout);
+}
+
+#[test]
+fn lists_upcoming_occurrences() {
+    let tok = Tok::new();
+    let output = tok.run(&["recur", "water", "-n", "3"], "");
+    assert_eq!(output.code, 0, "{}", output.stderr);
+
+    let due = tok.server.task("t3").unwrap()["dueDate"].as_str().unwrap().to_string();
+    let due = Timestamp::strptime("%Y-%m-%dT%H:%M:%S%.3f%z", &due)
+        .unwrap()
+        .to_zoned(TimeZone::UTC);
+    let expected: Vec<String> = [0, 2, 4]
+        .iter()
+        .map(|days| {
+            let next = due.checked_add(days.days()).unwrap();
+            format!("  {}", next.strftime("%a %m/%d/%Y %I:%M %p"))
+        })
+        .collect();
+    let lines: Vec<_> = output.stdout.lines().collect();
+    assert_eq!(lines[0], "Water plants ↻ every 2 days");
+    assert_eq!(lines[1..], expected);
+
+    // Found by id too
+    let output = tok.run(&["recur", "t3"], "");
+    assert_eq!(output.stdout.lines().count(), 6);
+}
+
+#[test]
+fn reports_tasks_that_cannot_be_followed() {
+    let tok = Tok::new();
+
+    let output = tok.run(&["recur", "report"], "");
+    assert_eq!(output.code, 1);
+    assert!(output.stderr.contains("No repeating To-Do item matches 'report'"));
+
+    let output = tok.run(&["recur", "someday"], "");
+    assert_eq!(output.code, 1);
+    assert!(output.stderr.contains("has no date to repeat from"));
+}
+
+// A daily task last due two days ago, and one that repeats every week
+fn repeating_tasks(tok: &Tok) {
+    let noon = |days: i64| {
+        Timestamp::now()
+            .to_zoned(TimeZone::UTC)
+            .date()
+            .checked_add(days.days())
+            .unwrap()
+            .at(12, 0, 0, 0)
+            .to_zoned(TimeZone::UTC)
+            .unwrap()
+    };
+    let mut daily = due_task("t1", "p1", "Stretch", &noon(-2));
+    daily["repeatFlag"] = json!("RRULE:FREQ=DAILY;INTERVAL=1");
+    let mut weekly = due_task("t2", "p1", "Plan week", &noon(1));
+    weekly["repeatFlag"] = json!("RRULE:FREQ=WEEKLY");
+    tok.server.replace(vec![project("p1", "Work")], vec![daily, weekly]);
+}
+
+#[test]
+fn expands_repeating_tasks_within_the_timeframe() {
+    let tok = Tok::new();
+    repeating_tasks(&tok);
+
+    // The daily task is two days overdue, only today is projected rather than every day it was missed
+    let output = tok.run(&["show", "today", "--expand-recurring"], "");
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    let lines: Vec<_> = output.stdout.lines().collect();
+    assert_eq!(lines.len(), 2, "{}", output.stdout);
+    assert!(lines[0].starts_with("(001)") && !lines[0].ends_with("(projected)"));
+    assert!(lines[1].starts_with("( ~ )") && lines[1].ends_with("(projected)"));
+    let today = Timestamp::now()
+        .to_zoned(TimeZone::UTC)
+        .strftime("[%m/%d 12:00 PM]")
+        .to_string();
+    assert!(lines[1].contains(&today), "{}", lines[1]);
+
+    let output = tok.run(&["show", "tomorrow", "--expand-recurring"], "");
+    assert_eq!(titles(&output.stdout), ["Stretch", "Plan week"]);
+    assert!(output.stdout.lines().next().unwrap().starts_with("( ~ )"));
+
+    // Nothing projected without asking, and nothing for a timeframe without an end
+    let output = tok.run(&["show", "tomorrow"], "");
+    assert_eq!(titles(&output.stdout), ["Plan week"]);
+    let output = tok.run(&["show", "all", "--expand-recurring"], "");
+    assert_eq!(titles(&output.stdout), ["Stretch", "Plan week"]);
+}
+
+#[test]
+fn projected_occurrences_cannot_be_chosen() {
+    let tok = Tok::new();
+    repeating_tasks(&tok);
+
+    // Only the real tasks are numbered, so 2 is the weekly task rather than a projected one
+    let output = tok.run(&["complete", "week", "--expand-recurring"], "2\n");
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    assert_eq!(tok.last_request().path, "/open/v1/project/p1/task/t2/complete");
+    assert!(output.stdout.contains("(projected)"), "{}", output.stdout);
+}
+
+#[test]
+fn lists_reminders() {
+    let tok = Tok::new();
+    let output = tok.run(&["remind", "list"], "");
+
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    let lines: Vec<_> = output.stdout.lines().collect();
+    assert_eq!(lines.len(), 2, "{}", output.stdout);
+    assert_eq!(titles(lines[0]), ["Write report"]);
+    assert!(
+        lines[1].contains("⏰ 30 minutes before (") && lines[1].ends_with("11:30 AM)"),
+        "{}",
+        lines[1]
+    );
+}
+
+#[test]
+fn adds_reminders_to_the_chosen_tasks() {
+    let tok = Tok::new();
+    let output = tok.run(&["remind", "add", "1h before", "today"], "1\n");
+
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    assert!(output.stdout.contains("to 1 task(s)"), "{}", output.stdout);
+    assert_eq!(
+        tok.server.task("t1").unwrap()["reminders"],
+        json!(["TRIGGER:-PT30M", "TRIGGER:-PT1H"])
+    );
+
+    // Already there, and a task without a due date can't have one
+    let before = tok.server.requests().len();
+    let output = tok.run(&["remind", "add", "30 minutes before", "all"], "1 4\n");
+    assert!(output.stdout.contains("to 0 task(s)"), "{}", output.stdout);
+    assert!(output.stderr.contains("'Someday' has no due date"), "{}", output.stderr);
+    assert!(tok.server.requests()[before..]
+        .iter()
+        .all(|request| request.method == "GET"));
+}
+
+#[test]
+fn removes_reminders_from_the_chosen_tasks() {
+    let tok = Tok::new();
+    let output = tok.run(&["remind", "remove", "30m before", "today"], "1\n");
+
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    assert!(output.stdout.contains("from 1 task(s)"), "{}", output.stdout);
+    assert_eq!(tok.server.task("t1").unwrap()["reminders"], json!([]));
+}
+
+#[test]
+fn rejects_reminders_it_cannot_read() {
+    let tok = Tok::new();
+    let output = tok.run(&["remind", "add", "whenever", "today"], "1\n");
+
+    assert_eq!(output.code, 1);
+    assert!(
+        output.stderr.contains("Unrecognized reminder 'whenever'"),
+        "{}",
+        output.stderr
+    );
+}
+
+// Everything a task can have, on top of the usual report due today
+fn detailed_task(tok: &Tok) {
+    let mut task = tok.server.task("t1").unwrap();
+    task["content"] =
+        json!("# Plan\n\n- first *draft*\n- [x] outline\n\nSee [the docs](https://example.com) and `notes.md`");
+    task["desc"] = json!("Quarterly numbers");
+    task["priority"] = json!(5);
+    task["startDate"] = task["dueDate"].clone();
+    task["columnId"] = json!("c1");
+    task["repeatFlag"] = json!("RRULE:FREQ=WEEKLY;INTERVAL=2;BYDAY=MO");
+    task["items"] = json!([
+        {"id": "i1", "title": "Gather data", "status": 1, "isAllDay": false, "sortOrder": 0, "timeZone": "UTC",
+         "completedTime": "2024-03-18T16:30:00.000+0000"},
+        {"id": "i2", "title": "Write it up", "status": 0, "isAllDay": false, "sortOrder": 1, "timeZone": "UTC"}
+    ]);
+
+    let mut tasks: Vec<Value> = ["t2", "t3", "t4"]
+        .iter()
+        .map(|id| tok.server.task(id).unwrap())
+        .collect();
+    tasks.insert(0, task);
+    tok.server
+        .replace(vec![project("p1", "Work"), project("p2", "Home")], tasks);
+}
+
+#[test]
+fn views_every_field_of_a_task() {
+    let tok = Tok::new();
+    detailed_task(&tok);
+    let output = tok.run(&["view", "t1"], "");
+
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    let stdout = &output.stdout;
+    assert!(stdout.starts_with("\x1b[1mWrite report\x1b[22m\n"), "{}", stdout);
+    for expected in [
+        "Work",
+        "Column:     Doing",
+        "Status:     Open",
+        "Priority:   High",
+        "#work",
+        "#urgent",
+        "Repeats:    ↻ every 2 weeks on Mon",
+        "Reminders:  30 minutes before (",
+        "Time zone:  UTC",
+        "Id:         t1",
+        "  [x] Gather data (done ",
+        "  [ ] Write it up",
+        "Quarterly numbers",
+    ] {
+        assert!(stdout.contains(expected), "missing {:?} in\n{}", expected, stdout);
+    }
+    let due = stdout.lines().find(|line| line.starts_with("Due:")).unwrap();
+    // Due today at noon, which is either still to come or already gone depending on when this runs
+    assert!(
+        due.contains("12:00 PM (in ") || due.ends_with(" ago)") || due.ends_with("(just now)"),
+        "{}",
+        due
+    );
+    assert!(stdout.lines().any(|line| line.starts_with("Start:")));
+}
+
+#[test]
+fn renders_task_content_as_markdown() {
+    let tok = Tok::new();
+    detailed_task(&tok);
+    let output = tok.run(&["view", "t1"], "");
+
+    let stdout = &output.stdout;
+    assert!(stdout.contains("\x1b[1mPlan\x1b[22m\n"), "{}", stdout);
+    assert!(!stdout.contains("# Plan"));
+    assert!(
+        stdout.contains("• first \x1b[3mdraft\x1b[23m\n• [x] outline"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("the docs\x1b[24m (https://example.com)"), "{}", stdout);
+    assert!(stdout.contains("notes.md") && !stdout.contains("`notes.md`"));
+}
+
+#[test]
+fn views_tasks_by_number_title_or_picking() {
+    let tok = Tok::new();
+    let first_line = |output: Output| output.stdout.lines().next().unwrap_or_default().to_string();
+
+    assert!(first_line(tok.run(&["view", "2"], "")).contains("Review PR"));
+    assert!(first_line(tok.run(&["view", "WATER"], "")).contains("Water plants"));
+    assert!(first_line(tok.run(&["view", "1", "--project", "Home"], "")).contains("Water plants"));
+
+    // Without a task, or with more than one match, it's picked from a list
+    let output = tok.run(&["view"], "4\n");
+    assert_eq!(titles(&output.stdout).len(), 4);
+    assert!(output.stdout.contains("\x1b[1mSomeday"), "{}", output.stdout);
+    let output = tok.run(&["view", "r"], "2\n");
+    assert_eq!(titles(&output.stdout), ["Write report", "Review PR", "Water plants"]);
+    assert!(output.stdout.contains("\x1b[1mReview PR"), "{}", output.stdout);
+}
+
+#[test]
+fn reports_tasks_it_cannot_find() {
+    let tok = Tok::new();
+
+    let output = tok.run(&["view", "nothing like it"], "");
+    assert_eq!(output.code, 1);
+    assert!(output.stderr.contains("No To-Do item matches 'nothing like it'"));
+
+    let output = tok.run(&["view", "9"], "");
+    assert_eq!(output.code, 1);
+    assert!(output.stderr.contains("Invalid task number 9"));
+}
+
+// Work as a kanban board, with a task in To do ahead of Review PR and nothing Done
+fn kanban(tok: &Tok) {
+    let column =
+        |id: &str, name: &str, order: i64| json!({"id": id, "projectId": "p1", "name": name, "sortOrder": order});
+    tok.server.replace_columns(vec![
+        column("c1", "Doing", 1),
+        column("c2", "To do", 0),
+        column("c3", "Done", 2),
+    ]);
+
+    let mut tasks: Vec<Value> = ["t1", "t2", "t3", "t4"]
+        .iter()
+        .map(|id| tok.server.task(id).unwrap())
+        .collect();
+    tasks[0]["columnId"] = json!("c1");
+    tasks[1]["columnId"] = json!("c2");
+    tasks[1]["sortOrder"] = json!(5);
+    let mut first = task("t5", "p1", "Plan sprint");
+    first["columnId"] = json!("c2");
+    first["sortOrder"] = json!(-5);
+    tasks.push(first);
+    tok.server
+        .replace(vec![project("p1", "Work"), project("p2", "Home")], tasks);
+}
+
+#[test]
+fn shows_columns_side_by_side() {
+    let tok = Tok::new();
+    kanban(&tok);
+    let output = tok.run(&["board", "Work", "--width", "80"], "");
+
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    let lines: Vec<_> = output
+        .stdout
+        .lines()
+        .map(|line| line.replace("\x1b[1m", "").replace("\x1b[22m", ""))
+        .collect();
+    assert_eq!(lines.len(), 4, "{}", output.stdout);
+    let headers: Vec<_> = lines[0]
+        .split("  ")
+        .map(str::trim)
+        .filter(|header| !header.is_empty())
+        .collect();
+    assert_eq!(headers, ["To do (2)", "Doing (1)", "Done (0)"]);
+    assert!(lines[2].starts_with("• Plan sprint") && lines[2].contains("• Write report"));
+    assert_eq!(lines[3].trim(), "• Review PR");
+    assert!(lines.iter().all(|line| line.chars().count() <= 80));
+}
+
+#[test]
+fn stacks_columns_when_narrow() {
+    let tok = Tok::new();
+    kanban(&tok);
+    let output = tok.run(&["board", "Work", "--width", "40"], "");
+
+    let lines: Vec<_> = output
+        .stdout
+        .lines()
+        .map(|line| line.replace("\x1b[1m", "").replace("\x1b[22m", ""))
+        .collect();
+    assert_eq!(lines[..2], ["To do (2)", "  • Plan sprint"]);
+    assert!(lines[2].starts_with("  • Review PR"));
+    assert_eq!(lines[3..5], ["", "Doing (1)"]);
+    assert!(lines[5].starts_with("  • Write report"));
+    assert_eq!(lines[6..], ["", "Done (0)"]);
+
+    let output = tok.run(&["board", "Nope"], "");
+    assert_eq!(output.code, 1);
+    assert!(output.stderr.contains("No project named 'Nope'"));
+}
+
+#[test]
+fn filters_by_column() {
+    let tok = Tok::new();
+    kanban(&tok);
+
+    let output = tok.run(&["show", "all", "--column", "to do"], "");
+    assert_eq!(titles(&output.stdout), ["Review PR", "Plan sprint"]);
+    let output = tok.run(&["show", "all", "--column", "Done"], "");
+    assert!(titles(&output.stdout).is_empty());
+}
+
+#[test]
+fn moves_tasks_between_columns() {
+    let tok = Tok::new();
+    kanban(&tok);
+    let output = tok.run(&["move", "review", "--column", "done"], "");
+
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    assert_eq!(output.stdout.trim(), "Moved 'Review PR' to Done");
+    let request = tok.last_request();
+    assert_eq!(request.path, "/open/v1/task/t2");
+    assert_eq!(tok.server.task("t2").unwrap()["columnId"], "c3");
+
+    let output = tok.run(&["move", "t2", "--column", "Done"], "");
+    assert_eq!(output.stdout.trim(), "'Review PR' is already in Done");
+
+    let output = tok.run(&["move", "t2", "--column", "Backlog"], "");
+    assert_eq!(output.code, 1);
+    assert!(output
+        .stderr
+        .contains("'Work' has no column named 'Backlog', try one of: Doing, To do, Done"));
+    let output = tok.run(&["move", "t3", "--column", "Doing"], "");
+    assert_eq!(output.code, 1);
+    assert!(output.stderr.contains("'Home' has no columns"));
+}
+
+#[test]
+fn triages_tasks_in_the_tui() {
+    let tok = Tok::new();
+    let mut screen = tok.interactive(&["tui"]);
+    screen.wait_for("Write report");
+
+    screen.send("c");
+    screen.wait_for("Completed 'Write report'");
+    assert!(tok.server.task("t1").is_none());
+
+    screen.send("d");
+    screen.wait_for("Delete 'Review PR'? (y/n)");
+    screen.send("n");
+    screen.wait_for("Nothing was deleted");
+    assert!(tok.server.task("t2").is_some());
+    screen.send("d");
+    screen.send("y");
+    screen.wait_for("Deleted 'Review PR'");
+    assert!(tok.server.task("t2").is_none());
+
+    let before = tok.server.task("t3").unwrap()["dueDate"].clone();
+    screen.send("s");
+    screen.wait_for("Snoozed 'Water plants'");
+    let due = |value: &Value| value.as_str().unwrap()[..10].parse::<jiff::civil::Date>().unwrap();
+    let after = tok.server.task("t3").unwrap()["dueDate"].clone();
+    assert_eq!(due(&after), due(&before).tomorrow().unwrap());
+
+    screen.send("j");
+    screen.send("s");
+    screen.wait_for("'Someday' has no due date to snooze");
+    screen.send("q");
+    assert_eq!(screen.exit_code(), 0);
+
+    let output = tok.run(&["history"], "");
+    let lines: Vec<_> = output.stdout.lines().collect();
+    assert!(lines[0].ends_with("Deleted    Review PR [Work]"), "{}", output.stdout);
+    assert!(
+        lines[1].ends_with("Completed  Write report [Work]"),
+        "{}",
+        output.stdout
+    );
+}
+
+#[test]
+fn searches_and_edits_in_the_tui() {
+    let tok = Tok::new();
+    let mut screen = tok.interactive(&["tui", "week"]);
+    screen.wait_for("Water plants");
+    assert!(!screen.text().contains("Someday"));
+
+    screen.send("/");
+    screen.send("PLANT");
+    screen.wait_until("the search to narrow the list", |text| !text.contains("Write report"));
+    screen.send("\r");
+    screen.send("e");
+    screen.wait_for("Title: Water plants");
+    screen.send(&"\x7f".repeat("plants".len()));
+    screen.send("the garden\r");
+    screen.wait_for("Renamed 'Water plants' to 'Water the garden'");
+    assert_eq!(tok.server.task("t3").unwrap()["title"], "Water the garden");
+
+    screen.send("\x1b");
+    screen.wait_for("Write report");
+    screen.send("1");
+    screen.wait_until("only today to be listed", |text| !text.contains("Review PR"));
+    screen.send("\x1b");
+    assert_eq!(screen.exit_code(), 0);
+}
+
+#[test]
+fn moves_tasks_and_picks_projects_in_the_tui() {
+    let tok = Tok::new();
+    kanban(&tok);
+    let mut screen = tok.interactive(&["tui"]);
+    screen.wait_for("Water plants");
+
+    screen.send("\t");
+    screen.send("j");
+    screen.wait_until("only Work to be listed", |text| !text.contains("Water plants"));
+    screen.send("\t");
+    screen.send("m");
+    screen.wait_for("Move to (To do, Doing, Done):");
+    screen.send("backlog\r");
+    screen.wait_for("'Work' has no column named 'backlog', try one of: Doing, To do, Done");
+    screen.send("m");
+    screen.send("done\r");
+    screen.wait_for("Moved 'Write report' to Done");
+    assert_eq!(tok.server.task("t1").unwrap()["columnId"], "c3");
+    screen.send("q");
+    assert_eq!(screen.exit_code(), 0);
+}
+
+#[test]
+fn refreshes_the_tui_in_the_background() {
+    let tok = Tok::new();
+    let mut screen = tok.interactive(&["tui", "--refresh", "1"]);
+    screen.wait_for("Write report");
+
+    tok.server
+        .replace(vec![project("p1", "Work")], vec![task("t9", "p1", "Showed up later")]);
+    screen.wait_for("Showed up later");
+    assert!(!screen.text().contains("Write report"));
+    screen.send("q");
+    assert_eq!(screen.exit_code(), 0);
+}
+
+#[test]
+fn needs_a_terminal_for_the_tui() {
+    let tok = Tok::new();
+    let output = tok.run(&["tui"], "");
+
+    assert_eq!(output.code, 1);
+    assert!(output.stderr.contains("tok tui needs a terminal"));
+    assert!(tok.server.requests().is_empty());
+}
+
+#[test]
+fn picks_a_task_by_typing_part_of_it() {
+    let tok = Tok::new();
+    let mut screen = tok.interactive(&["complete", "all"]);
+    screen.wait_for("4/4");
+
+    screen.send("plnts");
+    screen.wait_for("1/4");
+    screen.send("\r");
+    assert_eq!(screen.exit_code(), 0);
+    assert!(screen.text().contains("Task completed successfully"));
+    assert_eq!(tok.server.task_ids(), ["t1", "t2", "t4"]);
+}
+
+#[test]
+fn picks_several_tasks_with_space() {
+    let tok = Tok::new();
+    let mut screen = tok.interactive(&["tag", "add", "focus"]);
+    screen.wait_for("4/4");
+
+    // Review PR matches on its project and tag, even though its title doesn't mention work
+    screen.send("work");
+    screen.wait_for("2/4");
+    screen.send(" ");
+    screen.send(" ");
+    screen.send("\r");
+    assert_eq!(screen.exit_code(), 0);
+    assert!(screen.text().contains("Added #focus to 2 task(s)"));
+    for (id, tagged) in [("t1", true), ("t2", true), ("t3", false), ("t4", false)] {
+        let tags = tok.server.task(id).unwrap()["tags"].clone();
+        assert_eq!(
+            tags.as_array().into_iter().flatten().any(|tag| tag == "focus"),
+            tagged,
+            "{}",
+            id
+        );
+    }
+}
+
+#[test]
+fn cancels_the_picker_with_escape() {
+    let tok = Tok::new();
+    let mut screen = tok.interactive(&["delete", "all"]);
+    screen.wait_for("4/4");
+
+    screen.send("\x1b");
+    assert_eq!(screen.exit_code(), 1);
+    assert!(screen.text().contains("Nothing was chosen"));
+    assert_eq!(tok.server.task_ids().len(), 4);
+}
+
+#[test]
+fn picks_through_an_external_picker() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tok = Tok::new();
+    // Stands in for fzf, choosing every line with Review in it and noting how it was called
+    let fzf = tok.dir("bin").join("fzf");
+    std::fs::create_dir_all(tok.dir("bin")).unwrap();
+    std::fs::write(
+        &fzf,
+        "#!/bin/sh\n\
+         echo \"$@\" > \"${0%/*}/args\"\n\
+         while IFS= read -r line; do\n\
+           case \"$line\" in *Review*) printf '%s\\n' \"$line\" ;; esac\n\
+         done\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&fzf, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let args = || std::fs::read_to_string(tok.dir("bin").join("args")).unwrap();
+
+    let output = tok.run(&["view", "--picker", "external"], "");
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    assert!(output.stdout.contains("Review PR"));
+    assert!(!args().contains("--multi"), "{}", args());
+
+    let output = tok.run(&["complete", "all", "--picker", "external"], "");
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    assert_eq!(tok.server.task_ids(), ["t1", "t3", "t4"]);
+    assert!(
+        args().contains("--with-nth 2..") && args().contains("--multi"),
+        "{}",
+        args()
+    );
+}
+
+#[test]
+fn falls_back_to_numbers_without_an_external_picker() {
+    let tok = Tok::new();
+    let output = tok.run(&["complete", "all", "--picker", "external"], "1\n");
+
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    assert!(output.stderr.contains("Neither fzf nor sk is installed"));
+    assert!(output.stdout.contains("Please enter task numbers"));
+    assert!(tok.server.task("t1").is_none());
+
+    // Commands that never choose a task don't look for one, or warn that it's missing
+    for args in [["show", "today"], ["auth", "status"], ["profile", "list"]] {
+        let output = tok.run(&[&args[..], &["--picker", "external"]].concat(), "");
+        assert_eq!(output.code, 0, "{:?}: {}", args, output.stderr);
+        assert_eq!(output.stderr, "", "{:?}", args);
+    }
+}
+
+#[test]
+fn undoes_completions_and_deletions() {
+    let tok = Tok::new();
+    let output = tok.run(&["undo"], "");
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    assert_eq!(output.stdout.trim(), "Nothing to undo");
+
+    let deleted = tok.server.task("t2").unwrap();
+    tok.run(&["complete", "today"], "1\n");
+    tok.run(&["delete", "all"], "1\n");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let journal = tok.token_path(PROFILE).with_file_name("journal.json");
+        assert_eq!(std::fs::metadata(journal).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+    let output = tok.run(&["history"], "");
+    let lines: Vec<_> = output.stdout.lines().collect();
+    assert_eq!(lines.len(), 2, "{}", output.stdout);
+    assert!(lines[0].ends_with("Deleted    Review PR [Work]"), "{}", lines[0]);
+    assert!(lines[1].ends_with("Completed  Write report [Work]"), "{}", lines[1]);
+
+    let output = tok.run(&["undo"], "");
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    assert_eq!(output.stdout.trim(), "Restored 'Review PR' in Work");
+    let request = tok.last_request();
+    assert_eq!(
+        (request.method.as_str(), request.path.as_str()),
+        ("POST", "/open/v1/task")
+    );
+    let restored = tok.server.task("new1").unwrap();
+    for field in ["projectId", "title", "dueDate", "tags"] {
+        assert_eq!(restored[field], deleted[field], "{}", field);
+    }
+
+    let output = tok.run(&["undo"], "");
+    assert_eq!(output.stdout.trim(), "Reopened 'Write report' in Work");
+    assert_eq!(tok.last_request().path, "/open/v1/task/t1");
+    let reopened = tok.server.task("t1").unwrap();
+    assert_eq!(reopened["status"], 0);
+    assert!(reopened.get("completedTime").is_none(), "{}", reopened);
+
+    let output = tok.run(&["history"], "");
+    assert_eq!(output.stdout.trim(), "Nothing has been completed or deleted yet");
+}
+
+#[test]
+fn keeps_what_could_not_be_undone() {
+    let tok = Tok::new();
+    tok.run(&["complete", "all"], "1 2 3\n");
+    tok.server.fail_on("POST", "/open/v1/task/t2", 500, &[], "nope");
+
+    let output = tok.run(&["undo", "3"], "");
+    assert_eq!(output.code, 69);
+    assert_eq!(output.stdout.trim(), "Reopened 'Water plants' in Home");
+    assert!(output.stderr.contains("Unable to undo 'Review PR'"));
+    let output = tok.run(&["history", "-n", "5"], "");
+    assert_eq!(output.stdout.lines().count(), 2);
+
+    let output = tok.run(&["undo", "5"], "");
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    assert_eq!(tok.server.task_ids(), ["t4", "t3", "t2", "t1"]);
+}
+
+#[test]
+fn lists_what_was_done() {
+    let tok = Tok::new();
+    let mut taxes = task("t9", "p2", "File taxes");
+    taxes["status"] = json!(2);
+    taxes["completedTime"] = json!("2020-01-02T10:00:00.000+0000");
+    tok.server.add_completed(taxes);
+    let output = tok.run(&["done"], "");
+    assert_eq!(output.stdout.trim(), "Nothing was completed then");
+
+    tok.run(&["complete", "today"], "1\n");
+    for period in ["today", "week"] {
+        let output = tok.run(&["done", period], "");
+        assert_eq!(output.code, 0, "{}: {}", period, output.stderr);
+        let lines: Vec<_> = output.stdout.lines().collect();
+        assert_eq!(lines.len(), 1, "{}: {}", period, output.stdout);
+        assert!(lines[0].contains("  Write report ["), "{}", lines[0]);
+    }
+    let request = tok.last_request();
+    assert_eq!(request.path, "/open/v1/task/completed");
+    let sent: Value = serde_json::from_str(&request.body).unwrap();
+    assert_eq!(sent["projectIds"], json!(["p1", "p2"]));
+
+    let output = tok.run(&["done", "2020-01-01..2020-01-05"], "");
+    assert!(
+        output.stdout.starts_with("01/02 10:00 AM  File taxes ["),
+        "{}",
+        output.stdout
+    );
+    let output = tok.run(&["done", "2020-01-02", "--project", "Work"], "");
+    assert_eq!(output.stdout.trim(), "Nothing was completed then");
+
+    let output = tok.run(&["done", "2020-01-05..2020-01-01"], "");
+    assert_eq!(output.code, 1);
+    assert_eq!(output.stderr.trim(), "'2020-01-05..2020-01-01' ends before it starts");
+    let output = tok.run(&["done", "yesterday"], "");
+    assert_eq!(output.code, 1);
+    assert!(
+        output.stderr.contains("'yesterday' isn't a period"),
+        "{}",
+        output.stderr
+    );
+}
+
+#[test]
+fn falls_back_to_the_journal_for_what_was_done() {
+    let tok = Tok::new();
+    tok.run(&["complete", "all"], "1 3\n");
+
+    // A server without the endpoint only has what tok completed itself
+    tok.server.fail_on("POST", "/open/v1/task/completed", 404, &[], "");
+    let output = tok.run(&["done"], "");
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    let lines: Vec<_> = output.stdout.lines().collect();
+    assert_eq!(lines.len(), 2, "{}", output.stdout);
+    assert!(lines[0].contains("  Write report ["), "{}", lines[0]);
+    assert!(lines[1].contains("  Water plants ["), "{}", lines[1]);
+
+    // Completion times TickTick leaves out come from the journal too
+    let mut report = tok.server.completed("t1").unwrap();
+    report.as_object_mut().unwrap().remove("completedTime");
+    let mut unknown = task("t9", "p2", "Mystery");
+    unknown["status"] = json!(2);
+    let body = json!([report, unknown]).to_string();
+    tok.server.fail_on("POST", "/open/v1/task/completed", 200, &[], &body);
+    let output = tok.run(&["done"], "");
+    let lines: Vec<_> = output.stdout.lines().collect();
+    assert_eq!(lines.len(), 2, "{}", output.stdout);
+    assert!(lines[0].starts_with("Unknown time    Mystery ["), "{}", lines[0]);
+    let today = Timestamp::now().to_zoned(TimeZone::UTC).strftime("%m/%d ").to_string();
+    assert!(lines[1].starts_with(&today), "{}", lines[1]);
+    assert!(lines[1].contains("  Write report ["), "{}", lines[1]);
+}
+
+#[test]
+fn shows_completed_tasks_struck_through() {
+    let tok = Tok::new();
+    tok.run(&["complete", "today"], "1\n");
+
+    let output = tok.run(&["show", "all"], "");
+    assert_eq!(titles(&output.stdout), ["Review PR", "Water plants", "Someday"]);
+
+    let output = tok.run(&["show", "all", "--include-completed"], "");
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    let lines: Vec<_> = output.stdout.lines().collect();
+    assert_eq!(lines.len(), 4, "{}", output.stdout);
+    let (done, open): (Vec<&str>, Vec<&str>) = lines.iter().partition(|line| line.contains("Write report"));
+    assert!(done[0].starts_with("( ✓ )"), "{}", done[0]);
+    assert!(done[0].contains("\x1b[9mWrite report\x1b[29m"), "{}", done[0]);
+    // Completed tasks are listed but never numbered, so the open ones keep theirs
+    let labels: Vec<_> = open.iter().map(|line| &line[..5]).collect();
+    assert_eq!(labels, ["(001)", "(002)", "(003)"]);
+
+    let output = tok.run(&["show", "today", "--include-completed"], "");
+    assert_eq!(titles(&output.stdout), ["\x1b[9mWrite report\x1b[29m"]);
+}
+
+#[test]
+fn lists_what_was_completed_in_the_timeframe() {
+    let tok = Tok::new();
+    let yesterday = Zoned::now().with_time_zone(TimeZone::UTC).checked_sub(1.day()).unwrap();
+    let mut taxes = due_task("t9", "p2", "File taxes", &yesterday);
+    taxes["status"] = json!(2);
+    taxes["completedTime"] = json!(yesterday.strftime("%Y-%m-%dT%H:%M:%S.000%z").to_string());
+    taxes["reminders"] = json!(["TRIGGER:PT0S"]);
+    tok.server.add_completed(taxes);
+    let struck = "\x1b[9mFile taxes\x1b[29m";
+
+    let output = tok.run(&["show", "today", "--include-completed"], "");
+    assert!(!titles(&output.stdout).contains(&struck), "{}", output.stdout);
+    for frame in ["week", "all"] {
+        let output = tok.run(&["show", frame, "--include-completed"], "");
+        assert_eq!(output.code, 0, "{}: {}", frame, output.stderr);
+        assert!(titles(&output.stdout).contains(&struck), "{}: {}", frame, output.stdout);
+        let sent: Value = serde_json::from_str(&tok.last_request().body).unwrap();
+        let week_ago = Zoned::now()
+            .with_time_zone(TimeZone::UTC)
+            .checked_sub(6.days())
+            .unwrap();
+        let start = sent["startDate"].as_str().unwrap();
+        assert!(
+            start.starts_with(&week_ago.strftime("%Y-%m-%d").to_string()),
+            "{}",
+            start
+        );
+    }
+
+    let output = tok.run(&["remind", "list", "week", "--include-completed"], "");
+    assert!(output.stdout.contains(struck), "{}", output.stdout);
+    let output = tok.run(&["view", "taxes"], "");
+    assert_eq!(output.stderr.trim(), "No To-Do item matches 'taxes'");
+    let output = tok.run(&["view", "taxes", "--include-completed"], "");
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    assert!(output.stdout.contains("File taxes"), "{}", output.stdout);
+    let output = tok.run(&["shell"], "show week --include-completed\nexit\n");
+    assert!(output.stdout.contains(struck), "{}", output.stdout);
+}
+
+#[test]
+fn keeps_numbers_stable_in_the_shell() {
+    let tok = Tok::new();
+    let output = tok.run(
+        &["shell"],
+        "show week\ncomplete 1\ncomplete 1 3\nshow all\ndelete 2\nexit\nshow all\n",
+    );
+
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    assert_eq!(
+        titles(&output.stdout),
+        [
+            "Write report",
+            "Review PR",
+            "Water plants",
+            "Review PR",
+            "Water plants",
+            "Someday"
+        ]
+    );
+    assert!(output.stdout.contains("Completed 'Write report'"));
+    assert!(output
+        .stderr
+        .contains("Task 1 was completed or deleted since it was listed"));
+    assert!(output.stdout.contains("Deleted 'Water plants'"));
+    assert_eq!(tok.server.task_ids(), ["t2", "t4"]);
+
+    // Everything was fetched once, nothing after exit ran
+    let fetches = tok
+        .server
+        .requests()
+        .iter()
+        .filter(|request| request.path == "/open/v1/project")
+        .count();
+    assert_eq!(fetches, 1);
+}
+
+#[test]
+fn adds_and_edits_tasks_in_the_shell() {
+    let tok = Tok::new();
+    kanban(&tok);
+    let output = tok.run(
+        &["shell"],
+        "add Buy oat milk --project Home --due tomorrow --tag errands\n\
+         show tomorrow\n\
+         tag add urgent 2 review\n\
+         move 1 --column done\n\
+         view 'oat milk'\n\
+         tags\n",
+    );
+
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    assert!(output.stdout.contains("Added 'Buy oat milk' to Home"));
+    assert_eq!(titles(&output.stdout), ["Review PR", "Buy oat milk"]);
+    let created = tok.server.task("new1").unwrap();
+    assert_eq!(created["projectId"], "p2");
+    assert_eq!(created["isAllDay"], true);
+
+    assert!(
+        output.stdout.contains("urgent\x1b[0m to 2 task(s)"),
+        "{}",
+        output.stdout
+    );
+    assert_eq!(created["tags"], json!(["errands", "urgent"]));
+    assert!(output.stdout.contains("Moved 'Review PR' to Done"));
+    assert_eq!(tok.server.task("t2").unwrap()["columnId"], "c3");
+    assert!(output.stdout.contains("\x1b[1mBuy oat milk\x1b[22m"));
+    assert!(output.stdout.contains("Id:         new1"));
+    let counts: Vec<_> = output.stdout.lines().filter(|line| line.starts_with("   ")).collect();
+    assert!(
+        counts[0].starts_with("   3") && counts[0].contains("#urgent"),
+        "{:?}",
+        counts
+    );
+}
+
+#[test]
+fn reports_mistakes_in_the_shell_and_keeps_going() {
+    let tok = Tok::new();
+    let output = tok.run(
+        &["shell"],
+        "frobnicate\nview 9\nview 'unclosed\nadd Nothing --project Nope\nhelp\nshow today\n",
+    );
+
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    assert!(output.stderr.contains("unrecognized subcommand 'frobnicate'"));
+    assert!(output.stderr.contains("Invalid task number 9"));
+    assert!(output.stderr.contains("There's a quote without an end"));
+    assert!(output.stderr.contains("No project named 'Nope'"));
+    assert!(output.stdout.contains("refresh"));
+    assert_eq!(titles(&output.stdout), ["Write report"]);
+}
+
+#[test]
+fn refreshes_the_shell() {
+    let tok = Tok::new();
+    tok.server
+        .fail_on("POST", "/open/v1/project/p1/task/t1/complete", 500, &[], "boom");
+    let output = tok.run(&["shell"], "complete 1\nrefresh\nshow all\n");
+
+    assert!(output
+        .stderr
+        .contains("Unable to complete 'Write report': TickTick returned an error"));
+    assert!(output.stdout.contains("Fetched 2 project(s) from TickTick"));
+    assert_eq!(titles(&output.stdout).len(), 4);
+}
+
+#[test]
+fn undoes_in_the_shell() {
+    let tok = Tok::new();
+    let output = tok.run(&["shell"], "complete 1 2\nundo\nhistory\nshow all\n");
+
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    assert!(output.stdout.contains("Reopened 'Review PR' in Work"));
+    assert!(output.stdout.contains("Completed  Write report [Work]"));
+    assert!(!output.stdout.contains("Completed  Review PR"));
+    assert_eq!(titles(&output.stdout), ["Review PR", "Water plants", "Someday"]);
+
+    // The shell and the command line share the history
+    let output = tok.run(&["undo"], "");
+    assert_eq!(output.stdout.trim(), "Reopened 'Write report' in Work");
+}
+
+#[test]
+fn completes_commands_and_keeps_history_in_the_shell() {
+    let tok = Tok::new();
+    let mut screen = tok.interactive(&["shell"]);
+    screen.wait_for("tok> ");
+
+    screen.send("sh\t");
+    screen.wait_for("tok> show");
+    screen.send("all --project H\t");
+    screen.wait_for("tok> show all --project Home");
+    screen.send("\r");
+    screen.wait_for("Water plants");
+    screen.send("exit\r");
+    assert_eq!(screen.exit_code(), 0);
+
+    let history = tok.dir("cache").join("tok/profiles/mock/shell_history");
+    let history = std::fs::read_to_string(history).unwrap();
+    assert!(history.contains("show all --project Home"), "{}", history);
+}
+
+#[test]
+fn logs_in_with_a_pasted_code() {
+    let tok = Tok::new();
+    std::fs::remove_file(tok.token_path(PROFILE)).unwrap();
+
+    let output = tok.run(&["auth", "login", "--no-browser"], "the-code\n");
+
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    assert!(output.stdout.contains("/oauth/authorize?"));
+    assert!(output.stdout.contains("Logged in to profile 'mock'"));
+
+    let request = tok.last_request();
+    assert_eq!(request.path, "/oauth/token");
+    assert!(request.body.contains("code=the-code"));
+    assert!(request.body.contains("client_id=client"));
+
+    let stored: Value = serde_json::from_str(&std::fs::read_to_string(tok.token_path(PROFILE)).unwrap()).unwrap();
+    assert_eq!(stored["access_token"], TOKEN);
+}
+
+#[test]
+fn reports_auth_status() {
+    let tok = Tok::new();
+    let output = tok.run(&["auth", "status"], "");
+
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    assert!(output.stdout.contains("Profile: mock"));
+    assert!(output.stdout.contains("Token:   valid"));
+}
+
+#[test]
+fn logs_out_without_calling_ticktick() {
+    let tok = Tok::new();
+    let output = tok.run(&["auth", "logout"], "");
+
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    assert!(output.stdout.contains("Logged out of profile 'mock'"));
+    assert!(output.stdout.contains("TickTick keeps it valid until it expires"));
+    assert!(!tok.token_path(PROFILE).exists());
+    assert!(tok.server.requests().is_empty());
+
+    let output = tok.run(&["auth", "status"], "");
+    assert!(output.stdout.contains("Profile 'mock' is not logged in"));
+}
+
+#[test]
+fn manages_profiles() {
+    let tok = Tok::new();
+
+    let output = tok.run(
+        &[
+            "profile",
+            "add",
+            "work",
+            "--client-id",
+            "work-client",
+            "--auth-url",
+            "http://localhost:9/oauth",
+            "--api-url",
+            "http://localhost:9",
+        ],
+        "",
+    );
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    assert_eq!(tok.config()["profiles"]["work"]["client_id"], "work-client");
+    assert_eq!(
+        tok.config()["profiles"]["work"]["host"]["custom"]["api_url"],
+        "http://localhost:9"
+    );
+
+    let output = tok.run(&["profile", "list"], "");
+    assert_eq!(output.stdout, "  default (environment)\n* mock\n  work\n");
+
+    let output = tok.run(&["profile", "default", "work"], "");
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    assert_eq!(tok.config()["default_profile"], "work");
+
+    tok.write_token("work", TOKEN);
+    let output = tok.run(&["profile", "remove", "work"], "");
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    assert!(tok.config()["profiles"].get("work").is_none());
+    assert!(!tok.token_path("work").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn keeps_client_secrets_private() {
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+
+    let tok = Tok::new();
+    let output = tok.run(&["profile", "add", "work", "--client-secret", "hunter2"], "");
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    assert_eq!(tok.config()["profiles"]["work"]["client_secret"], "hunter2");
+
+    let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+    assert_eq!(mode(&tok.config_path()), 0o600);
+    assert_eq!(mode(tok.config_path().parent().unwrap()), 0o700);
+}
+
+#[test]
+fn writes_and_reads_host_names_like_the_host_flag() {
+    let tok = Tok::new();
+    let output = tok.run(&["profile", "add", "work", "--host", "dida365"], "");
+    assert_eq!(output.code, 0, "{}", output.stderr);
+    assert_eq!(tok.config()["profiles"]["work"]["host"], "dida365");
+
+    for name in ["ticktick", "tick-tick"] {
+        let mut config = tok.config();
+        config["profiles"]["work"]["host"] = json!(name);
+        tok.write_config(config);
+        let output = tok.run(&["--profile", "work", "-v", "auth", "status"], "");
+        assert!(
+            output.stderr.contains("Using API: https://api.ticktick.com"),
+            "{}: {}",
+            name,
+            output.stderr
+        );
+    }
+}
+
+#[test]
+fn picks_the_profile_from_the_commandline() {
+    let tok = Tok::new();
+    let output = tok.run(&["--profile", "missing", "show", "all"], "");
+
+    assert_eq!(output.code, 1);
+    assert!(output.stderr.contains("missing"), "{}", output.stderr);
+}
+
+#[test]
+fn exits_with_rate_limiting_code() {
+    let tok = Tok::new();
+    tok.server.fail_next(429, &[("Retry-After", "30")], "");
+    let output = tok.run(&["show", "all"], "");
+
+    assert_eq!(output.code, 75);
+    assert!(output.stderr.contains("try again in 30 seconds"));
+}
+
+#[test]
+fn exits_with_server_error_code() {
+    let tok = Tok::new();
+    tok.server.fail_on("GET", "/open/v1/project/p2/data", 500, &[], "oops");
+    let output = tok.run(&["show", "week"], "");
+
+    assert_eq!(output.code, 69);
+    assert!(output.stderr.contains("oops"));
+}
+
+#[test]
+fn reports_failed_completions() {
+    let tok = Tok::new();
+    tok.server
+        .fail_on("POST", "/open/v1/project/p1/task/t1/complete", 500, &[], "nope");
+    let output = tok.run(&["complete", "today"], "1\n");
+
+    assert_eq!(output.code, 69);
+    assert!(output.stderr.contains("Unable to complete task"));
+    assert!(tok.server.task("t1").is_some());
+}
+
+#[test]
+fn reports_missing_tasks_on_delete() {
+    let tok = Tok::new();
+    tok.server
+        .fail_on("DELETE", "/open/v1/project/p1/task/t1", 404, &[], "");
+    let output = tok.run(&["delete", "today"], "1\n");
+
+    assert_eq!(output.code, 66);
+    assert!(output.stderr.contains("Unable to delete task"));
+}
+
+/*
+There's no browser in the test environment (PATH is empty), so logging in again
+can't get anywhere, but tok should still notice and try
+*/
+#[cfg(target_os = "linux")]
+#[test]
+fn logs_in_again_when_the_token_is_rejected() {
+    let tok = Tok::new();
+    tok.server.fail_next(401, &[], "");
+    let output = tok.run(&["show", "all"], "");
+
+    assert_ne!(output.code, 0);
+    assert!(output.stderr.contains("logging in again"), "{}", output.stderr);
+    assert!(output.stdout.is_empty());
+}
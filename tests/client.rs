@@ -0,0 +1,392 @@
+/*
+The same tests for the blocking and the async client.
+Each test takes a way to connect to the mock server, the async client gets
+wrapped up so it can be driven from a plain test function
+*/
+
+mod common;
+
+use common::{MockServer, TOKEN};
+use jiff::{ToSpan, Zoned};
+use serde_json::json;
+use std::time::Duration;
+use tok::data::{Project, ProjectData, Task};
+use tok::TickTickError;
+
+trait Api {
+    fn get_projects(&self) -> Result<Vec<Project>, TickTickError>;
+    fn get_projects_with_data(&self) -> Result<Vec<ProjectData>, TickTickError>;
+    fn get_single_project_with_data(&self, name: &str) -> Result<Vec<ProjectData>, TickTickError>;
+    fn get_project_data(&self, project_id: &str) -> Result<ProjectData, TickTickError>;
+    fn complete_task(&self, task: &Task) -> Result<(), TickTickError>;
+    fn delete_task(&self, task: &Task) -> Result<(), TickTickError>;
+    fn update_task(&self, task: &Task) -> Result<Task, TickTickError>;
+    fn create_task(&self, task: &Task) -> Result<Task, TickTickError>;
+    fn get_completed_tasks(
+        &self,
+        project_ids: &[String],
+        start: &Zoned,
+        end: &Zoned,
+    ) -> Result<Vec<Task>, TickTickError>;
+}
+
+type Connect = fn(&MockServer, &str) -> Box<dyn Api>;
+
+impl Api for tok::TickTickClient {
+    fn get_projects(&self) -> Result<Vec<Project>, TickTickError> {
+        self.get_projects()
+    }
+
+    fn get_projects_with_data(&self) -> Result<Vec<ProjectData>, TickTickError> {
+        self.get_projects_with_data()
+    }
+
+    fn get_single_project_with_data(&self, name: &str) -> Result<Vec<ProjectData>, TickTickError> {
+        self.get_single_project_with_data(name)
+    }
+
+    fn get_project_data(&self, project_id: &str) -> Result<ProjectData, TickTickError> {
+        self.get_project_data(project_id)
+    }
+
+    fn complete_task(&self, task: &Task) -> Result<(), TickTickError> {
+        self.complete_task(task)
+    }
+
+    fn delete_task(&self, task: &Task) -> Result<(), TickTickError> {
+        self.delete_task(task)
+    }
+
+    fn update_task(&self, task: &Task) -> Result<Task, TickTickError> {
+        self.update_task(task)
+    }
+
+    fn create_task(&self, task: &Task) -> Result<Task, TickTickError> {
+        self.create_task(task)
+    }
+
+    fn get_completed_tasks(
+        &self,
+        project_ids: &[String],
+        start: &Zoned,
+        end: &Zoned,
+    ) -> Result<Vec<Task>, TickTickError> {
+        self.get_completed_tasks(project_ids, start, end)
+    }
+}
+
+fn blocking(server: &MockServer, token: &str) -> Box<dyn Api> {
+    let client = tok::TickTickClient::builder()
+        .host(server.host())
+        .access_token(token)
+        .timeout(Duration::from_secs(5))
+        .build()
+        .unwrap();
+    Box::new(client)
+}
+
+#[cfg(feature = "async")]
+struct Nonblocking {
+    client: tok::nonblocking::TickTickClient,
+    runtime: tokio::runtime::Runtime,
+}
+
+#[cfg(feature = "async")]
+impl Api for Nonblocking {
+    fn get_projects(&self) -> Result<Vec<Project>, TickTickError> {
+        self.runtime.block_on(self.client.get_projects())
+    }
+
+    fn get_projects_with_data(&self) -> Result<Vec<ProjectData>, TickTickError> {
+        self.runtime.block_on(self.client.get_projects_with_data())
+    }
+
+    fn get_single_project_with_data(&self, name: &str) -> Result<Vec<ProjectData>, TickTickError> {
+        self.runtime.block_on(self.client.get_single_project_with_data(name))
+    }
+
+    fn get_project_data(&self, project_id: &str) -> Result<ProjectData, TickTickError> {
+        self.runtime.block_on(self.client.get_project_data(project_id))
+    }
+
+    fn complete_task(&self, task: &Task) -> Result<(), TickTickError> {
+        self.runtime.block_on(self.client.complete_task(task))
+    }
+
+    fn delete_task(&self, task: &Task) -> Result<(), TickTickError> {
+        self.runtime.block_on(self.client.delete_task(task))
+    }
+
+    fn update_task(&self, task: &Task) -> Result<Task, TickTickError> {
+        self.runtime.block_on(self.client.update_task(task))
+    }
+
+    fn create_task(&self, task: &Task) -> Result<Task, TickTickError> {
+        self.runtime.block_on(self.client.create_task(task))
+    }
+
+    fn get_completed_tasks(
+        &self,
+        project_ids: &[String],
+        start: &Zoned,
+        end: &Zoned,
+    ) -> Result<Vec<Task>, TickTickError> {
+        self.runtime
+            .block_on(self.client.get_completed_tasks(project_ids, start, end))
+    }
+}
+
+#[cfg(feature = "async")]
+fn nonblocking(server: &MockServer, token: &str) -> Box<dyn Api> {
+    let client = tok::nonblocking::TickTickClient::builder()
+        .host(server.host())
+        .access_token(token)
+        .timeout(Duration::from_secs(5))
+        .build_async()
+        .unwrap();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    Box::new(Nonblocking { client, runtime })
+}
+
+macro_rules! suite {
+    ($($test:ident),* $(,)?) => {
+        mod blocking {
+            $(
+                #[test]
+                fn $test() {
+                    super::$test(super::blocking);
+                }
+            )*
+        }
+
+        #[cfg(feature = "async")]
+        mod nonblocking {
+            $(
+                #[test]
+                fn $test() {
+                    super::$test(super::nonblocking);
+                }
+            )*
+        }
+    };
+}
+
+suite!(
+    lists_projects,
+    fetches_every_project_with_data,
+    fetches_single_project_by_name,
+    completes_task,
+    deletes_task,
+    updates_task_without_losing_fields,
+    creates_task,
+    fetches_completed_tasks,
+    sends_token_and_user_agent,
+    maps_unauthorized,
+    maps_not_found,
+    maps_rate_limiting,
+    maps_server_errors,
+    maps_decode_errors,
+);
+
+fn first_task(client: &dyn Api, project_id: &str) -> Task {
+    client.get_project_data(project_id).unwrap().tasks.remove(0)
+}
+
+fn lists_projects(connect: Connect) {
+    let server = MockServer::start();
+    let projects = connect(&server, TOKEN).get_projects().unwrap();
+
+    let names: Vec<_> = projects.iter().map(|project| project.name.as_str()).collect();
+    assert_eq!(names, ["Work", "Home"]);
+}
+
+fn fetches_every_project_with_data(connect: Connect) {
+    let server = MockServer::start();
+    let data = connect(&server, TOKEN).get_projects_with_data().unwrap();
+
+    assert_eq!(data.len(), 2);
+    assert_eq!(data[0].tasks.len(), 2);
+    assert_eq!(data[0].columns[0].name, "Doing");
+    assert_eq!(data[1].tasks[0].title, "Water plants");
+}
+
+fn fetches_single_project_by_name(connect: Connect) {
+    let server = MockServer::start();
+    let client = connect(&server, TOKEN);
+
+    let data = client.get_single_project_with_data("Home").unwrap();
+    assert_eq!(data.len(), 1);
+    assert_eq!(data[0].project.id, "p2");
+    assert!(client.get_single_project_with_data("Nope").unwrap().is_empty());
+}
+
+fn completes_task(connect: Connect) {
+    let server = MockServer::start();
+    let client = connect(&server, TOKEN);
+
+    let task = first_task(client.as_ref(), "p1");
+    client.complete_task(&task).unwrap();
+
+    let last = server.requests().pop().unwrap();
+    assert_eq!(last.method, "POST");
+    assert_eq!(last.path, "/open/v1/project/p1/task/t1/complete");
+    assert_eq!(server.task_ids(), ["t2", "t3"]);
+}
+
+fn deletes_task(connect: Connect) {
+    let server = MockServer::start();
+    let client = connect(&server, TOKEN);
+
+    let task = first_task(client.as_ref(), "p2");
+    client.delete_task(&task).unwrap();
+
+    let last = server.requests().pop().unwrap();
+    assert_eq!(last.method, "DELETE");
+    assert_eq!(last.path, "/open/v1/project/p2/task/t3");
+    assert!(server.task("t3").is_none());
+}
+
+fn updates_task_without_losing_fields(connect: Connect) {
+    let server = MockServer::start();
+    let mut unknown = common::task("t9", "p1", "Plan trip");
+    unknown["columnId"] = json!("c1");
+    unknown["focusSummaries"] = json!([{"pomoCount": 2}]);
+    server.replace(vec![common::project("p1", "Work")], vec![unknown]);
+    let client = connect(&server, TOKEN);
+
+    let mut task = first_task(client.as_ref(), "p1");
+    task.title = "Plan the trip".to_string();
+    let updated = client.update_task(&task).unwrap();
+
+    assert_eq!(updated.title, "Plan the trip");
+    let request = server.requests().pop().unwrap();
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.path, "/open/v1/task/t9");
+    let sent: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+    assert_eq!(sent["columnId"], "c1");
+    assert_eq!(sent["focusSummaries"][0]["pomoCount"], 2);
+    assert_eq!(sent["projectId"], "p1");
+}
+
+fn creates_task(connect: Connect) {
+    let server = MockServer::start();
+    let client = connect(&server, TOKEN);
+
+    let task = Task {
+        project_id: "p2".to_string(),
+        title: "Buy milk".to_string(),
+        tags: Some(vec!["errands".to_string()]),
+        ..Task::default()
+    };
+    let created = client.create_task(&task).unwrap();
+
+    assert_eq!(created.id, "new1");
+    assert_eq!(created.title, "Buy milk");
+    let request = server.requests().pop().unwrap();
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.path, "/open/v1/task");
+    let sent: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+    assert!(sent.get("id").is_none());
+    assert_eq!(sent["projectId"], "p2");
+    assert_eq!(server.task_ids(), ["t1", "t2", "t3", "new1"]);
+}
+
+fn fetches_completed_tasks(connect: Connect) {
+    let server = MockServer::start();
+    let client = connect(&server, TOKEN);
+    let mut old = common::task("t9", "p1", "Old news");
+    old["status"] = json!(2);
+    old["completedTime"] = json!("2020-01-02T10:00:00.000+0000");
+    server.add_completed(old);
+    client.complete_task(&first_task(client.as_ref(), "p1")).unwrap();
+
+    let now = Zoned::now();
+    let (start, end) = (now.checked_sub(1.hour()).unwrap(), now.checked_add(1.hour()).unwrap());
+    let done = client.get_completed_tasks(&["p1".to_string()], &start, &end).unwrap();
+
+    assert_eq!(done.len(), 1);
+    assert_eq!(done[0].title, "Write report");
+    assert_eq!(done[0].status, 2);
+    assert!(done[0].completed_time.is_some());
+    let request = server.requests().pop().unwrap();
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.path, "/open/v1/task/completed");
+    let sent: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+    assert_eq!(sent["projectIds"], json!(["p1"]));
+    assert!(sent["startDate"].as_str().unwrap().ends_with("+0000"));
+    assert!(client
+        .get_completed_tasks(&["p2".to_string()], &start, &end)
+        .unwrap()
+        .is_empty());
+}
+
+fn sends_token_and_user_agent(connect: Connect) {
+    let server = MockServer::start();
+    connect(&server, TOKEN).get_projects().unwrap();
+
+    let request = server.requests().pop().unwrap();
+    assert_eq!(request.authorization, Some(format!("Bearer {}", TOKEN)));
+    assert!(request.user_agent.unwrap().starts_with("tok/"));
+}
+
+fn maps_unauthorized(connect: Connect) {
+    let server = MockServer::start();
+    let err = connect(&server, "wrong-token").get_projects().unwrap_err();
+
+    assert!(matches!(err, TickTickError::Unauthorized), "{err:?}");
+}
+
+fn maps_not_found(connect: Connect) {
+    let server = MockServer::start();
+    let err = connect(&server, TOKEN).get_project_data("missing").unwrap_err();
+
+    assert!(matches!(err, TickTickError::NotFound), "{err:?}");
+}
+
+fn maps_rate_limiting(connect: Connect) {
+    let server = MockServer::start();
+    server.fail_next(429, &[("Retry-After", "30")], "");
+    let err = connect(&server, TOKEN).get_projects().unwrap_err();
+
+    match err {
+        TickTickError::RateLimited { retry_after } => assert_eq!(retry_after, Some(Duration::from_secs(30))),
+        err => panic!("expected rate limiting, got {err:?}"),
+    }
+}
+
+fn maps_server_errors(connect: Connect) {
+    let server = MockServer::start();
+    server.fail_next(500, &[], "something broke");
+    let err = connect(&server, TOKEN).get_projects_with_data().unwrap_err();
+
+    match err {
+        TickTickError::Server { status, body } => {
+            assert_eq!(status.as_u16(), 500);
+            assert_eq!(body, "something broke");
+        }
+        err => panic!("expected a server error, got {err:?}"),
+    }
+
+    // A proxy's error page shouldn't end up in the terminal in full
+    let page = format!("<html>{}</html>", "x".repeat(5000));
+    server.fail_next(502, &[], &page);
+    let err = connect(&server, TOKEN).get_projects().unwrap_err();
+    match err {
+        TickTickError::Server { body, .. } => assert_eq!(body, format!("{}...", &page[..200])),
+        err => panic!("expected a server error, got {err:?}"),
+    }
+}
+
+fn maps_decode_errors(connect: Connect) {
+    let server = MockServer::start();
+    server.fail_next(200, &[], r#"{"not": "a list"}"#);
+    let err = connect(&server, TOKEN).get_projects().unwrap_err();
+
+    match err {
+        TickTickError::Decode { body, .. } => assert!(body.contains("not")),
+        err => panic!("expected a decode error, got {err:?}"),
+    }
+}
@@ -0,0 +1,368 @@
+/*
+A stand in for the TickTick open API and its OAuth endpoints, good enough for the client and CLI tests.
+Everything lives in memory and every request is recorded so tests can check what was sent
+*/
+
+#![allow(dead_code)]
+
+pub mod scrub;
+
+use jiff::Timestamp;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tiny_http::{Header, Method, Request, Response, Server};
+use tok::Host;
+
+pub const TOKEN: &str = "test-token";
+
+#[derive(Debug, Clone)]
+pub struct Recorded {
+    pub method: String,
+    pub path: String,
+    pub authorization: Option<String>,
+    pub user_agent: Option<String>,
+    pub body: String,
+}
+
+// Answered in place of the next request that matches, or the next request at all without a target
+#[derive(Debug, Clone)]
+pub struct Fault {
+    pub target: Option<(String, String)>,
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+#[derive(Default)]
+struct State {
+    projects: Vec<Value>,
+    tasks: Vec<Value>,
+    // Still around so they can be reopened, but no longer listed with their project
+    completed: Vec<Value>,
+    columns: Vec<Value>,
+    faults: VecDeque<Fault>,
+    requests: Vec<Recorded>,
+    created: usize,
+}
+
+pub struct MockServer {
+    server: Arc<Server>,
+    state: Arc<Mutex<State>>,
+    base: String,
+}
+
+impl MockServer {
+    // Two projects with a couple of tasks each
+    pub fn start() -> Self {
+        let server = Arc::new(Server::http("127.0.0.1:0").unwrap());
+        let base = format!("http://{}", server.server_addr().to_ip().unwrap());
+        let state = Arc::new(Mutex::new(State {
+            projects: vec![project("p1", "Work"), project("p2", "Home")],
+            tasks: vec![
+                task("t1", "p1", "Write report"),
+                task("t2", "p1", "Review PR"),
+                task("t3", "p2", "Water plants"),
+            ],
+            columns: vec![json!({"id": "c1", "projectId": "p1", "name": "Doing", "sortOrder": 0})],
+            ..State::default()
+        }));
+
+        let handle = Arc::clone(&server);
+        let shared = Arc::clone(&state);
+        thread::spawn(move || {
+            for request in handle.incoming_requests() {
+                handle_request(request, &shared);
+            }
+        });
+
+        Self { server, state, base }
+    }
+
+    pub fn host(&self) -> Host {
+        Host::Custom {
+            auth_url: format!("{}/oauth", self.base),
+            api_url: self.base.clone(),
+        }
+    }
+
+    // Swaps out the starting projects and tasks
+    pub fn replace(&self, projects: Vec<Value>, tasks: Vec<Value>) {
+        let mut state = self.state.lock().unwrap();
+        state.projects = projects;
+        state.tasks = tasks;
+    }
+
+    pub fn replace_columns(&self, columns: Vec<Value>) {
+        self.state.lock().unwrap().columns = columns;
+    }
+
+    pub fn fail_next(&self, status: u16, headers: &[(&str, &str)], body: &str) {
+        self.push_fault(None, status, headers, body);
+    }
+
+    // Only fails the next request with this method and path, e.g. ("POST", "/open/v1/project/p1/task/t1/complete")
+    pub fn fail_on(&self, method: &str, path: &str, status: u16, headers: &[(&str, &str)], body: &str) {
+        self.push_fault(Some((method.to_string(), path.to_string())), status, headers, body);
+    }
+
+    fn push_fault(&self, target: Option<(String, String)>, status: u16, headers: &[(&str, &str)], body: &str) {
+        self.state.lock().unwrap().faults.push_back(Fault {
+            target,
+            status,
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            body: body.to_string(),
+        });
+    }
+
+    pub fn requests(&self) -> Vec<Recorded> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    pub fn task_ids(&self) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        state
+            .tasks
+            .iter()
+            .map(|task| task["id"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    pub fn task(&self, id: &str) -> Option<Value> {
+        let state = self.state.lock().unwrap();
+        state.tasks.iter().find(|task| task["id"] == id).cloned()
+    }
+
+    // Adds a task that was completed before the test started, completedTime and all
+    pub fn add_completed(&self, task: Value) {
+        self.state.lock().unwrap().completed.push(task);
+    }
+
+    pub fn completed(&self, id: &str) -> Option<Value> {
+        let state = self.state.lock().unwrap();
+        state.completed.iter().find(|task| task["id"] == id).cloned()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+    }
+}
+
+pub fn project(id: &str, name: &str) -> Value {
+    json!({
+        "id": id,
+        "name": name,
+        "color": "#4772FA",
+        "sortOrder": 0,
+        "closed": false,
+        "viewMode": "list",
+        "permission": "write",
+        "kind": "TASK"
+    })
+}
+
+pub fn task(id: &str, project_id: &str, title: &str) -> Value {
+    json!({
+        "id": id,
+        "projectId": project_id,
+        "title": title,
+        "isAllDay": false,
+        "priority": 0,
+        "sortOrder": 0,
+        "status": 0,
+        "timeZone": "UTC"
+    })
+}
+
+fn handle_request(mut request: Request, state: &Mutex<State>) {
+    let mut body = String::new();
+    let _ = request.as_reader().read_to_string(&mut body);
+    let method = request.method().to_string();
+    let path = request.url().to_string();
+
+    let mut state = state.lock().unwrap();
+    state.requests.push(Recorded {
+        method: method.clone(),
+        path: path.clone(),
+        authorization: header(&request, "Authorization"),
+        user_agent: header(&request, "User-Agent"),
+        body: body.clone(),
+    });
+
+    let fault = state.faults.iter().position(|fault| match &fault.target {
+        Some((fault_method, fault_path)) => *fault_method == method && *fault_path == path,
+        None => true,
+    });
+    if let Some(fault) = fault.and_then(|index| state.faults.remove(index)) {
+        let mut response = Response::from_string(fault.body).with_status_code(fault.status);
+        for (name, value) in fault.headers {
+            response.add_header(Header::from_bytes(name, value).unwrap());
+        }
+        let _ = request.respond(response);
+        return;
+    }
+
+    // The OAuth endpoints are authenticated with the client credentials, if at all
+    if let Some(body) = oauth(&path) {
+        let content_type = Header::from_bytes("Content-Type", "application/json").unwrap();
+        let status = if body.is_some() { 200 } else { 404 };
+        let _ = request.respond(
+            Response::from_string(body.map(|body| body.to_string()).unwrap_or_default())
+                .with_status_code(status)
+                .with_header(content_type),
+        );
+        return;
+    }
+
+    if header(&request, "Authorization").as_deref() != Some(&format!("Bearer {}", TOKEN)) {
+        let _ = request.respond(Response::from_string("").with_status_code(401));
+        return;
+    }
+
+    let (status, body) = route(&mut state, request.method(), &path, &body);
+    let content_type = Header::from_bytes("Content-Type", "application/json").unwrap();
+    let _ = request.respond(
+        Response::from_string(body.map(|body| body.to_string()).unwrap_or_default())
+            .with_status_code(status)
+            .with_header(content_type),
+    );
+}
+
+fn header(request: &Request, name: &'static str) -> Option<String> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.to_string())
+}
+
+fn oauth(path: &str) -> Option<Option<Value>> {
+    match path {
+        "/oauth/token" => Some(Some(json!({
+            "access_token": TOKEN,
+            "expires_in": 3600,
+            "scope": "tasks:read tasks:write",
+            "token_type": "bearer"
+        }))),
+        _ if path.starts_with("/oauth/") => Some(None),
+        _ => None,
+    }
+}
+
+fn route(state: &mut State, method: &Method, url: &str, body: &str) -> (u16, Option<Value>) {
+    let segments: Vec<&str> = url.trim_start_matches("/open/v1/").split('/').collect();
+
+    match (method, segments.as_slice()) {
+        (Method::Get, ["project"]) => (200, Some(Value::Array(state.projects.clone()))),
+        (Method::Get, ["project", project_id, "data"]) => {
+            let Some(project) = state.projects.iter().find(|project| project["id"] == *project_id) else {
+                return (404, None);
+            };
+            let in_project = |item: &&Value| item["projectId"] == *project_id;
+            (
+                200,
+                Some(json!({
+                    "project": project,
+                    "tasks": state.tasks.iter().filter(in_project).collect::<Vec<_>>(),
+                    "columns": state.columns.iter().filter(in_project).collect::<Vec<_>>(),
+                })),
+            )
+        }
+        (Method::Post, ["project", project_id, "task", task_id, "complete"]) => {
+            match find_task(state, project_id, task_id) {
+                Some(index) => {
+                    let mut task = state.tasks.remove(index);
+                    task["status"] = json!(2);
+                    task["completedTime"] = json!(Timestamp::now().strftime("%Y-%m-%dT%H:%M:%S.000+0000").to_string());
+                    state.completed.push(task);
+                    (200, None)
+                }
+                None => (404, None),
+            }
+        }
+        (Method::Delete, ["project", project_id, "task", task_id]) => match find_task(state, project_id, task_id) {
+            Some(index) => {
+                state.tasks.remove(index);
+                (200, None)
+            }
+            None => (404, None),
+        },
+        (Method::Post, ["task"]) => {
+            let Ok(mut task) = serde_json::from_str::<Value>(body) else {
+                return (400, None);
+            };
+            if task.get("id").is_none() {
+                state.created += 1;
+                task["id"] = json!(format!("new{}", state.created));
+            }
+            state.tasks.push(task.clone());
+            (200, Some(task))
+        }
+        (Method::Post, ["task", "completed"]) => {
+            let Ok(query) = serde_json::from_str::<Value>(body) else {
+                return (400, None);
+            };
+            let when = |date: &Value| {
+                date.as_str()
+                    .and_then(|date| Timestamp::strptime("%Y-%m-%dT%H:%M:%S%.f%z", date).ok())
+            };
+            let (Some(start), Some(end)) = (when(&query["startDate"]), when(&query["endDate"])) else {
+                return (400, None);
+            };
+            let project_ids = query["projectIds"].as_array().cloned().unwrap_or_default();
+            // Tasks without a completedTime can't be ruled out, so they're always sent back
+            let tasks = state
+                .completed
+                .iter()
+                .filter(|task| project_ids.contains(&task["projectId"]))
+                .filter(|task| when(&task["completedTime"]).is_none_or(|at| start <= at && at <= end))
+                .cloned()
+                .collect();
+            (200, Some(Value::Array(tasks)))
+        }
+        (Method::Post, ["task", task_id]) => {
+            let Ok(Value::Object(changes)) = serde_json::from_str::<Value>(body) else {
+                return (400, None);
+            };
+            // Reopening a completed task puts it back in its project
+            if let Some(index) = state.completed.iter().position(|task| task["id"] == *task_id) {
+                if changes.get("status") == Some(&json!(0)) {
+                    let task = state.completed.remove(index);
+                    state.tasks.push(task);
+                }
+            }
+            let Some(task) = state
+                .tasks
+                .iter_mut()
+                .chain(state.completed.iter_mut())
+                .find(|task| task["id"] == *task_id)
+            else {
+                return (404, None);
+            };
+            // Null clears a field, the way TickTick treats it
+            for (key, value) in changes {
+                match value {
+                    Value::Null => {
+                        task.as_object_mut().unwrap().remove(&key);
+                    }
+                    value => task[key] = value,
+                }
+            }
+            (200, Some(task.clone()))
+        }
+        _ => (404, None),
+    }
+}
+
+fn find_task(state: &State, project_id: &str, task_id: &str) -> Option<usize> {
+    state
+        .tasks
+        .iter()
+        .position(|task| task["id"] == task_id && task["projectId"] == project_id)
+}
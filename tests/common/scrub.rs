@@ -0,0 +1,79 @@
+/*
+Anonymizes API responses before they become fixtures.
+Ids, etags and anything a person typed are swapped for placeholders, the same value always
+getting the same placeholder so references between projects, tasks and columns still line up.
+Dates, flags and repeat rules are left alone since those are what the fixtures are for.
+Running it over something that's already scrubbed changes nothing
+*/
+
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+const ID_KEYS: &[&str] = &["id", "projectId", "parentId", "groupId", "columnId", "childIds"];
+const TEXT_KEYS: &[&str] = &["title", "content", "desc", "name", "tags"];
+
+#[derive(Default)]
+pub struct Scrubber {
+    ids: HashMap<String, String>,
+    etags: HashMap<String, String>,
+    text: HashMap<(String, String), String>,
+}
+
+impl Scrubber {
+    pub fn scrub(&mut self, value: &mut Value) {
+        match value {
+            Value::Object(map) => self.scrub_object(map),
+            Value::Array(values) => values.iter_mut().for_each(|value| self.scrub(value)),
+            _ => {}
+        }
+    }
+
+    fn scrub_object(&mut self, map: &mut Map<String, Value>) {
+        for (key, value) in map.iter_mut() {
+            if ID_KEYS.contains(&key.as_str()) {
+                replace_strings(value, &mut |id| {
+                    let next = self.ids.len() + 1;
+                    self.ids
+                        .entry(id.to_string())
+                        .or_insert_with(|| format!("{:024x}", next))
+                        .clone()
+                });
+            } else if key == "etag" {
+                replace_strings(value, &mut |etag| {
+                    let next = self.etags.len() + 1;
+                    self.etags
+                        .entry(etag.to_string())
+                        .or_insert_with(|| format!("{:08x}", next))
+                        .clone()
+                });
+            } else if TEXT_KEYS.contains(&key.as_str()) {
+                replace_strings(value, &mut |text| {
+                    // Empty strings say something about the API, so they stay empty
+                    if text.is_empty() {
+                        return String::new();
+                    }
+                    let next = self.text.keys().filter(|(seen, _)| seen == key).count() + 1;
+                    self.text
+                        .entry((key.clone(), text.to_string()))
+                        .or_insert_with(|| match key.as_str() {
+                            // Tags can't have spaces in them
+                            "tags" => format!("tag{}", next),
+                            _ => format!("{} {}", key, next),
+                        })
+                        .clone()
+                });
+            } else {
+                self.scrub(value);
+            }
+        }
+    }
+}
+
+// Strings on their own or in a list, anything else under these keys is left as is
+fn replace_strings(value: &mut Value, replace: &mut impl FnMut(&str) -> String) {
+    match value {
+        Value::String(text) => *text = replace(text),
+        Value::Array(values) => values.iter_mut().for_each(|value| replace_strings(value, replace)),
+        _ => {}
+    }
+}
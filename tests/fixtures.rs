@@ -0,0 +1,231 @@
+/*
+Deserializes every response in tests/fixtures into the data models and writes it back out.
+The fixtures are real responses with the personal bits scrubbed out, recorded with
+`cargo run --example record_fixtures`, so anything TickTick actually sends should end up in here
+*/
+
+mod common;
+
+use common::scrub::Scrubber;
+use jiff::Timestamp;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tok::data::{Project, ProjectData, Task};
+
+fn fixtures(kind: &str) -> Vec<(PathBuf, Value)> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(kind);
+    let mut paths: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "No fixtures in {}", dir.display());
+
+    paths
+        .into_iter()
+        .map(|path| {
+            let value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+            (path, value)
+        })
+        .collect()
+}
+
+fn fixture<T: DeserializeOwned>(kind: &str, name: &str) -> T {
+    let (path, value) = fixtures(kind)
+        .into_iter()
+        .find(|(path, _)| path.file_stem().unwrap() == name)
+        .unwrap();
+    serde_json::from_value(value).unwrap_or_else(|err| panic!("{}: {}", path.display(), err))
+}
+
+/*
+Writing a model back out and reading it again shouldn't change anything, and what gets
+written has to be what was read, down to the fields tok doesn't know about. It's only the
+same after normalizing both sides though, tok drops nulls and writes every date in UTC
+*/
+fn round_trip<T: DeserializeOwned + Serialize>(path: &Path, original: &Value) {
+    let model: T = serde_json::from_value(original.clone()).unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+    let written = serde_json::to_value(&model).unwrap();
+
+    let reread: T = serde_json::from_value(written.clone()).unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+    assert_eq!(written, serde_json::to_value(&reread).unwrap(), "{}", path.display());
+
+    assert_eq!(normalized(original), normalized(&written), "{}", path.display());
+}
+
+// Every field the models read as a date
+const DATE_FIELDS: &[&str] = &["completedTime", "createdTime", "dueDate", "modifiedTime", "startDate"];
+
+fn normalized(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| match value {
+                    Value::String(date) if DATE_FIELDS.contains(&key.as_str()) => (key.clone(), json!(in_utc(date))),
+                    value => (key.clone(), normalized(value)),
+                })
+                .collect(),
+        ),
+        Value::Array(values) => Value::Array(values.iter().map(normalized).collect()),
+        value => value.clone(),
+    }
+}
+
+fn in_utc(date: &str) -> String {
+    Timestamp::strptime("%Y-%m-%dT%H:%M:%S%.f%z", date)
+        .unwrap_or_else(|err| panic!("{}: {}", date, err))
+        .strftime("%Y-%m-%dT%H:%M:%S%.3f+0000")
+        .to_string()
+}
+
+#[test]
+fn round_trips_projects_after_normalizing() {
+    for (path, value) in fixtures("projects") {
+        round_trip::<Vec<Project>>(&path, &value);
+    }
+}
+
+#[test]
+fn round_trips_project_data_after_normalizing() {
+    for (path, value) in fixtures("project_data") {
+        round_trip::<ProjectData>(&path, &value);
+    }
+}
+
+#[test]
+fn round_trips_tasks_after_normalizing() {
+    for (path, value) in fixtures("tasks") {
+        round_trip::<Task>(&path, &value);
+    }
+}
+
+#[test]
+fn fixtures_are_scrubbed() {
+    for kind in ["projects", "project_data", "tasks"] {
+        for (path, value) in fixtures(kind) {
+            let mut scrubbed = value.clone();
+            Scrubber::default().scrub(&mut scrubbed);
+            assert_eq!(
+                value,
+                scrubbed,
+                "{} has unscrubbed values, run it through `cargo run --example record_fixtures -- --scrub`",
+                path.display()
+            );
+        }
+    }
+}
+
+#[test]
+fn reads_closed_and_odd_projects() {
+    let projects: Vec<Project> = fixture("projects", "projects");
+
+    assert_eq!(projects.len(), 5);
+    assert_eq!(projects[3].closed, Some(true));
+    assert_eq!(projects[4].closed, None);
+    assert_eq!(projects[4].color, None);
+    assert_eq!(projects[4].kind, "NOTE");
+    assert_eq!(projects[2].view_mode.as_deref(), Some("kanban"));
+
+    let data: ProjectData = fixture("project_data", "closed");
+    assert_eq!(data.project.closed, Some(true));
+    assert!(data.tasks.is_empty());
+}
+
+#[test]
+fn reads_checklist_items() {
+    let data: ProjectData = fixture("project_data", "checklist");
+    let items = data.tasks[0].items.as_ref().unwrap();
+
+    assert_eq!(items.len(), 3);
+    assert_eq!(items[0].status, 1);
+    assert!(items[0].completed_time.is_some());
+    assert!(items[1].completed_time.is_none());
+    assert!(items[2].start_date.is_some());
+    assert_eq!(data.tasks[1].items.as_ref().map(Vec::len), Some(0));
+}
+
+#[test]
+fn reads_repeating_tasks() {
+    let data: ProjectData = fixture("project_data", "repeating");
+
+    let flags: Vec<_> = data
+        .tasks
+        .iter()
+        .map(|task| task.repeat_flag.as_deref().unwrap())
+        .collect();
+    assert!(flags.iter().all(|flag| flag.starts_with("RRULE:FREQ=")));
+    assert_eq!(data.tasks[0].reminders.as_ref().unwrap().len(), 2);
+    let offsets: Vec<_> = data.tasks[0]
+        .parsed_reminders()
+        .unwrap()
+        .iter()
+        .map(|reminder| reminder.offset.as_secs())
+        .collect();
+    assert_eq!(offsets, [0, -15 * 60]);
+    assert_eq!(
+        data.tasks[1].parsed_reminders().unwrap()[0].offset.as_secs(),
+        9 * 60 * 60
+    );
+    assert!(data.tasks[2].start_date.is_none());
+}
+
+#[test]
+fn reads_all_day_and_completed_tasks() {
+    let data: ProjectData = fixture("project_data", "all_day");
+
+    assert!(data.tasks.iter().all(|task| task.is_all_day));
+    assert_eq!(data.tasks[0].time_zone, "Asia/Shanghai");
+    assert_eq!(data.tasks[1].status, 2);
+    assert!(data.tasks[1].completed_time.is_some());
+
+    let task: Task = fixture("tasks", "minimal");
+    assert!(task.due_date.is_none());
+    assert!(task.content.is_none());
+}
+
+#[test]
+fn keeps_fields_tok_does_not_know() {
+    let data: ProjectData = fixture("project_data", "kanban");
+    let task = &data.tasks[0];
+
+    assert_eq!(task.column_id.as_ref(), Some(&data.columns[0].id));
+    assert_eq!(task.tags.as_deref(), Some(&["tag1".to_string()][..]));
+    assert_eq!(task.kind.as_deref(), Some("TEXT"));
+    assert!(task.etag.is_some());
+
+    let data: ProjectData = fixture("project_data", "repeating");
+    assert_eq!(data.tasks[2].parent_id.as_ref(), Some(&data.tasks[0].id));
+    assert!(data.tasks[0].created_time.is_some());
+    assert!(data.tasks[0].modified_time.is_some());
+}
+
+#[test]
+fn reads_kanban_columns() {
+    let data: ProjectData = fixture("project_data", "kanban");
+
+    assert_eq!(data.columns.len(), 2);
+    assert!(data.columns.iter().all(|column| column.project_id == data.project.id));
+}
+
+#[test]
+fn follows_every_recorded_repeat_rule() {
+    for (path, value) in fixtures("project_data") {
+        let data: ProjectData = serde_json::from_value(value).unwrap();
+        for task in data.tasks.iter().filter(|task| task.repeat_flag.is_some()) {
+            let rule = task
+                .recurrence()
+                .unwrap_or_else(|err| panic!("{}: {}", path.display(), err))
+                .unwrap();
+            let start = task.start_date.as_ref().or(task.due_date.as_ref()).unwrap();
+            assert_eq!(rule.occurrences(start).take(3).count(), 3, "{}", path.display());
+        }
+    }
+}